@@ -0,0 +1,106 @@
+//! Compares interpolation with and without the spatial index over input nodes.
+//!
+//! `cargo bench -p haptic-core --bench interp`
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use haptic_core::devices::DeviceId;
+use haptic_core::glam::Vec3;
+use haptic_core::mapping::haptic_node::HapticNode;
+use haptic_core::mapping::input_node::{InputNode, InputType};
+use haptic_core::mapping::interp::{GaussianState, InterpAlgo, Interpolate};
+use haptic_core::mapping::spatial::{InputIndex, InputView};
+use haptic_core::mapping::{NodeGroup, NodeId};
+use haptic_core::state::PerDevice;
+
+const GROUPS: [NodeGroup; 6] = [
+    NodeGroup::TorsoFront,
+    NodeGroup::TorsoBack,
+    NodeGroup::UpperArmLeft,
+    NodeGroup::UpperArmRight,
+    NodeGroup::UpperLegLeft,
+    NodeGroup::UpperLegRight,
+];
+
+/// Cheap deterministic spread of points over a body sized box.
+fn point(i: usize) -> Vec3 {
+    let f = i as f32;
+    Vec3::new(
+        ((f * 0.618_034).fract() - 0.5) * 0.8,
+        (f * 0.414_213).fract() * 1.8,
+        ((f * 0.732_050).fract() - 0.5) * 0.4,
+    )
+}
+
+fn input_nodes(count: usize) -> Vec<InputNode> {
+    (0..count)
+        .map(|i| {
+            let mut node = InputNode::new(
+                HapticNode::new(point(i), vec![GROUPS[i % GROUPS.len()]]),
+                vec!["bench".into()],
+                NodeId(i.to_string()),
+                0.08,
+                InputType::INTERP,
+            );
+            node.set_intensity(0.5);
+            node
+        })
+        .collect()
+}
+
+fn haptic_nodes(count: usize) -> Vec<HapticNode> {
+    (0..count)
+        .map(|i| HapticNode::new(point(i * 7 + 3), vec![GROUPS[i % GROUPS.len()]]))
+        .collect()
+}
+
+fn settings() -> PerDevice {
+    PerDevice {
+        id: DeviceId("bench".into()),
+        intensity: 1.0,
+        offset: 0.01,
+        interp_algo: InterpAlgo::Gaussian(GaussianState::default()),
+        post_process: Vec::new(),
+        motors: Default::default(),
+        safety: Default::default(),
+        wifi_rate: Default::default(),
+    }
+}
+
+fn bench_interp(c: &mut Criterion) {
+    let settings = settings();
+    let motors = haptic_nodes(40);
+    let mut output = vec![0.0; motors.len()];
+
+    let mut group = c.benchmark_group("gaussian_40_motors");
+    for inputs in [64usize, 512, 4096] {
+        let nodes = input_nodes(inputs);
+        let mut index = InputIndex::default();
+        index.sync(&nodes);
+
+        group.bench_with_input(BenchmarkId::new("linear_scan", inputs), &nodes, |b, nodes| {
+            b.iter(|| {
+                settings.interp_algo.interp(&motors, &mut output, InputView::unindexed(nodes), &settings);
+                black_box(&output);
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("indexed", inputs), &nodes, |b, nodes| {
+            b.iter(|| {
+                settings.interp_algo.interp(&motors, &mut output, InputView::new(nodes, &index), &settings);
+                black_box(&output);
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("indexed_with_sync", inputs), &nodes, |b, nodes| {
+            b.iter(|| {
+                index.sync(nodes);
+                settings.interp_algo.interp(&motors, &mut output, InputView::new(nodes, &index), &settings);
+                black_box(&output);
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_interp);
+criterion_main!(benches);
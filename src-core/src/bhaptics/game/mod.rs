@@ -1,7 +1,8 @@
 /// A mess of serialization crap that sorta works to deserialize the weirdly formatted AuthenticationInit Message
 pub mod network;
+mod v1;
 mod v3;
-mod v2;
+pub(crate) mod v2;
 
 use crate::mapping::{MapHandle, event::Event};
 
@@ -38,8 +39,8 @@ pub async fn start_bhaptics(map: MapHandle) -> BhapticHandle {
     let token = CancellationToken::new();
 
     tokio::spawn(v3::run_server(map.clone(), token.child_token()));
+    // also serves V1, both live on the same port.
     tokio::spawn(v2::run_server(map.clone(), token.child_token()));
-    // tokio::spawn(v1::run_server(map.clone(), token.child_token()));
 
     BhapticHandle { shutdown_token: token }
 
@@ -1,12 +1,10 @@
 use std::collections::HashMap;
 
 use base64::{engine::general_purpose, Engine};
-use strum::EnumIter;
+use strum::{EnumIter, IntoEnumIterator};
 
-use crate::bhaptics::maps::{
-    x40_vest::x40_vest_back, x40_vest::x40_vest_front, x6_head::x6_headset,
-};
-use crate::mapping::NodeId;
+use crate::bhaptics::maps;
+use crate::mapping::{NodeGroup, NodeId};
 use glam::Vec3;
 
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
@@ -71,7 +69,7 @@ pub struct PatternClip {
     pub patterns: HashMap<PatternLocation, Vec<PatternLine>>,
 }
 
-#[derive(serde::Serialize, serde::Deserialize, Debug, Eq, PartialEq, Hash, EnumIter)]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, Eq, PartialEq, Hash, EnumIter)]
 #[serde(rename_all = "PascalCase")]
 pub enum PatternLocation {
     VestFront,
@@ -89,31 +87,37 @@ pub enum PatternLocation {
 
 impl PatternLocation {
     pub fn to_position(&self, index: usize) -> Vec3 {
-        match self {
-            PatternLocation::VestBack => x40_vest_back().rows[index],
-            PatternLocation::VestFront => x40_vest_front().rows[index],
-            PatternLocation::Head => x6_headset().rows[index],
-            PatternLocation::Unknown => {
-                log::error!("Unknown pattern location!");
-                return Vec3::new(0., 0., 0.);
-            }
-            _ => {
-                log::error!("Unimplemented pattern location {}!", self.to_input_tag());
-                return Vec3::new(0., 0., 0.);
-            }
-        }
+        maps::to_position(*self, index)
     }
 
     pub fn motor_count(&self) -> usize {
         match *self {
             Self::VestFront | Self::VestBack => 20,
             Self::Head => 6,
-            Self::FootL | Self::FootR | Self::HandL | Self::HandR => 8, // TODO: Verify this
-            Self::ForearmL | Self::ForearmR => 8,
+            // TactGlove: five fingers and the wrist.
+            Self::HandL | Self::HandR => 6,
+            Self::ForearmL | Self::ForearmR => 6,
+            Self::FootL | Self::FootR => 3,
             Self::Unknown => 0,
         }
     }
 
+    /// The body groups a motor sits in, so it only drives devices on the same part of the body.
+    pub fn node_groups(&self, index: usize) -> Vec<NodeGroup> {
+        // vest columns run Left -> Right, the left half is on the wearers left.
+        let side = |left, right| if index % 4 < 2 { left } else { right };
+        match *self {
+            Self::VestFront => vec![side(NodeGroup::TorsoLeft, NodeGroup::TorsoRight), NodeGroup::TorsoFront],
+            Self::VestBack => vec![side(NodeGroup::TorsoLeft, NodeGroup::TorsoRight), NodeGroup::TorsoBack],
+            Self::Head => vec![NodeGroup::Head],
+            Self::ForearmL | Self::HandL => vec![NodeGroup::LowerArmLeft],
+            Self::ForearmR | Self::HandR => vec![NodeGroup::LowerArmRight],
+            Self::FootL => vec![NodeGroup::FootLeft],
+            Self::FootR => vec![NodeGroup::FootRight],
+            Self::Unknown => vec![NodeGroup::All],
+        }
+    }
+
     /// returns the tag this location all input nodes belonging to this device share.
     pub fn to_input_tag(&self) -> &str {
         match *self {
@@ -139,6 +143,23 @@ impl PatternLocation {
 
         return Some(NodeId(format!("{}_{}", self.to_input_tag(), motor_index)));
     }
+
+    /// Same as `to_id`, but prefixed with `scope` so each source gets its own motor nodes.
+    ///
+    /// Sources sharing node ids would remove or zero each others nodes.
+    pub fn to_scoped_id(&self, scope: &str, motor_index: usize) -> Option<NodeId> {
+        let id = self.to_id(motor_index)?;
+        Some(NodeId(format!("{}/{}", scope, id.0)))
+    }
+
+    /// Reverse of `to_id` and `to_scoped_id`, the location and motor index an `InputNode` id was made for.
+    pub fn from_id(id: &NodeId) -> Option<(PatternLocation, usize)> {
+        let id = id.0.rsplit_once('/').map_or(id.0.as_str(), |(_, id)| id);
+        let (tag, index) = id.rsplit_once('_')?;
+        let index = index.parse().ok()?;
+        let location = PatternLocation::iter().find(|l| l.to_input_tag() == tag)?;
+        (index < location.motor_count()).then_some((location, index))
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -3,7 +3,8 @@ pub mod event_map;
 use std::{
     fs,
     path::PathBuf,
-    time::{Duration, SystemTime},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use directories::ProjectDirs;
@@ -12,21 +13,36 @@ use event_map::{BaseMessage, GameMapping};
 use crate::network::{self, fetch_text};
 use crate::file::{resolve_dir, Directory};
 use crate::log_err;
+use crate::state::{self, BhapticsSettings};
 
 const CACHE_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+const CACHE_PREFIX: &str = "bhaptics_cache_";
+/// Format version written into exported bundles.
+pub const BUNDLE_VERSION: u32 = 1;
 
+/// Latest mapping for `app_id`, refreshed once it is older than `CACHE_MAX_AGE`.
 fn cache_path(app_id: &str) -> PathBuf {
     let folder = resolve_dir(Directory::BhapticsCache);
-    let mut file = folder.join(format!("bhaptics_cache_{}", app_id));
+    let mut file = folder.join(format!("{}{}", CACHE_PREFIX, app_id));
     file.add_extension("json");
     file
 }
 
-fn read_cache(path: &PathBuf) -> Option<GameMapping> {
-    let metadata = fs::metadata(path).ok()?;
-    let modified = metadata.modified().ok()?;
-    if SystemTime::now().duration_since(modified).ok()? > CACHE_MAX_AGE {
-        return None;
+/// A specific mapping version, these never change so they never expire.
+fn version_path(app_id: &str, version: i32) -> PathBuf {
+    let folder = resolve_dir(Directory::BhapticsCache).join("versions");
+    let mut file = folder.join(format!("{}{}_v{}", CACHE_PREFIX, app_id, version));
+    file.add_extension("json");
+    file
+}
+
+/// Reads a cached mapping, `max_age` of None accepts any age.
+fn read_cache(path: &PathBuf, max_age: Option<Duration>) -> Option<GameMapping> {
+    if let Some(max_age) = max_age {
+        let modified = fs::metadata(path).ok()?.modified().ok()?;
+        if SystemTime::now().duration_since(modified).ok()? > max_age {
+            return None;
+        }
     }
     let data = fs::read_to_string(path).ok()?;
     serde_json::from_str::<GameMapping>(&data).ok()
@@ -44,34 +60,56 @@ fn write_cache(path: &PathBuf, mapping: &GameMapping) -> Result<(), std::io::Err
 
 /// Tries to get a response from the api and parse it.
 /// Results are cached to disk and reused for up to 24 hours.
+/// When the api can't be reached an expired cache is used instead.
 ///
-/// Set version to -1 to get latest version.
+/// Set version to -1 to get latest version, or the version pinned for this app if there is one.
 pub async fn fetch_mappings(
     api_key: String,
     app_id: String,
     version: i32,
 ) -> Result<GameMapping, FetchMappingsError> {
-    let path = cache_path(&app_id);
+    let version = match version {
+        v if v < 0 => pinned_version(&app_id).unwrap_or(v),
+        v => v,
+    };
+
+    let (path, max_age) = match version {
+        v if v < 0 => (cache_path(&app_id), Some(CACHE_MAX_AGE)),
+        v => (version_path(&app_id, v), None),
+    };
 
-    if let Some(cached) = read_cache(&path) {
+    if let Some(cached) = read_cache(&path, max_age) {
         log::info!("Using cached bHaptics mappings for {}", app_id);
         return Ok(cached);
     }
 
+    match request_mappings(&api_key, &app_id, version).await {
+        Ok(mapping) => {
+            if version < 0 {
+                log_err!(write_cache(&path, &mapping));
+            }
+            log_err!(write_cache(&version_path(&app_id, mapping.version), &mapping));
+            Ok(mapping)
+        }
+        Err(e) => match read_cache(&path, None) {
+            Some(stale) => {
+                log::warn!("Couldn't fetch bHaptics mappings for {}, using stale cache: {:?}", app_id, e);
+                Ok(stale)
+            }
+            None => Err(e),
+        },
+    }
+}
+
+async fn request_mappings(api_key: &str, app_id: &str, version: i32) -> Result<GameMapping, FetchMappingsError> {
     let url = format!(
         "http://sdk-apis.bhaptics.com/api/v1/haptic-definitions/workspace-v3/latest?latest-version={}&api-key={}&app-id={}",
         version, api_key, app_id
     );
 
-    let resp = fetch_text(&url).await.map_err(FetchMappingsError::HttpError)?;
-    let body = resp;
+    let body = fetch_text(&url).await.map_err(FetchMappingsError::HttpError)?;
     let msg: BaseMessage =
         serde_json::from_str(&body).map_err(|e| FetchMappingsError::DeserializeError(e, body))?;
-
-
-    log_err!(write_cache(&path, &msg.message));
-    
-
     Ok(msg.message)
 }
 
@@ -79,4 +117,166 @@ pub async fn fetch_mappings(
 pub enum FetchMappingsError {
     HttpError(network::HttpError),
     DeserializeError(serde_json::Error, String),
+}
+
+/// The mapping version pinned for `app_id`, if any.
+pub fn pinned_version(app_id: &str) -> Option<i32> {
+    state::get_config().bhaptics.load().pinned_versions.get(app_id).copied()
+}
+
+/// Pins `app_id` to `version`, or follows the latest version again when None.
+///
+/// Pinned versions are only fetched once, after that the cached copy is used even offline.
+pub fn pin_version(app_id: &str, version: Option<i32>) {
+    let shared = &state::get_config().bhaptics;
+    let mut new = BhapticsSettings::clone(&shared.load());
+    match version {
+        Some(v) => new.pinned_versions.insert(app_id.to_string(), v),
+        None => new.pinned_versions.remove(app_id),
+    };
+    shared.swap(Arc::new(new));
+    state::mark_dirty();
+}
+
+/// Summary of a cached game mapping.
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CachedMapping {
+    pub app_id: String,
+    /// Game name from the mapping.
+    pub name: String,
+    pub version: i32,
+    /// Every version cached for this app, sorted.
+    pub versions: Vec<i32>,
+    /// Unix seconds the latest mapping was written.
+    pub cached_at: u64,
+    pub pinned: Option<i32>,
+}
+
+/// Lists every app with a cached latest mapping.
+pub fn list_cached() -> Vec<CachedMapping> {
+    let Ok(entries) = fs::read_dir(resolve_dir(Directory::BhapticsCache)) else {
+        return Vec::new();
+    };
+
+    let mut cached: Vec<CachedMapping> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.path();
+            let app_id = path.file_stem()?.to_str()?.strip_prefix(CACHE_PREFIX)?.to_string();
+            let mapping = read_cache(&path, None)?;
+            let cached_at = entry
+                .metadata()
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_secs());
+            Some(CachedMapping {
+                versions: cached_versions(&app_id),
+                pinned: pinned_version(&app_id),
+                name: mapping.name,
+                version: mapping.version,
+                cached_at,
+                app_id,
+            })
+        })
+        .collect();
+    cached.sort_by(|a, b| a.name.cmp(&b.name));
+    cached
+}
+
+fn cached_versions(app_id: &str) -> Vec<i32> {
+    let prefix = format!("{}{}_v", CACHE_PREFIX, app_id);
+    let Ok(entries) = fs::read_dir(resolve_dir(Directory::BhapticsCache).join("versions")) else {
+        return Vec::new();
+    };
+    let mut versions: Vec<i32> = entries
+        .flatten()
+        .filter_map(|e| e.path().file_stem()?.to_str()?.strip_prefix(&prefix)?.parse().ok())
+        .collect();
+    versions.sort();
+    versions
+}
+
+/// Game mappings packed into one file, for moving them between machines.
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MappingBundle {
+    pub version: u32,
+    pub mappings: Vec<BundledMapping>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct BundledMapping {
+    /// The mapping itself doesn't carry the application id it is requested by.
+    pub app_id: String,
+    pub mapping: GameMapping,
+}
+
+#[derive(Debug)]
+pub enum BundleError {
+    Io(std::io::Error),
+    Parse(serde_json::Error),
+    UnsupportedVersion(u32),
+    /// App ids can't contain path separators or `..`.
+    InvalidAppId(String),
+}
+
+/// Bundles the cached mappings for `app_ids`, or every cached app when empty.
+///
+/// Apps pinned to a version export that version, otherwise the latest.
+pub fn export_bundle(app_ids: &[String]) -> MappingBundle {
+    let app_ids: Vec<String> = if app_ids.is_empty() {
+        list_cached().into_iter().map(|c| c.app_id).collect()
+    } else {
+        app_ids.to_vec()
+    };
+
+    let mappings = app_ids
+        .into_iter()
+        .filter_map(|app_id| {
+            let pinned = pinned_version(&app_id).and_then(|v| read_cache(&version_path(&app_id, v), None));
+            let mapping = pinned.or_else(|| read_cache(&cache_path(&app_id), None));
+            if mapping.is_none() {
+                log::warn!("No cached bHaptics mapping to export for {}", app_id);
+            }
+            Some(BundledMapping { mapping: mapping?, app_id })
+        })
+        .collect();
+
+    MappingBundle { version: BUNDLE_VERSION, mappings }
+}
+
+/// Writes every mapping in `bundle` into the cache, returns how many were imported.
+///
+/// Imports count as freshly fetched, and stay usable as stale fallbacks once they expire.
+pub fn import_bundle(bundle: &MappingBundle) -> Result<usize, BundleError> {
+    if bundle.version > BUNDLE_VERSION {
+        return Err(BundleError::UnsupportedVersion(bundle.version));
+    }
+    for entry in &bundle.mappings {
+        let id = &entry.app_id;
+        if id.is_empty() || id.contains(['/', '\\']) || id.contains("..") {
+            return Err(BundleError::InvalidAppId(id.clone()));
+        }
+    }
+
+    for entry in &bundle.mappings {
+        write_cache(&cache_path(&entry.app_id), &entry.mapping).map_err(BundleError::Io)?;
+        write_cache(&version_path(&entry.app_id, entry.mapping.version), &entry.mapping).map_err(BundleError::Io)?;
+    }
+    Ok(bundle.mappings.len())
+}
+
+/// Reads a bundle file written by `write_bundle`.
+pub fn read_bundle(path: &PathBuf) -> Result<MappingBundle, BundleError> {
+    let data = fs::read_to_string(path).map_err(BundleError::Io)?;
+    serde_json::from_str(&data).map_err(BundleError::Parse)
+}
+
+pub fn write_bundle(path: &PathBuf, bundle: &MappingBundle) -> Result<(), BundleError> {
+    let json = serde_json::to_string_pretty(bundle).map_err(BundleError::Parse)?;
+    fs::write(path, json).map_err(BundleError::Io)
 }
\ No newline at end of file
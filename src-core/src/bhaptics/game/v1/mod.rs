@@ -1,21 +1,125 @@
-use crate::mapping::event::Event;
-use std::sync::{Arc, Mutex};
+//! Legacy bHaptics Player API, `ws://localhost:15881/v1/feedbacks`.
+//!
+//! V1 shares the V2 listener and connection handling, only the request format differs.
+//! Everything is PascalCase, and forearms go by `Left`/`Right`.
 
-pub struct BhapticsApiV1 {
-    smth: String,
+use super::v2::{DotPoint, Frame, PathPoint, PlayerRequest, RegisterRequest, SubmitRequest};
+
+/// Decodes a V1 request into the shared V2 request types.
+pub(super) fn decode(raw: &str) -> Result<PlayerRequest, serde_json::Error> {
+    let request: LegacyRequest = serde_json::from_str(raw)?;
+    Ok(request.into())
 }
 
-impl BhapticsApiV1 {
-    /// Creates a new instance, starts the server on a separate thread,
-    /// and returns an Arc-wrapped and Mutex-guarded game state.
-    pub fn new() -> Arc<Mutex<Self>> {
-        Arc::new(Mutex::new(BhapticsApiV1 {
-            smth: "THIS".to_string(),
-        }))
+/// Maps legacy position names onto the ones V2 understands.
+fn position(pos: String) -> String {
+    match pos.as_str() {
+        "Left" => "ForearmL".to_string(),
+        "Right" => "ForearmR".to_string(),
+        _ => pos,
     }
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct LegacyRequest {
+    #[serde(default)]
+    register: Vec<LegacyRegister>,
+    #[serde(default)]
+    submit: Vec<LegacySubmit>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct LegacyRegister {
+    key: String,
+    project: serde_json::Value,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct LegacySubmit {
+    #[serde(rename = "Type")]
+    submit_type: String,
+    #[serde(default)]
+    key: String,
+    /// Same `scaleOption`/`rotationOption` object as V2.
+    #[serde(default)]
+    parameters: Option<serde_json::Value>,
+    #[serde(default)]
+    frame: Option<LegacyFrame>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct LegacyFrame {
+    duration_millis: u32,
+    position: String,
+    #[serde(default)]
+    path_points: Vec<LegacyPathPoint>,
+    #[serde(default)]
+    dot_points: Vec<LegacyDotPoint>,
+}
+
+// points were lowercase in some SDK releases.
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct LegacyDotPoint {
+    #[serde(alias = "index")]
+    index: usize,
+    #[serde(alias = "intensity")]
+    intensity: i32,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct LegacyPathPoint {
+    #[serde(alias = "x")]
+    x: f32,
+    #[serde(alias = "y")]
+    y: f32,
+    #[serde(alias = "intensity")]
+    intensity: i32,
+}
+
+impl From<LegacyRequest> for PlayerRequest {
+    fn from(request: LegacyRequest) -> Self {
+        PlayerRequest {
+            register: request
+                .register
+                .into_iter()
+                .map(|r| RegisterRequest { key: r.key, project: r.project })
+                .collect(),
+            submit: request.submit.into_iter().map(Into::into).collect(),
+        }
+    }
+}
 
-    /// Returns the list of events that were triggerd during this tick.
-    pub fn tick(&mut self) -> Vec<Event> {
-        Vec::new()
+impl From<LegacySubmit> for SubmitRequest {
+    fn from(submit: LegacySubmit) -> Self {
+        SubmitRequest {
+            submit_type: submit.submit_type,
+            key: submit.key,
+            parameters: submit.parameters,
+            frame: submit.frame.map(|f| Frame {
+                duration_millis: f.duration_millis,
+                position: position(f.position),
+                path_points: f
+                    .path_points
+                    .into_iter()
+                    .map(|p| PathPoint {
+                        x: p.x,
+                        y: p.y,
+                        intensity: p.intensity,
+                        motor_count: super::v2::default_motor_count(),
+                    })
+                    .collect(),
+                dot_points: f
+                    .dot_points
+                    .into_iter()
+                    .map(|d| DotPoint { index: d.index, intensity: d.intensity })
+                    .collect(),
+            }),
+        }
     }
 }
@@ -1,17 +1,18 @@
 // v2/mod.rs
 
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     io,
     net::SocketAddr,
     time::Duration,
 };
 
-use super::network::event_map::PatternLocation;
+use super::{network::event_map::PatternLocation, v1};
+pub use crate::bhaptics::maps::rotation::RotationOption;
 use crate::{
     log_err,
     mapping::{
-        InputEventMessage, MapHandle, NodeGroup, NodeId, event::{Event, EventEffectType}, haptic_node::HapticNode, input_node::{InputNode, InputType}
+        InputEventMessage, MapHandle, NodeId, event::{Event, EventEffectType}, haptic_node::HapticNode, input_node::{InputNode, InputType}
     },
 };
 use glam::Vec3;
@@ -24,6 +25,33 @@ use tokio_util::sync::CancellationToken;
 use tokio_websockets::Message;
 
 const V2_TAG: &str = "Bhaptics_V2";
+const V1_TAG: &str = "Bhaptics_V1";
+
+/// Wire format spoken by a connection, both share the 15881 listener and are told apart by path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Protocol {
+    /// Legacy `/v1/feedbacks`.
+    V1,
+    /// `/v2/feedbacks`, and anything else for compatibility.
+    V2,
+}
+
+impl Protocol {
+    fn from_path(path: &str) -> Protocol {
+        if path.starts_with("/v1/") {
+            Protocol::V1
+        } else {
+            Protocol::V2
+        }
+    }
+
+    fn tag(&self) -> &'static str {
+        match self {
+            Protocol::V1 => V1_TAG,
+            Protocol::V2 => V2_TAG,
+        }
+    }
+}
 
 // ─── Server ──────────────────────────────────────────────────────────
 
@@ -36,7 +64,7 @@ pub async fn run_server(map: MapHandle, token: CancellationToken) {
 async fn run_server_inner(map: MapHandle, token: CancellationToken) -> io::Result<()> {
     let addr = SocketAddr::from(([127, 0, 0, 1], 15881));
     let listener = TcpListener::bind(&addr).await?;
-    log::info!("bHaptics V2 API server started on {}", addr);
+    log::info!("bHaptics V1/V2 API server started on {}", addr);
 
     loop {
         tokio::select! {
@@ -65,6 +93,7 @@ async fn run_server_inner(map: MapHandle, token: CancellationToken) -> io::Resul
 // ─── Connection ──────────────────────────────────────────────────────
 
 struct ConnectionState {
+    protocol: Protocol,
     app_id: String,
     app_name: String,
     /// Registered patterns keyed by their string key.
@@ -83,9 +112,10 @@ async fn handle_connection(
         .await?;
 
     let uri = request.uri().to_string();
+    let protocol = Protocol::from_path(request.uri().path());
     let (app_id, app_name) = parse_query_params(&uri);
 
-    log::info!("V2 WebSocket connection: app_id={}, app_name={}", app_id, app_name);
+    log::info!("{:?} WebSocket connection: app_id={}, app_name={}", protocol, app_id, app_name);
 
     let (mut ws_write, mut ws_read) = ws_stream.split();
     let (tx, mut rx) = mpsc::unbounded_channel::<Message>();
@@ -98,9 +128,10 @@ async fn handle_connection(
         }
     });
 
-    insert_bhaptics_maps(&map).await;
+    insert_bhaptics_maps(&map, protocol.tag()).await;
 
     let mut state = ConnectionState {
+        protocol,
         app_id,
         app_name,
         registered: HashMap::new(),
@@ -128,8 +159,8 @@ async fn handle_connection(
         }
     }
 
-    remove_bhaptics_maps(&map).await;
-    log::info!("V2 connection closed");
+    remove_bhaptics_maps(&map, protocol.tag()).await;
+    log::info!("{:?} connection closed", protocol);
     Ok(())
 }
 
@@ -163,10 +194,14 @@ async fn handle_player_request(
     map: &MapHandle,
     ws_tx: &mpsc::UnboundedSender<Message>,
 ) {
-    let request: PlayerRequest = match serde_json::from_str(raw) {
+    let decoded = match state.protocol {
+        Protocol::V1 => v1::decode(raw),
+        Protocol::V2 => serde_json::from_str(raw),
+    };
+    let request: PlayerRequest = match decoded {
         Ok(r) => r,
         Err(e) => {
-            log::error!("V2 decode error: {} | raw: {:?}", e, raw);
+            log::error!("{:?} decode error: {} | raw: {:?}", state.protocol, e, raw);
             return;
         }
     };
@@ -210,6 +245,7 @@ async fn handle_submit_key(
     let events = project_to_events(
         &project_json,
         &submit.key,
+        state.protocol.tag(),
         scale,
         rotation,
     );
@@ -262,10 +298,12 @@ fn extract_rotation_option(params: &Option<serde_json::Value>) -> RotationOption
     }
 }
 
-#[derive(Debug, Clone)]
-struct ScaleOption {
-    intensity: f32,
-    duration: f32,
+/// Scales a patterns intensity and duration, 1 leaves it unchanged.
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(default)]
+pub struct ScaleOption {
+    pub intensity: f32,
+    pub duration: f32,
 }
 
 impl Default for ScaleOption {
@@ -277,17 +315,16 @@ impl Default for ScaleOption {
     }
 }
 
-#[derive(Debug, Clone, Default)]
-struct RotationOption {
-    offset_angle_x: f32,
-    offset_y: f32,
-}
-
 // ─── Project → Events conversion ────────────────────────────────────
 
-fn project_to_events(
+/// Converts a bHaptics project (the `tracks` of a registered pattern or `.tact` file) into events.
+///
+/// `tag` is the source the events come from, they are tagged with it and drive the motor nodes
+/// `insert_bhaptics_maps` created for it.
+pub(crate) fn project_to_events(
     project_json: &serde_json::Value,
     key: &str,
+    tag: &str,
     scale: ScaleOption,
     rotation: RotationOption,
 ) -> Vec<Event> {
@@ -345,9 +382,11 @@ fn project_to_events(
                                 dot_mode,
                                 &location,
                                 key,
+                                tag,
                                 track_idx,
                                 effect_idx,
                                 &scale,
+                                &rotation,
                             );
                             events.extend(new);
                         }
@@ -358,6 +397,7 @@ fn project_to_events(
                                 path_mode,
                                 &location,
                                 key,
+                                tag,
                                 track_idx,
                                 effect_idx,
                                 start_time,
@@ -381,9 +421,11 @@ fn dot_mode_to_events(
     dot_mode: &serde_json::Value,
     location: &PatternLocation,
     key: &str,
+    tag: &str,
     track_idx: usize,
     effect_idx: usize,
     scale: &ScaleOption,
+    rotation: &RotationOption,
 ) -> Vec<Event> {
     let mut events = Vec::new();
 
@@ -418,27 +460,35 @@ fn dot_mode_to_events(
             motor_points.entry(index).or_default().push((time, intensity));
         }
 
-        for (motor_idx, mut points) in motor_points {
-            let Some(node_id) = location.to_id(motor_idx) else {
+        // Rotation can move a motor onto its neighbours (or the other side of the vest),
+        // so gather what each target motor receives at each point in time first.
+        let mut targets: HashMap<(PatternLocation, usize), BTreeMap<i32, f32>> = HashMap::new();
+        for (motor_idx, points) in motor_points {
+            for (target_loc, target_idx, share) in rotation.motor_targets(*location, motor_idx) {
+                let timeline = targets.entry((target_loc, target_idx)).or_default();
+                for (time, intensity) in points.iter() {
+                    *timeline.entry(*time).or_default() += *intensity as f32 * share;
+                }
+            }
+        }
+
+        for ((target_loc, motor_idx), timeline) in targets {
+            let Some(node_id) = target_loc.to_scoped_id(tag, motor_idx) else {
                 continue;
             };
 
-            // Sort by time to build sequential steps.
-            points.sort_by_key(|(t, _)| *t);
-
-            let steps: Vec<f32> = points
-                .iter()
-                .map(|(_, intensity)| {
-                    ((*intensity as f32) * scale.intensity).clamp(0.0, 1.0)
-                })
+            // BTreeMap keeps the steps in time order.
+            let steps: Vec<f32> = timeline
+                .values()
+                .map(|intensity| (intensity * scale.intensity).clamp(0.0, 1.0))
                 .collect();
 
             if steps.is_empty() {
                 continue;
             }
 
-            let name = format!("v2_{}_t{}_e{}_fb{}_m{}", key, track_idx, effect_idx, fb_idx, motor_idx);
-            let tags = vec![V2_TAG.to_string(), key.to_string()];
+            let name = format!("v2_{}_t{}_e{}_fb{}_{:?}_m{}", key, track_idx, effect_idx, fb_idx, target_loc, motor_idx);
+            let tags = vec![tag.to_string(), key.to_string()];
 
             match Event::new(name, EventEffectType::SingleNode(node_id), steps, duration, tags) {
                 Ok(event) => events.push(event),
@@ -454,6 +504,7 @@ fn path_mode_to_events(
     path_mode: &serde_json::Value,
     location: &PatternLocation,
     key: &str,
+    tag: &str,
     track_idx: usize,
     effect_idx: usize,
     start_time: i32,
@@ -484,18 +535,23 @@ fn path_mode_to_events(
             let intensity = point.get("intensity").and_then(|v| v.as_f64()).unwrap_or(0.0) as f32;
             let time = point.get("time").and_then(|v| v.as_i64()).unwrap_or(0) as i32;
 
-            // Apply rotation offset: shift x by angle, clamp to [0,1].
-            let rotated_x = (x + rotation.offset_angle_x / 360.0).rem_euclid(1.0);
-            let shifted_y = (y + rotation.offset_y).clamp(0.0, 1.0);
+            // rotation can carry the point around onto the other side of the vest, or off the top or bottom.
+            let Some((location, x, y)) = rotation.path_point(*location, x, y) else {
+                continue;
+            };
 
             path_entries.push(PathEntry {
-                x: rotated_x,
-                y: shifted_y,
+                location,
+                x,
+                y,
                 intensity,
                 time,
             });
         }
 
+        if path_entries.is_empty() {
+            continue;
+        }
         path_entries.sort_by_key(|e| e.time);
 
         // Determine total duration from the time range of points.
@@ -506,11 +562,11 @@ fn path_mode_to_events(
 
         // If points move over time, use MovingLocation. Otherwise, use single Location.
         let name = format!("v2_{}_t{}_e{}_pfb{}", key, track_idx, effect_idx, fb_idx);
-        let tags = vec![V2_TAG.to_string(), key.to_string()];
+        let tags = vec![tag.to_string(), key.to_string()];
 
         if path_entries.len() == 1 {
             let e = &path_entries[0];
-            let pos = xy_to_vec3(location, e.x, e.y);
+            let pos = xy_to_vec3(&e.location, e.x, e.y);
             let intensity = ((e.intensity) * scale.intensity).clamp(0.0, 1.0);
 
             match Event::new(name, EventEffectType::Location(pos), vec![intensity], duration, tags) {
@@ -520,7 +576,7 @@ fn path_mode_to_events(
         } else {
             let positions: Vec<Vec3> = path_entries
                 .iter()
-                .map(|e| xy_to_vec3(location, e.x, e.y))
+                .map(|e| xy_to_vec3(&e.location, e.x, e.y))
                 .collect();
             let steps: Vec<f32> = path_entries
                 .iter()
@@ -538,6 +594,8 @@ fn path_mode_to_events(
 }
 
 struct PathEntry {
+    /// The surface the point is on after rotation.
+    location: PatternLocation,
     x: f32,
     y: f32,
     intensity: f32,
@@ -657,11 +715,12 @@ async fn handle_submit_frame(
     let duration = Duration::from_millis(frame.duration_millis.max(10) as u64);
     let mut events = Vec::new();
 
+    let tag = state.protocol.tag();
     for dot in &frame.dot_points {
-        if let Some(node_id) = location.to_id(dot.index) {
-            let intensity = (dot.intensity as f32).clamp(0.0, 1.0);
+        if let Some(node_id) = location.to_scoped_id(tag, dot.index) {
+            let intensity = frame_intensity(dot.intensity);
             let name = format!("v2_dot_{}_{}", submit.key, dot.index);
-            let tags = vec![V2_TAG.to_string(), submit.key.clone()];
+            let tags = vec![tag.to_string(), submit.key.clone()];
 
             match Event::new(name, EventEffectType::SingleNode(node_id), vec![intensity], duration, tags) {
                 Ok(event) => events.push(event),
@@ -672,9 +731,9 @@ async fn handle_submit_frame(
 
     for (i, path) in frame.path_points.iter().enumerate() {
         let pos = xy_to_vec3(&location, path.x, path.y);
-        let intensity = (path.intensity as f32).clamp(0.0, 1.0);
+        let intensity = frame_intensity(path.intensity);
         let name = format!("v2_path_{}_{}", submit.key, i);
-        let tags = vec![V2_TAG.to_string(), submit.key.clone()];
+        let tags = vec![tag.to_string(), submit.key.clone()];
 
         match Event::new(name, EventEffectType::Location(pos), vec![intensity], duration, tags) {
             Ok(event) => events.push(event),
@@ -688,6 +747,11 @@ async fn handle_submit_frame(
     }
 }
 
+/// Frame points carry intensity as a 0-100 percentage.
+fn frame_intensity(percent: i32) -> f32 {
+    (percent as f32 / 100.0).clamp(0.0, 1.0)
+}
+
 // ─── Turn off ────────────────────────────────────────────────────────
 
 async fn handle_turn_off(key: &str, state: &mut ConnectionState, map: &MapHandle) {
@@ -700,7 +764,7 @@ async fn handle_turn_off(key: &str, state: &mut ConnectionState, map: &MapHandle
 
 async fn handle_turn_off_all(state: &mut ConnectionState, map: &MapHandle) {
     log_err!(
-        map.send_event(InputEventMessage::CancelAllWithTags(vec![V2_TAG.to_string()]))
+        map.send_event(InputEventMessage::CancelAllWithTags(vec![state.protocol.tag().to_string()]))
             .await
     );
     state.active_keys.clear();
@@ -724,7 +788,10 @@ fn build_response(state: &ConnectionState) -> PlayerResponse {
 
 // ─── Node management ─────────────────────────────────────────────────
 
-async fn insert_bhaptics_maps(map: &MapHandle) {
+/// Inserts an input node for every bHaptics motor, tagged with `tag` and the motors location tag.
+///
+/// Node ids are scoped by `tag`, so each source has its own nodes to drive and remove.
+pub(crate) async fn insert_bhaptics_maps(map: &MapHandle, tag: &str) {
     for loc in PatternLocation::iter() {
         for index in 0..loc.motor_count() {
             let pos = loc.to_position(index);
@@ -732,10 +799,10 @@ async fn insert_bhaptics_maps(map: &MapHandle) {
                 x: pos.x,
                 y: pos.y,
                 z: pos.z,
-                groups: vec![NodeGroup::All],
+                groups: loc.node_groups(index),
             };
-            let tags = vec![V2_TAG.to_string(), loc.to_input_tag().to_string()];
-            if let Some(id) = loc.to_id(index) {
+            let tags = vec![tag.to_string(), loc.to_input_tag().to_string()];
+            if let Some(id) = loc.to_scoped_id(tag, index) {
                 let input = InputNode::new(node, tags, id, 0.1, InputType::ADDITIVE);
                 log_err!(map.send_event(InputEventMessage::InsertNode(input)).await);
             }
@@ -743,9 +810,9 @@ async fn insert_bhaptics_maps(map: &MapHandle) {
     }
 }
 
-async fn remove_bhaptics_maps(map: &MapHandle) {
+async fn remove_bhaptics_maps(map: &MapHandle, tag: &str) {
     log_err!(
-        map.send_event(InputEventMessage::RemoveWithTags(vec![V2_TAG.to_string()]))
+        map.send_event(InputEventMessage::RemoveWithTags(vec![tag.to_string()]))
             .await
     );
 }
@@ -786,63 +853,67 @@ fn pattern_to_position_str(loc: PatternLocation) -> Option<String> {
 
 // ─── Protocol types ──────────────────────────────────────────────────
 
+// V1 requests are decoded into these as well, see `v1::decode`.
+
 #[derive(Debug, serde::Deserialize)]
 #[serde(rename_all = "PascalCase")]
-struct PlayerRequest {
+pub(super) struct PlayerRequest {
     #[serde(default)]
-    register: Vec<RegisterRequest>,
+    pub register: Vec<RegisterRequest>,
     #[serde(default)]
-    submit: Vec<SubmitRequest>,
+    pub submit: Vec<SubmitRequest>,
 }
 
 #[derive(Debug, serde::Deserialize)]
 #[serde(rename_all = "PascalCase")]
-struct RegisterRequest {
-    key: String,
-    project: serde_json::Value,
+pub(super) struct RegisterRequest {
+    pub key: String,
+    pub project: serde_json::Value,
 }
 
 #[derive(Debug, serde::Deserialize)]
-struct SubmitRequest {
+pub(super) struct SubmitRequest {
     #[serde(rename = "type")]
-    submit_type: String,
+    pub submit_type: String,
     #[serde(default)]
-    key: String,
+    pub key: String,
     #[serde(rename = "Parameters")]
-    parameters: Option<serde_json::Value>,
+    pub parameters: Option<serde_json::Value>,
     #[serde(rename = "Frame")]
-    frame: Option<Frame>,
+    pub frame: Option<Frame>,
 }
 
 #[derive(Debug, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct Frame {
-    duration_millis: u32,
-    position: String,
+pub(super) struct Frame {
+    pub duration_millis: u32,
+    pub position: String,
     #[serde(default)]
-    path_points: Vec<PathPoint>,
+    pub path_points: Vec<PathPoint>,
     #[serde(default)]
-    dot_points: Vec<DotPoint>,
+    pub dot_points: Vec<DotPoint>,
 }
 
 #[derive(Debug, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct DotPoint {
-    index: usize,
-    intensity: i32,
+pub(super) struct DotPoint {
+    pub index: usize,
+    /// 0-100
+    pub intensity: i32,
 }
 
 #[derive(Debug, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct PathPoint {
-    x: f32,
-    y: f32,
-    intensity: i32,
+pub(super) struct PathPoint {
+    pub x: f32,
+    pub y: f32,
+    /// 0-100
+    pub intensity: i32,
     #[serde(default = "default_motor_count")]
-    motor_count: i32,
+    pub motor_count: i32,
 }
 
-fn default_motor_count() -> i32 {
+pub(super) fn default_motor_count() -> i32 {
     3
 }
 
@@ -854,4 +925,71 @@ struct PlayerResponse {
     connected_device_count: i32,
     connected_positions: Vec<String>,
     status: HashMap<String, Vec<i32>>,
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One dot on the first front vest motor.
+    fn dot_project() -> serde_json::Value {
+        serde_json::json!({
+            "tracks": [{
+                "effects": [{
+                    "startTime": 0,
+                    "offsetTime": 100,
+                    "modes": {
+                        "VestFront": {
+                            "mode": "DOT_MODE",
+                            "dotMode": {
+                                "feedback": [{
+                                    "startTime": 0,
+                                    "endTime": 100,
+                                    "pointList": [{ "index": 0, "intensity": 0.5, "time": 0 }],
+                                }],
+                            },
+                        },
+                    },
+                }],
+            }],
+        })
+    }
+
+    #[test]
+    fn project_events_only_carry_their_protocols_tag() {
+        let events = project_to_events(
+            &dot_project(),
+            "hit",
+            Protocol::V1.tag(),
+            ScaleOption::default(),
+            RotationOption::default(),
+        );
+        assert!(!events.is_empty());
+        for event in events {
+            assert_eq!(event.tags, vec![V1_TAG.to_string(), "hit".to_string()]);
+        }
+    }
+
+    #[test]
+    fn protocols_drive_separate_nodes() {
+        let node = |protocol: Protocol| {
+            let events = project_to_events(
+                &dot_project(),
+                "hit",
+                protocol.tag(),
+                ScaleOption::default(),
+                RotationOption::default(),
+            );
+            match &events[0].effect {
+                EventEffectType::SingleNode(id) => id.clone(),
+                other => panic!("expected a single node event, got {other:?}"),
+            }
+        };
+
+        let v1 = node(Protocol::V1);
+        let v2 = node(Protocol::V2);
+        assert_ne!(v1, v2);
+        assert_eq!(v1, PatternLocation::VestFront.to_scoped_id(V1_TAG, 0).unwrap());
+        assert_eq!(PatternLocation::from_id(&v2), Some((PatternLocation::VestFront, 0)));
+    }
+}
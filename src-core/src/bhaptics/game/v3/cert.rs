@@ -0,0 +1,147 @@
+//! The localhost certificate V3 games connect to.
+//!
+//! A self-signed certificate is generated into `Directory::Security` on first run and replaced well before it expires.
+//! Placing `custom.crt` and `custom.key` in the same folder uses those instead, they are never rotated or overwritten.
+use std::{
+    fs::{self, File},
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+};
+
+use rcgen::{CertificateParams, DnType, ExtendedKeyUsagePurpose, KeyPair, KeyUsagePurpose};
+use rustls_pemfile::{certs, private_key};
+use rustls_pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls;
+
+use crate::file::{resolve_dir, Directory};
+
+const GENERATED_CERT: &str = "localhost.crt";
+const GENERATED_KEY: &str = "localhost.key";
+const CUSTOM_CERT: &str = "custom.crt";
+const CUSTOM_KEY: &str = "custom.key";
+
+/// How long generated certificates are valid for.
+const VALIDITY_DAYS: i64 = 365;
+/// Generated certificates are replaced once they expire within this many days.
+const ROTATE_BEFORE_DAYS: i64 = 30;
+
+#[derive(Debug)]
+pub enum CertError {
+    Io(PathBuf, io::Error),
+    /// The file holds no PEM certificates.
+    NoCertificate(PathBuf),
+    /// The file holds no PEM private key (PKCS#8, PKCS#1 or SEC1).
+    NoPrivateKey(PathBuf),
+    /// Only one of `custom.crt` and `custom.key` exists.
+    IncompleteCustom(PathBuf),
+    Generate(rcgen::Error),
+    /// The certificate and key loaded but rustls refused them, usually a key that doesn't match.
+    Tls(rustls::Error),
+}
+
+/// Where the certificate in use lives.
+#[derive(Debug, Clone)]
+pub struct TlsFiles {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    /// Provided by the user rather than generated.
+    pub custom: bool,
+}
+
+/// Finds the certificate to serve with, generating or rotating our own if needed.
+///
+/// Returns whether a new certificate was generated alongside the files.
+pub fn ensure_certificate() -> Result<(TlsFiles, bool), CertError> {
+    let dir = resolve_dir(Directory::Security);
+
+    let custom_cert = dir.join(CUSTOM_CERT);
+    let custom_key = dir.join(CUSTOM_KEY);
+    match (custom_cert.exists(), custom_key.exists()) {
+        (true, true) => {
+            let files = TlsFiles { cert: custom_cert, key: custom_key, custom: true };
+            return Ok((files, false));
+        }
+        (true, false) => return Err(CertError::IncompleteCustom(custom_key)),
+        (false, true) => return Err(CertError::IncompleteCustom(custom_cert)),
+        (false, false) => {}
+    }
+
+    let files = TlsFiles { cert: dir.join(GENERATED_CERT), key: dir.join(GENERATED_KEY), custom: false };
+    if !needs_rotation(&files) {
+        return Ok((files, false));
+    }
+
+    generate(&files)?;
+    log::info!("Generated bHaptics V3 localhost certificate at {:?}", files.cert);
+    Ok((files, true))
+}
+
+/// Missing, unreadable or soon to expire generated certificates are replaced.
+///
+/// Goes by the certificates own `not_after`, file times change with copies and backups.
+fn needs_rotation(files: &TlsFiles) -> bool {
+    if !files.key.exists() || load_key(&files.key).is_err() {
+        return true;
+    }
+    let Ok(certs) = load_certs(&files.cert) else {
+        return true;
+    };
+    match CertificateParams::from_ca_cert_der(&certs[0]) {
+        Ok(params) => time::OffsetDateTime::now_utc() + time::Duration::days(ROTATE_BEFORE_DAYS) >= params.not_after,
+        Err(e) => {
+            log::warn!("Unable to read the generated certificate, replacing it: {:?}", e);
+            true
+        }
+    }
+}
+
+/// Writes a fresh self-signed certificate for `localhost` and `127.0.0.1`.
+fn generate(files: &TlsFiles) -> Result<(), CertError> {
+    let mut params = CertificateParams::new(vec!["localhost".to_string(), "127.0.0.1".to_string()])
+        .map_err(CertError::Generate)?;
+    params.distinguished_name.push(DnType::CommonName, "localhost");
+    // a day of slack for clocks that are slightly behind.
+    let now = time::OffsetDateTime::now_utc();
+    params.not_before = now - time::Duration::days(1);
+    params.not_after = now + time::Duration::days(VALIDITY_DAYS);
+    params.key_usages = vec![KeyUsagePurpose::DigitalSignature];
+    params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+
+    let key = KeyPair::generate().map_err(CertError::Generate)?;
+    let cert = params.self_signed(&key).map_err(CertError::Generate)?;
+
+    if let Some(parent) = files.cert.parent() {
+        fs::create_dir_all(parent).map_err(|e| CertError::Io(parent.to_path_buf(), e))?;
+    }
+    fs::write(&files.key, key.serialize_pem()).map_err(|e| CertError::Io(files.key.clone(), e))?;
+    fs::write(&files.cert, cert.pem()).map_err(|e| CertError::Io(files.cert.clone(), e))?;
+    Ok(())
+}
+
+/// Builds the rustls config serving `files`.
+pub fn server_config(files: &TlsFiles) -> Result<rustls::ServerConfig, CertError> {
+    let certs = load_certs(&files.cert)?;
+    let key = load_key(&files.key)?;
+    rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(CertError::Tls)
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, CertError> {
+    let file = File::open(path).map_err(|e| CertError::Io(path.to_path_buf(), e))?;
+    let certs = certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| CertError::Io(path.to_path_buf(), e))?;
+    if certs.is_empty() {
+        return Err(CertError::NoCertificate(path.to_path_buf()));
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &Path) -> Result<PrivateKeyDer<'static>, CertError> {
+    let file = File::open(path).map_err(|e| CertError::Io(path.to_path_buf(), e))?;
+    private_key(&mut BufReader::new(file))
+        .map_err(|e| CertError::Io(path.to_path_buf(), e))?
+        .ok_or_else(|| CertError::NoPrivateKey(path.to_path_buf()))
+}
@@ -1,35 +1,45 @@
 mod auth_message;
+mod cert;
 
 use std::{
     collections::HashMap,
-    fs::File,
-    io::{self, BufReader},
+    io,
     net::SocketAddr,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 
 use super::network::event_map::PatternLocation;
 use crate::{
-    bhaptics::{game::network, maps::pattern_to_events},
+    bhaptics::{game::network, maps::{pattern_to_events, rotate_events, rotation::RotationOption}},
     log_err,
     mapping::{
-        event::Event, haptic_node::HapticNode, input_node::{InputNode, InputType},
-        InputEventMessage, MapHandle, NodeGroup,
+        event::{Event, EventEffectType}, haptic_node::HapticNode, input_node::{InputNode, InputType},
+        InputEventMessage, MapHandle,
     },
 };
 use strum::IntoEnumIterator;
 
 use futures_util::{SinkExt, StreamExt};
-use rustls_pemfile::{certs, pkcs8_private_keys};
-use rustls_pki_types::{CertificateDer, PrivateKeyDer};
 use tokio::net::TcpListener;
 use tokio::sync::mpsc;
-use tokio_rustls::{rustls, TlsAcceptor};
+use tokio_rustls::TlsAcceptor;
 use tokio_util::sync::CancellationToken;
 use tokio_websockets::Message;
 
-const PATH_TO_CERT: &str = "security/localhost.crt";
-const PATH_TO_KEY: &str = "security/localhost.key";
+/// How often the certificate is checked for rotation while the server runs.
+const CERT_CHECK_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Tag on every node and event created by a V3 connection.
+const V3_TAG: &str = "Bhaptics_V3";
+/// How often finished requests are dropped and the playing list is re-sent.
+const STATUS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Tells connections apart in request tags, games all count request ids from the same place.
+static NEXT_CONNECTION: AtomicU64 = AtomicU64::new(0);
 
 pub(crate) struct ApiInfo {
     pub application_id: String,
@@ -38,11 +48,46 @@ pub(crate) struct ApiInfo {
     pub workspace_id: String,
 }
 
+/// A play request that has not finished yet.
+struct ActiveRequest {
+    request_id: u32,
+    event_name: String,
+    ends: Instant,
+}
+
 /// per Connection local state.
 struct ConnectionState {
+    id: u64,
     game_mapping: HashMap<String, Vec<Event>>,
     api_info: Option<ApiInfo>,
     name: Option<String>,
+    playing: Vec<ActiveRequest>,
+    /// Playing event names last sent to the game.
+    reported: Vec<String>,
+}
+
+impl ConnectionState {
+    fn request_tag(&self, request_id: u32) -> String {
+        format!("{}_{}_Request_{}", V3_TAG, self.id, request_id)
+    }
+
+    fn event_tag(&self, event_name: &str) -> String {
+        format!("{}_{}_Event_{}", V3_TAG, self.id, event_name)
+    }
+
+    /// Sends the names of the playing events if they changed since the last report.
+    fn report_playing(&mut self, ws_tx: &mpsc::UnboundedSender<Message>) {
+        let now = Instant::now();
+        self.playing.retain(|r| r.ends > now);
+
+        let mut names: Vec<String> = self.playing.iter().map(|r| r.event_name.clone()).collect();
+        names.sort();
+        names.dedup();
+        if names != self.reported {
+            send_messages(ws_tx, &[SendMessage::ServerActiveEventNameList(names.clone())]);
+            self.reported = names;
+        }
+    }
 }
 
 pub async fn run_server(map: MapHandle, token: CancellationToken) {
@@ -53,21 +98,33 @@ pub async fn run_server(map: MapHandle, token: CancellationToken) {
 
 async fn run_server_inner(map: MapHandle, token: CancellationToken) -> io::Result<()> {
     let addr = SocketAddr::from(([127, 0, 0, 1], 15882));
-    let certs = load_certs(PATH_TO_CERT)?;
-    let key = load_key(PATH_TO_KEY)?;
-
-    let config = rustls::ServerConfig::builder()
-        .with_no_client_auth()
-        .with_single_cert(certs, key)
-        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut acceptor = match load_acceptor() {
+        Ok((acceptor, _)) => acceptor,
+        Err(e) => {
+            log::error!("bHaptics V3 server not started, no usable TLS certificate: {:?}", e);
+            return Ok(());
+        }
+    };
 
-    let acceptor = TlsAcceptor::from(Arc::new(config));
     let listener = TcpListener::bind(&addr).await?;
     log::info!("bHaptics V3 API server started on {}", addr);
 
+    let mut cert_check = tokio::time::interval_at(
+        tokio::time::Instant::now() + CERT_CHECK_INTERVAL,
+        CERT_CHECK_INTERVAL,
+    );
+
     loop {
         tokio::select! {
             _ = token.cancelled() => break,
+            _ = cert_check.tick() => {
+                // existing connections keep the old certificate, only new ones pick up a rotation.
+                match load_acceptor() {
+                    Ok((rotated, true)) => acceptor = rotated,
+                    Ok((_, false)) => {}
+                    Err(e) => log::error!("V3: certificate check failed, keeping the current one: {:?}", e),
+                }
+            }
             result = listener.accept() => {
                 match result {
                     Ok((stream, _)) => {
@@ -118,14 +175,19 @@ async fn handle_connection(
     insert_bhaptics_maps(&map).await;
 
     let mut state = ConnectionState {
+        id: NEXT_CONNECTION.fetch_add(1, Ordering::Relaxed),
         game_mapping: HashMap::new(),
         api_info: None,
         name: None,
+        playing: Vec::new(),
+        reported: Vec::new(),
     };
+    let mut status = tokio::time::interval(STATUS_INTERVAL);
 
     loop {
         tokio::select! {
             _ = token.cancelled() => break,
+            _ = status.tick() => state.report_playing(&tx),
             frame = ws_read.next() => {
                 match frame {
                     Some(Ok(msg)) if msg.is_text() => {
@@ -162,14 +224,34 @@ async fn handle_message(
         }
         Ok(ReceivedMessage::SdkPlay(payload)) => {
             handle_play(&payload, state, map).await;
+            state.report_playing(ws_tx);
+        }
+        Ok(ReceivedMessage::SdkStopByRequestId(payload)) => {
+            match payload.trim().trim_matches('"').parse::<u32>() {
+                Ok(request_id) => {
+                    let tag = state.request_tag(request_id);
+                    log_err!(map.send_event(InputEventMessage::CancelAllWithTags(vec![tag])).await);
+                    state.playing.retain(|r| r.request_id != request_id);
+                    state.report_playing(ws_tx);
+                }
+                Err(e) => log::error!("V3: SdkStopByRequestId parse error: {} | {:?}", e, payload),
+            }
+        }
+        Ok(ReceivedMessage::SdkStopByEventId(event_name)) => {
+            let tag = state.event_tag(&event_name);
+            log_err!(map.send_event(InputEventMessage::CancelAllWithTags(vec![tag])).await);
+            state.playing.retain(|r| r.event_name != event_name);
+            state.report_playing(ws_tx);
         }
         Ok(ReceivedMessage::SdkStopAll(_)) => {
             log_err!(
                 map.send_event(InputEventMessage::CancelAllWithTags(
-                    vec!["Bhaptics_V3".to_string()]
+                    vec![V3_TAG.to_string()]
                 ))
                 .await
             );
+            state.playing.clear();
+            state.report_playing(ws_tx);
         }
         Err(e) => log::error!("V3 decode error: {} | raw: {:?}", e, raw),
     }
@@ -200,10 +282,7 @@ async fn handle_auth(
     });
 
     // Respond to the game immediately so it starts sending events.
-    let response = create_init_response();
-    if let Ok(json) = serde_json::to_string(&response) {
-        let _ = ws_tx.send(Message::text(json));
-    }
+    send_messages(ws_tx, &create_init_response());
 
     // Fetch mappings from the bHaptics HTTP API.
     let api_key = parsed.api_key;
@@ -217,20 +296,48 @@ async fn handle_auth(
                 state.game_mapping.insert(key, events);
             }
             log::info!("V3: Loaded {} event mappings", state.game_mapping.len());
+            send_messages(ws_tx, &event_list_messages(&state.game_mapping));
         }
         Err(e) => log::error!("V3: mapping task panicked: {:?}", e),
     }
 }
 
 /// Sends a play message to the map
-async fn handle_play(payload: &str, state: &ConnectionState, map: &MapHandle) {
+///
+/// The cached events are scaled by the requests intensity and duration, limited to the requested position,
+/// and tagged so they can be stopped by request id or event name.
+async fn handle_play(payload: &str, state: &mut ConnectionState, map: &MapHandle) {
     match serde_json::from_str::<SdkPlayMessage>(payload) {
         Ok(msg) => {
             if let Some(events) = state.game_mapping.get(&msg.event_name) {
+                let rotation = RotationOption {
+                    offset_angle_x: msg.offset_angle_x,
+                    offset_y: msg.offset_y,
+                };
+                let tags = [
+                    state.request_tag(msg.request_id),
+                    state.event_tag(&msg.event_name),
+                ];
+                let events: Vec<Event> = rotate_events(events, &rotation)
+                    .into_iter()
+                    .filter(|event| plays_at(event, msg.position))
+                    .map(|event| {
+                        let mut event = scale_event(event, msg.intensity, msg.duration);
+                        event.tags.extend(tags.iter().cloned());
+                        event
+                    })
+                    .collect();
+
+                let length = events.iter().map(|e| e.duration).max().unwrap_or_default();
                 log_err!(
-                    map.send_event(InputEventMessage::StartEvents(events.clone()))
+                    map.send_event(InputEventMessage::StartEvents(events))
                         .await
                 );
+                state.playing.push(ActiveRequest {
+                    request_id: msg.request_id,
+                    event_name: msg.event_name.clone(),
+                    ends: Instant::now() + length,
+                });
                 log::trace!("V3: Started event: {}", msg.event_name);
             } else {
                 log::trace!("V3: Unknown event: {}", msg.event_name);
@@ -240,6 +347,57 @@ async fn handle_play(payload: &str, state: &ConnectionState, map: &MapHandle) {
     }
 }
 
+/// The locations a play request's `position` limits it to, None plays the whole event.
+///
+/// Numbered like the SDK's `PositionType`. 0 is what games send by default, and is also the vest, so it plays everything.
+fn position_locations(position: u32) -> Option<&'static [PatternLocation]> {
+    match position {
+        1 => Some(&[PatternLocation::ForearmL]),
+        2 => Some(&[PatternLocation::ForearmR]),
+        3 => Some(&[PatternLocation::Head]),
+        4 | 8 => Some(&[PatternLocation::HandL]),
+        5 | 9 => Some(&[PatternLocation::HandR]),
+        6 => Some(&[PatternLocation::FootL]),
+        7 => Some(&[PatternLocation::FootR]),
+        _ => None,
+    }
+}
+
+/// Whether `event` drives a motor at the requested position. Events not tied to a motor always play.
+fn plays_at(event: &Event, position: u32) -> bool {
+    let Some(locations) = position_locations(position) else {
+        return true;
+    };
+    match &event.effect {
+        EventEffectType::SingleNode(id) => {
+            PatternLocation::from_id(id).map_or(true, |(location, _)| locations.contains(&location))
+        }
+        _ => true,
+    }
+}
+
+/// Applies a play requests intensity and duration multipliers.
+///
+/// Games send 1.0 for both by default. A duration too short for the event's steps keeps the original timing.
+fn scale_event(event: Event, intensity: f32, duration: f32) -> Event {
+    let mut event = event;
+    if intensity.is_finite() && intensity >= 0.0 {
+        event.steps.iter_mut().for_each(|s| *s = (*s * intensity).min(1.0));
+    }
+    if !duration.is_finite() || duration <= 0.0 || (duration - 1.0).abs() < f32::EPSILON {
+        return event;
+    }
+
+    let scaled = event.duration.mul_f32(duration);
+    match event.clone().with_duration(scaled) {
+        Ok(e) => e,
+        Err(e) => {
+            log::trace!("V3: Can't scale {} to {:?}: {:?}", event.name, scaled, e);
+            event
+        }
+    }
+}
+
 /// On connection; initializes our nodes we will address through events later.
 async fn insert_bhaptics_maps(map: &MapHandle) {
     for loc in PatternLocation::iter() {
@@ -249,9 +407,9 @@ async fn insert_bhaptics_maps(map: &MapHandle) {
                 x: pos.x,
                 y: pos.y,
                 z: pos.z,
-                groups: vec![NodeGroup::All],
+                groups: loc.node_groups(index),
             };
-            let tags = vec!["Bhaptics_V3".to_string(), loc.to_input_tag().to_string()];
+            let tags = vec![V3_TAG.to_string(), loc.to_input_tag().to_string()];
             if let Some(id) = loc.to_id(index) {
                 let input = InputNode::new(node, tags, id, 0.1, InputType::ADDITIVE);
                 log_err!(map.send_event(InputEventMessage::InsertNode(input)).await);
@@ -264,22 +422,54 @@ async fn insert_bhaptics_maps(map: &MapHandle) {
 async fn remove_bhaptics_maps(map: &MapHandle) {
     log_err!(
         map.send_event(InputEventMessage::RemoveWithTags(vec![
-            "Bhaptics_V3".to_string()
+            V3_TAG.to_string()
         ]))
         .await
     );
 }
 
 
-fn load_certs(path: &str) -> io::Result<Vec<CertificateDer<'static>>> {
-    certs(&mut BufReader::new(File::open(path)?)).collect()
+/// Builds the TLS acceptor, generating or rotating the certificate first if needed.
+///
+/// Also returns whether the certificate was just generated.
+fn load_acceptor() -> Result<(TlsAcceptor, bool), cert::CertError> {
+    let (files, generated) = cert::ensure_certificate()?;
+    let config = cert::server_config(&files)?;
+    if files.custom {
+        log::info!("V3: Using custom certificate {:?}", files.cert);
+    }
+    Ok((TlsAcceptor::from(Arc::new(config)), generated))
+}
+
+/// Serializes and queues messages for the websocket writer.
+fn send_messages(ws_tx: &mpsc::UnboundedSender<Message>, messages: &[SendMessage]) {
+    match serde_json::to_string(messages) {
+        Ok(json) => {
+            let _ = ws_tx.send(Message::text(json));
+        }
+        Err(e) => log::error!("V3: Failed to encode messages: {}", e),
+    }
 }
 
-fn load_key(path: &str) -> io::Result<PrivateKeyDer<'static>> {
-    pkcs8_private_keys(&mut BufReader::new(File::open(path)?))
-        .next()
-        .unwrap()
-        .map(Into::into)
+/// Every event the game can play and how long each runs, sent once its mappings are loaded.
+fn event_list_messages(mapping: &HashMap<String, Vec<Event>>) -> Vec<SendMessage> {
+    let mut names: Vec<String> = mapping.keys().cloned().collect();
+    names.sort();
+    let events = names
+        .iter()
+        .map(|name| ServerEvent {
+            event_name: name.clone(),
+            event_time: mapping[name]
+                .iter()
+                .map(|e| e.duration.as_millis() as u32)
+                .max()
+                .unwrap_or(0),
+        })
+        .collect();
+    vec![
+        SendMessage::ServerEventNameList(names),
+        SendMessage::ServerEventList(events),
+    ]
 }
 
 fn create_init_response() -> Vec<SendMessage> {
@@ -295,6 +485,10 @@ fn create_init_response() -> Vec<SendMessage> {
 enum ReceivedMessage {
     SdkRequestAuthInit(String),
     SdkPlay(String),
+    /// Payload is the request id from `SdkPlay`.
+    SdkStopByRequestId(String),
+    /// Payload is the event name.
+    SdkStopByEventId(String),
     SdkStopAll(Option<String>),
 }
 
@@ -304,6 +498,8 @@ pub enum SendMessage {
     ServerReady,
     ServerEventNameList(Vec<String>),
     ServerEventList(Vec<ServerEvent>),
+    /// Names of the events still playing, games poll `isPlaying` against this.
+    ServerActiveEventNameList(Vec<String>),
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
@@ -323,4 +519,33 @@ struct SdkPlayMessage {
     duration: f32,
     offset_angle_x: f32,
     offset_y: f32,
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn motor_event(location: PatternLocation) -> Event {
+        Event::new(
+            "motor".to_string(),
+            EventEffectType::SingleNode(location.to_id(0).unwrap()),
+            vec![1.0],
+            Duration::from_millis(100),
+            vec![V3_TAG.to_string()],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn position_limits_play_to_that_device() {
+        let hand = motor_event(PatternLocation::HandL);
+        let vest = motor_event(PatternLocation::VestFront);
+
+        // left glove
+        assert!(plays_at(&hand, 8));
+        assert!(!plays_at(&vest, 8));
+
+        // the default plays everything
+        assert!(plays_at(&hand, 0));
+        assert!(plays_at(&vest, 0));
+    }
+}
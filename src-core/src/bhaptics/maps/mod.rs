@@ -1,6 +1,10 @@
+use x3_foot::{x3_foot_left, x3_foot_right};
 use x40_vest::{x40_vest_back, x40_vest_front};
+use x6_forearm::{x6_forearm_left, x6_forearm_right};
+use x6_glove::{x6_glove_left, x6_glove_right};
 use x6_head::x6_headset;
 
+use std::collections::HashMap;
 use std::time::Duration;
 use glam::Vec3;
 
@@ -10,8 +14,14 @@ use super::game::network::event_map::{
 use crate::mapping::event::Event;
 /// Contains all the index -> position matricies for bhaptics devices.
 use crate::mapping::event::EventEffectType;
+use crate::mapping::NodeId;
+use rotation::RotationOption;
 
+pub mod rotation;
+pub mod x3_foot;
 pub mod x40_vest;
+pub mod x6_forearm;
+pub mod x6_glove;
 pub mod x6_head;
 
 /// Describes the indices on recieved f
@@ -23,17 +33,26 @@ pub struct BhapticsDevicePositions {
 
 /// Get the location of an index of a bhaptics device.
 pub fn to_position(device: PatternLocation, index: usize) -> Vec3 {
-    match device {
-        PatternLocation::VestBack => x40_vest_back().rows[index],
-        PatternLocation::VestFront => x40_vest_front().rows[index],
-        PatternLocation::Head => x6_headset().rows[index],
+    let positions = match device {
+        PatternLocation::VestBack => x40_vest_back(),
+        PatternLocation::VestFront => x40_vest_front(),
+        PatternLocation::Head => x6_headset(),
+        PatternLocation::ForearmL => x6_forearm_left(),
+        PatternLocation::ForearmR => x6_forearm_right(),
+        PatternLocation::HandL => x6_glove_left(),
+        PatternLocation::HandR => x6_glove_right(),
+        PatternLocation::FootL => x3_foot_left(),
+        PatternLocation::FootR => x3_foot_right(),
         PatternLocation::Unknown => {
             log::error!("Unknown pattern location!");
             return Vec3::new(0., 0., 0.);
         }
-        _ => {
-            log::trace!("Unimplemented pattern location!");
-            return Vec3::new(0., 0., 0.);
+    };
+    match positions.rows.get(index) {
+        Some(pos) => *pos,
+        None => {
+            log::error!("Motor {} out of range for {}", index, positions.name);
+            Vec3::new(0., 0., 0.)
         }
     }
 }
@@ -49,6 +68,54 @@ pub fn pattern_to_events(mapping: HapticMapping) -> Vec<Event> {
     return audio_patterns;
 }
 
+/// Applies `rotation` to events made by `pattern_to_events`, moving each motors steps to wherever the rotation puts that motor.
+///
+/// Motors that land on the same target are summed, as long as their timing matches.
+pub fn rotate_events(events: &[Event], rotation: &RotationOption) -> Vec<Event> {
+    if rotation.is_identity() {
+        return events.to_vec();
+    }
+
+    let mut rotated: Vec<Event> = Vec::with_capacity(events.len());
+    // (target, duration, step count) -> index into rotated
+    let mut merged: HashMap<(NodeId, Duration, usize), usize> = HashMap::new();
+
+    for event in events {
+        let EventEffectType::SingleNode(id) = &event.effect else {
+            rotated.push(event.clone());
+            continue;
+        };
+        let Some((location, index)) = PatternLocation::from_id(id) else {
+            rotated.push(event.clone());
+            continue;
+        };
+
+        for (target_loc, target_idx, share) in rotation.motor_targets(location, index) {
+            let Some(target) = target_loc.to_id(target_idx) else {
+                continue;
+            };
+
+            let key = (target.clone(), event.duration, event.steps.len());
+            match merged.get(&key) {
+                Some(&i) => {
+                    for (into, step) in rotated[i].steps.iter_mut().zip(event.steps.iter()) {
+                        *into = (*into + step * share).min(1.0);
+                    }
+                }
+                None => {
+                    let mut moved = event.clone();
+                    moved.effect = EventEffectType::SingleNode(target);
+                    moved.steps.iter_mut().for_each(|s| *s *= share);
+                    merged.insert(key, rotated.len());
+                    rotated.push(moved);
+                }
+            }
+        }
+    }
+
+    rotated
+}
+
 fn build_audio_pattern(
     patterns: Vec<AudioFilePattern>,
     name: String,
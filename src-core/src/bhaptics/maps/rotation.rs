@@ -0,0 +1,129 @@
+use crate::bhaptics::game::network::event_map::PatternLocation;
+
+/// Columns on each side of the vest. The vest is treated as a ring of twice this many columns.
+const VEST_COLS: usize = 4;
+const VEST_ROWS: usize = 5;
+const RING: f32 = (VEST_COLS * 2) as f32;
+
+/// Rotates a pattern around the body, used by games for directional hits.
+///
+/// Only vest patterns are rotated. The front and back are joined into a cylinder,
+/// so turning far enough carries the pattern around the side and onto the other surface.
+#[derive(Debug, Clone, Copy, Default, serde::Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RotationOption {
+    /// Degrees around the vertical axis. Positive turns from the front towards the right side and on to the back.
+    pub offset_angle_x: f32,
+    /// Vertical shift as a fraction of the vests height, positive moves down. Anything shifted off the vest is dropped.
+    pub offset_y: f32,
+}
+
+impl RotationOption {
+    pub fn is_identity(&self) -> bool {
+        self.offset_angle_x.rem_euclid(360.0) < f32::EPSILON && self.offset_y.abs() < f32::EPSILON
+    }
+
+    /// Columns around the ring the pattern is turned by.
+    #[inline]
+    fn ring_shift(&self) -> f32 {
+        self.offset_angle_x / 360.0 * RING
+    }
+
+    /// Where a motor ends up, as `(location, motor index, share of its intensity)`.
+    ///
+    /// A motor that lands between others is split across up to four of them. Non-vest motors come back unchanged.
+    pub fn motor_targets(&self, location: PatternLocation, index: usize) -> Vec<(PatternLocation, usize, f32)> {
+        if !is_vest(location) || self.is_identity() || index >= location.motor_count() {
+            return vec![(location, index, 1.0)];
+        }
+
+        let ring = to_ring(location, (index % VEST_COLS) as f32) + self.ring_shift();
+        let row = (index / VEST_COLS) as f32 + self.offset_y * (VEST_ROWS - 1) as f32;
+
+        let mut targets = Vec::with_capacity(4);
+        for (ring_idx, ring_share) in split(ring) {
+            let (loc, col) = from_ring(ring_idx.rem_euclid(RING as i32) as usize);
+            for (row_idx, row_share) in split(row) {
+                if row_idx < 0 || row_idx >= VEST_ROWS as i32 {
+                    continue;
+                }
+                let share = ring_share * row_share;
+                if share > 0.0 {
+                    targets.push((loc, row_idx as usize * VEST_COLS + col, share));
+                }
+            }
+        }
+        targets
+    }
+
+    /// Moves a path mode point, `x` and `y` are normalized over the surface of `location`.
+    ///
+    /// Returns None when the point is shifted off the top or bottom.
+    pub fn path_point(&self, location: PatternLocation, x: f32, y: f32) -> Option<(PatternLocation, f32, f32)> {
+        let y = y + self.offset_y;
+        if !(0.0..=1.0).contains(&y) {
+            return None;
+        }
+        if !is_vest(location) {
+            return Some((location, x, y));
+        }
+
+        let last = (VEST_COLS - 1) as f32;
+        let ring = (to_ring(location, x * last) + self.ring_shift()).rem_euclid(RING);
+        let (location, x) = ring_to_surface(ring);
+        Some((location, x, y))
+    }
+}
+
+#[inline]
+fn is_vest(location: PatternLocation) -> bool {
+    matches!(location, PatternLocation::VestFront | PatternLocation::VestBack)
+}
+
+/// Position around the ring of a (fractional) column.
+///
+/// The front runs left to right from 0, then the back runs right to left, so neighbours on the body are neighbours on the ring.
+#[inline]
+fn to_ring(location: PatternLocation, col: f32) -> f32 {
+    match location {
+        PatternLocation::VestBack => (RING - 1.0) - col,
+        _ => col,
+    }
+}
+
+#[inline]
+fn from_ring(ring_idx: usize) -> (PatternLocation, usize) {
+    if ring_idx < VEST_COLS {
+        (PatternLocation::VestFront, ring_idx)
+    } else {
+        (PatternLocation::VestBack, VEST_COLS * 2 - 1 - ring_idx)
+    }
+}
+
+/// Maps a ring position back onto a surface and its normalized x.
+///
+/// Positions in the gaps down either side snap to the closest edge column.
+fn ring_to_surface(ring: f32) -> (PatternLocation, f32) {
+    let last = (VEST_COLS - 1) as f32;
+    if ring <= last {
+        (PatternLocation::VestFront, ring / last)
+    } else if ring < last + 0.5 {
+        (PatternLocation::VestFront, 1.0)
+    } else if ring < last + 1.0 {
+        (PatternLocation::VestBack, 1.0)
+    } else if ring <= RING - 1.0 {
+        (PatternLocation::VestBack, (RING - 1.0 - ring) / last)
+    } else if ring < RING - 0.5 {
+        (PatternLocation::VestBack, 0.0)
+    } else {
+        (PatternLocation::VestFront, 0.0)
+    }
+}
+
+/// Splits a fractional position linearly between the two whole positions around it.
+#[inline]
+fn split(pos: f32) -> [(i32, f32); 2] {
+    let base = pos.floor();
+    let frac = pos - base;
+    [(base as i32, 1.0 - frac), (base as i32 + 1, frac)]
+}
@@ -0,0 +1,30 @@
+use glam::Vec3;
+
+use super::{x6_forearm::mirror_x, BhapticsDevicePositions};
+
+/// returns the right Tactosy for Feet positions in haptic space
+pub fn x3_foot_right() -> BhapticsDevicePositions {
+    let name = "FootR".to_string();
+    let locations: Vec<Vec3> = vec![
+        // across the top of the foot: Left -> Right
+        Vec3::new(0.065, 0.07, 0.08),
+        Vec3::new(0.1, 0.075, 0.085),
+        Vec3::new(0.135, 0.07, 0.08),
+    ];
+
+    BhapticsDevicePositions {
+        name: name,
+        rows: locations,
+    }
+}
+
+/// returns the left Tactosy for Feet positions in haptic space
+pub fn x3_foot_left() -> BhapticsDevicePositions {
+    // mirroring flips the order, keep it Left -> Right.
+    let mut rows: Vec<Vec3> = x3_foot_right().rows.into_iter().map(mirror_x).collect();
+    rows.reverse();
+    BhapticsDevicePositions {
+        name: "FootL".to_string(),
+        rows,
+    }
+}
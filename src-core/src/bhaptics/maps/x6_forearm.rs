@@ -0,0 +1,37 @@
+use glam::Vec3;
+
+use super::BhapticsDevicePositions;
+
+/// returns the right Tactosy for Arms positions in haptic space
+pub fn x6_forearm_right() -> BhapticsDevicePositions {
+    let name = "ForearmR".to_string();
+    let locations: Vec<Vec3> = vec![
+        // T-pose, motors on top of the forearm
+        // row 0 (front): elbow -> wrist
+        Vec3::new(0.47, 1.425, 0.02),
+        Vec3::new(0.56, 1.42, 0.02),
+        Vec3::new(0.65, 1.415, 0.018),
+        // row 1 (back): elbow -> wrist
+        Vec3::new(0.47, 1.425, -0.02),
+        Vec3::new(0.56, 1.42, -0.02),
+        Vec3::new(0.65, 1.415, -0.018),
+    ];
+
+    BhapticsDevicePositions {
+        name: name,
+        rows: locations,
+    }
+}
+
+/// returns the left Tactosy for Arms positions in haptic space
+pub fn x6_forearm_left() -> BhapticsDevicePositions {
+    BhapticsDevicePositions {
+        name: "ForearmL".to_string(),
+        rows: x6_forearm_right().rows.into_iter().map(mirror_x).collect(),
+    }
+}
+
+/// Swaps a position to the other side of the body.
+pub(super) fn mirror_x(pos: Vec3) -> Vec3 {
+    Vec3::new(-pos.x, pos.y, pos.z)
+}
@@ -0,0 +1,32 @@
+use glam::Vec3;
+
+use super::{x6_forearm::mirror_x, BhapticsDevicePositions};
+
+/// returns the right TactGlove positions in haptic space
+pub fn x6_glove_right() -> BhapticsDevicePositions {
+    let name = "GloveRight".to_string();
+    let locations: Vec<Vec3> = vec![
+        // T-pose palm down, motors on the back of the hand
+        // thumb, index, middle, ring, little
+        Vec3::new(0.80, 1.405, 0.065),
+        Vec3::new(0.865, 1.41, 0.032),
+        Vec3::new(0.875, 1.41, 0.01),
+        Vec3::new(0.865, 1.41, -0.012),
+        Vec3::new(0.845, 1.405, -0.032),
+        // wrist
+        Vec3::new(0.73, 1.415, 0.0),
+    ];
+
+    BhapticsDevicePositions {
+        name: name,
+        rows: locations,
+    }
+}
+
+/// returns the left TactGlove positions in haptic space
+pub fn x6_glove_left() -> BhapticsDevicePositions {
+    BhapticsDevicePositions {
+        name: "GloveLeft".to_string(),
+        rows: x6_glove_right().rows.into_iter().map(mirror_x).collect(),
+    }
+}
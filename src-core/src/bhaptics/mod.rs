@@ -1,3 +1,4 @@
 
 pub mod maps;
-pub mod game;
\ No newline at end of file
+pub mod game;
+pub mod patterns;
\ No newline at end of file
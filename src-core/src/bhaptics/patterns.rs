@@ -0,0 +1,178 @@
+//! Offline bHaptics `.tact` pattern files.
+//!
+//! Patterns are loaded from `Directory::Patterns` and played straight onto the map, no game or websocket needed.
+use std::path::PathBuf;
+
+use crate::bhaptics::game::v2::{insert_bhaptics_maps, project_to_events};
+pub use crate::bhaptics::game::v2::{RotationOption, ScaleOption};
+use crate::file::{list_named_files, named_file, Directory};
+use crate::mapping::{InputEventMessage, MapHandle};
+
+/// Extension bHaptics Designer exports patterns with.
+pub const TACT_EXTENSION: &str = "tact";
+/// Tag on the events and motor nodes created by offline pattern playback.
+pub const PATTERN_TAG: &str = "Bhaptics_Pattern";
+
+#[derive(Debug)]
+pub enum PatternError {
+    /// Names can't contain path separators or `..`.
+    InvalidName(String),
+    Io(std::io::Error),
+    Parse(serde_json::Error),
+    /// The file parsed but has no `tracks`.
+    NoTracks,
+    /// The map is no longer accepting events.
+    MapClosed,
+}
+
+/// A loaded `.tact` file.
+#[derive(Debug, Clone)]
+pub struct TactPattern {
+    /// File name without extension, used as the pattern key.
+    pub name: String,
+    /// The project holding `tracks` and `layout`.
+    pub project: serde_json::Value,
+}
+
+impl TactPattern {
+    /// Parses the contents of a `.tact` file.
+    ///
+    /// Designer exports wrap the project in `{ "project": {...} }`, registered patterns are the bare project. Both are accepted.
+    pub fn parse(name: String, data: &str) -> Result<TactPattern, PatternError> {
+        let mut value: serde_json::Value = serde_json::from_str(data).map_err(PatternError::Parse)?;
+        if let Some(project) = value.get_mut("project").map(serde_json::Value::take) {
+            value = project;
+        }
+        if !value.get("tracks").is_some_and(|t| t.is_array()) {
+            return Err(PatternError::NoTracks);
+        }
+        Ok(TactPattern { name, project: value })
+    }
+
+    /// Loads `<name>.tact` from the patterns folder.
+    pub async fn load(name: &str) -> Result<TactPattern, PatternError> {
+        let path = pattern_path(name)?;
+        let data = tokio::fs::read_to_string(&path).await.map_err(PatternError::Io)?;
+        Self::parse(name.to_string(), &data)
+    }
+}
+
+fn pattern_path(name: &str) -> Result<PathBuf, PatternError> {
+    named_file(Directory::Patterns, name, TACT_EXTENSION).ok_or_else(|| PatternError::InvalidName(name.to_string()))
+}
+
+/// Names of every `.tact` file in the patterns folder, sorted.
+pub async fn list_patterns() -> Vec<String> {
+    list_named_files(Directory::Patterns, TACT_EXTENSION).await
+}
+
+/// Plays `pattern` onto the map. Returns the number of events started.
+///
+/// Inserts the pattern motor nodes first, dot mode patterns drive those directly.
+pub async fn play_pattern(
+    map: &MapHandle,
+    pattern: &TactPattern,
+    scale: ScaleOption,
+    rotation: RotationOption,
+) -> Result<usize, PatternError> {
+    let events = project_to_events(&pattern.project, &pattern.name, PATTERN_TAG, scale, rotation);
+    if events.is_empty() {
+        return Ok(0);
+    }
+    let count = events.len();
+
+    // patterns get their own motor nodes, a connected game's nodes are never touched.
+    insert_bhaptics_maps(map, PATTERN_TAG).await;
+    map.send_event(InputEventMessage::StartEvents(events))
+        .await
+        .map_err(|_| PatternError::MapClosed)?;
+
+    log::debug!("Playing pattern {} as {} events", pattern.name, count);
+    Ok(count)
+}
+
+/// Cancels every playing pattern and removes the motor nodes inserted for them.
+pub async fn stop_patterns(map: &MapHandle) -> Result<(), PatternError> {
+    map.send_event(InputEventMessage::CancelAllWithTags(vec![PATTERN_TAG.to_string()]))
+        .await
+        .map_err(|_| PatternError::MapClosed)?;
+    map.send_event(InputEventMessage::RemoveWithTags(vec![PATTERN_TAG.to_string()]))
+        .await
+        .map_err(|_| PatternError::MapClosed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::devices::DeviceManager;
+    use crate::mapping::haptic_node::HapticNode;
+    use crate::mapping::input_node::{InputNode, InputType};
+    use crate::mapping::{start_interp_map, NodeId};
+
+    const GAME_TAG: &str = "Bhaptics_V2";
+
+    const DOT_PATTERN: &str = r#"{"project": {"tracks": [{"effects": [{
+        "startTime": 0,
+        "offsetTime": 500,
+        "modes": {"VestFront": {"mode": "DOT_MODE", "dotMode": {"feedback": [{
+            "startTime": 0,
+            "endTime": 500,
+            "pointList": [{"index": 0, "intensity": 1.0, "time": 0}]
+        }]}}}
+    }]}]}}"#;
+
+    fn has_tag(node: &InputNode, tag: &str) -> bool {
+        node.tags.iter().any(|t| t == tag)
+    }
+
+    /// Events are handled in order, once the marker shows up everything sent before it has been too.
+    async fn flush(map: &MapHandle) {
+        let id = NodeId::new();
+        let marker = InputNode::new(
+            HapticNode::new(glam::Vec3::ZERO, vec![]),
+            vec!["Flush".to_string()],
+            id.clone(),
+            0.0,
+            InputType::INTERP,
+        );
+        map.send_event(InputEventMessage::InsertNode(marker)).await.unwrap();
+        for _ in 0..200 {
+            if map.with_node(&id, |_| ()).is_some() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        panic!("map never handled the flush marker");
+    }
+
+    #[tokio::test]
+    async fn patterns_and_games_keep_separate_nodes() {
+        let manager = DeviceManager::new();
+        let map = start_interp_map(&manager.get_handle()).await;
+        insert_bhaptics_maps(&map, GAME_TAG).await;
+        flush(&map).await;
+        let game_nodes = map.with_nodes(|nodes| nodes.iter().filter(|n| has_tag(n, GAME_TAG)).count());
+        assert!(game_nodes > 0);
+
+        let pattern = TactPattern::parse("hit".to_string(), DOT_PATTERN).unwrap();
+        let started = play_pattern(&map, &pattern, ScaleOption::default(), RotationOption::default())
+            .await
+            .unwrap();
+        assert!(started > 0);
+        flush(&map).await;
+        map.with_nodes(|nodes| {
+            let pattern_nodes = nodes.iter().filter(|n| has_tag(n, PATTERN_TAG)).count();
+            assert_eq!(pattern_nodes, game_nodes, "patterns should insert their own motor nodes");
+            assert!(!nodes.iter().any(|n| has_tag(n, GAME_TAG) && has_tag(n, PATTERN_TAG)));
+        });
+
+        stop_patterns(&map).await.unwrap();
+        flush(&map).await;
+        map.with_nodes(|nodes| {
+            assert!(!nodes.iter().any(|n| has_tag(n, PATTERN_TAG)));
+            assert_eq!(nodes.iter().filter(|n| has_tag(n, GAME_TAG)).count(), game_nodes);
+        });
+    }
+}
@@ -0,0 +1,173 @@
+//! Who may use the control api.
+//!
+//! Every request must name loopback, or the address the api is bound to, in its `Host` header, so a page that
+//! rebinds its own domain onto 127.0.0.1 is turned away. Anything that changes state, and the websocket, also
+//! needs `Authorization: Bearer <token>` with the token kept in `<app-root>/api_token`.
+//! Browsers can't set headers on websockets, so `/ws` takes `?token=<token>` as well.
+//! When bound beyond loopback every route needs the token.
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use uuid::Uuid;
+use warp::http::{Method, StatusCode};
+use warp::path::FullPath;
+use warp::reply::Response;
+use warp::Filter;
+
+use super::error_reply;
+use crate::file::ROOT_DIR;
+
+/// Name of the file in the app root holding the api token.
+pub const TOKEN_FILE: &str = "api_token";
+
+pub(super) struct Access {
+    token: String,
+    bound: IpAddr,
+}
+
+impl Access {
+    pub(super) fn new(token: String, bound: IpAddr) -> Self {
+        Access { token, bound }
+    }
+
+    fn host_allowed(&self, host: &str) -> bool {
+        let name = match host.strip_prefix('[') {
+            Some(v6) => v6.split(']').next().unwrap_or_default(),
+            None => host.split(':').next().unwrap_or_default(),
+        };
+        if name.eq_ignore_ascii_case("localhost") {
+            return true;
+        }
+        // only addresses, a domain name is exactly what a rebinding page would send.
+        let Ok(ip) = name.parse::<IpAddr>() else {
+            return false;
+        };
+        ip.is_loopback() || ip == self.bound || self.bound.is_unspecified()
+    }
+
+    fn needs_token(&self, method: &Method, path: &str) -> bool {
+        let read = *method == Method::GET || *method == Method::HEAD;
+        !self.bound.is_loopback() || !read || path == "/ws"
+    }
+
+    fn authorized(&self, path: &str, auth: Option<&str>, query: &str) -> bool {
+        let header = auth.and_then(|a| a.strip_prefix("Bearer ")).map(str::trim);
+        let query = (path == "/ws")
+            .then(|| query.split('&').find_map(|pair| pair.strip_prefix("token=")))
+            .flatten();
+        header.or(query).is_some_and(|given| same_token(given, &self.token))
+    }
+
+    /// The reply refusing this request, `None` when it may go ahead.
+    fn check(&self, method: &Method, path: &str, host: Option<&str>, auth: Option<&str>, query: &str) -> Option<Response> {
+        if !host.is_some_and(|h| self.host_allowed(h)) {
+            return Some(error_reply(StatusCode::FORBIDDEN, "Host must be loopback or the api address"));
+        }
+        if self.needs_token(method, path) && !self.authorized(path, auth, query) {
+            return Some(error_reply(StatusCode::UNAUTHORIZED, "missing or wrong api token"));
+        }
+        None
+    }
+}
+
+/// Compares without stopping at the first difference, so timing doesn't leak the token.
+fn same_token(given: &str, token: &str) -> bool {
+    given.len() == token.len() && given.bytes().zip(token.bytes()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+/// Matches refused requests with the reply refusing them, and rejects the rest so the routes get them.
+pub(super) fn refused(access: Arc<Access>) -> impl Filter<Extract = (Response,), Error = warp::Rejection> + Clone {
+    let query = warp::query::raw().or(warp::any().map(String::new)).unify();
+    warp::method()
+        .and(warp::path::full())
+        .and(warp::header::optional::<String>("host"))
+        .and(warp::header::optional::<String>("authorization"))
+        .and(query)
+        .and_then(
+            move |method: Method, path: FullPath, host: Option<String>, auth: Option<String>, query: String| {
+                let access = Arc::clone(&access);
+                async move {
+                    match access.check(&method, path.as_str(), host.as_deref(), auth.as_deref(), &query) {
+                        Some(reply) => Ok(reply),
+                        None => Err(warp::reject::not_found()),
+                    }
+                }
+            },
+        )
+}
+
+fn token_path() -> io::Result<PathBuf> {
+    let root = ROOT_DIR
+        .get()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "app root hasn't been set yet"))?;
+    Ok(root.join(TOKEN_FILE))
+}
+
+/// Reads the api token from the app root, creating one the first time.
+pub fn load_token() -> io::Result<String> {
+    let path = token_path()?;
+    match std::fs::read_to_string(&path) {
+        Ok(token) if !token.trim().is_empty() => return Ok(token.trim().to_string()),
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+
+    let token = Uuid::new_v4().simple().to_string();
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(&path)?.write_all(token.as_bytes())?;
+    log::info!("Created control api token in {:?}", path);
+    Ok(token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    fn local() -> Access {
+        Access::new("secret".to_string(), IpAddr::V4(Ipv4Addr::LOCALHOST))
+    }
+
+    #[test]
+    fn only_loopback_hosts_reach_a_loopback_api() {
+        let access = local();
+        for host in ["localhost:9980", "127.0.0.1:9980", "127.0.0.1", "[::1]:9980"] {
+            assert!(access.check(&Method::GET, "/devices", Some(host), None, "").is_none(), "{host}");
+        }
+        for host in ["evil.example:9980", "192.168.1.5:9980", "localhost.evil.example"] {
+            assert!(access.check(&Method::GET, "/devices", Some(host), None, "").is_some(), "{host}");
+        }
+        assert!(access.check(&Method::GET, "/devices", None, None, "").is_some());
+    }
+
+    #[test]
+    fn changes_and_the_socket_need_the_token() {
+        let access = local();
+        let host = Some("127.0.0.1:9980");
+        assert!(access.check(&Method::PUT, "/layers", host, None, "").is_some());
+        assert!(access.check(&Method::PUT, "/layers", host, Some("Bearer wrong!"), "").is_some());
+        assert!(access.check(&Method::PUT, "/layers", host, Some("Bearer secret"), "").is_none());
+        assert!(access.check(&Method::GET, "/ws", host, None, "").is_some());
+        assert!(access.check(&Method::GET, "/ws", host, None, "token=secret").is_none());
+        // tokens in urls only count for the socket.
+        assert!(access.check(&Method::POST, "/map/play_point", host, None, "token=secret").is_some());
+    }
+
+    #[test]
+    fn a_lan_api_needs_the_token_everywhere() {
+        let access = Access::new("secret".to_string(), IpAddr::V4(Ipv4Addr::new(192, 168, 1, 5)));
+        let host = Some("192.168.1.5:9980");
+        assert!(access.check(&Method::GET, "/devices", host, None, "").is_some());
+        assert!(access.check(&Method::GET, "/devices", host, Some("Bearer secret"), "").is_none());
+        assert!(access.check(&Method::GET, "/devices", Some("192.168.1.6:9980"), Some("Bearer secret"), "").is_some());
+    }
+}
@@ -0,0 +1,46 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use warp::http::StatusCode;
+use warp::reply::{Reply, Response};
+
+use super::{decode_segment, error_reply, ok_reply};
+use crate::bhaptics::game::network::{self, BundleError, MappingBundle};
+
+#[derive(Debug, Deserialize)]
+pub(super) struct PinBody {
+    /// None follows the latest version again.
+    version: Option<i32>,
+}
+
+#[derive(Debug, Serialize)]
+struct ImportedBody {
+    imported: usize,
+}
+
+pub(super) fn list() -> Response {
+    warp::reply::json(&network::list_cached()).into_response()
+}
+
+pub(super) fn pin(app_id: String, body: PinBody) -> Response {
+    let app_id = decode_segment(app_id);
+    network::pin_version(&app_id, body.version);
+    ok_reply()
+}
+
+/// `?apps=a,b` limits the export to those application ids.
+pub(super) fn export(query: HashMap<String, String>) -> Response {
+    let apps: Vec<String> = query
+        .get("apps")
+        .map(|a| a.split(',').filter(|id| !id.is_empty()).map(str::to_string).collect())
+        .unwrap_or_default();
+    warp::reply::json(&network::export_bundle(&apps)).into_response()
+}
+
+pub(super) fn import(bundle: MappingBundle) -> Response {
+    match network::import_bundle(&bundle) {
+        Ok(imported) => warp::reply::json(&ImportedBody { imported }).into_response(),
+        Err(e @ BundleError::Io(_)) => error_reply(StatusCode::INTERNAL_SERVER_ERROR, format!("{:?}", e)),
+        Err(e) => error_reply(StatusCode::BAD_REQUEST, format!("{:?}", e)),
+    }
+}
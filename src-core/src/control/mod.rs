@@ -0,0 +1,605 @@
+//! Local JSON/HTTP control api.
+//!
+//! Mirrors the commands the GUI has over Tauri IPC so scripts, overlays and headless setups can drive the server.
+//! Not started by `start_server`, call `start_control_api` with the handles it returned.
+//! Requests are checked against `auth` before reaching any route.
+mod auth;
+mod mappings;
+mod patterns;
+mod recording;
+mod ws;
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use glam::Vec3;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+use warp::http::StatusCode;
+use warp::reply::{Reply, Response};
+use warp::Filter;
+
+use crate::devices::{Device, DeviceHandle, DeviceId, DeviceInfo};
+use crate::devices::update::{Firmware, UpdateMethod};
+use crate::devices::wifi::WifiSendRate;
+use crate::mapping::event::{Event, EventEffectType, EventInterp};
+use crate::mapping::haptic_node::HapticNode;
+use crate::mapping::interp::InterpAlgo;
+use crate::mapping::layer::Layering;
+use crate::mapping::post::{MotorOverride, PostStage};
+use crate::mapping::safety::SafetyLimits;
+use crate::mapping::{InputEventMessage, MapHandle};
+use crate::state::{self, GitRepo, PerDevice, StandardMenu, VrcSettings};
+use crate::vrc::config::GameMap;
+use crate::vrc::VrcHandle;
+
+pub use auth::{load_token, TOKEN_FILE};
+
+/// Default address the control api listens on. Localhost only.
+pub const DEFAULT_CONTROL_ADDR: ([u8; 4], u16) = ([127, 0, 0, 1], 9980);
+
+#[derive(Debug, Clone)]
+pub struct ControlHandle {
+    pub shutdown_token: CancellationToken,
+}
+
+impl ControlHandle {
+    pub fn shutdown(&self) {
+        self.shutdown_token.cancel();
+    }
+}
+
+/// Everything a route might need, cheap to clone.
+#[derive(Clone)]
+struct Handles {
+    vrc: VrcHandle,
+    map: MapHandle,
+    devices: DeviceHandle,
+    sessions: Arc<tokio::sync::Mutex<recording::Sessions>>,
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorBody {
+    error: String,
+}
+
+fn error_reply(status: StatusCode, msg: impl Into<String>) -> Response {
+    warp::reply::with_status(warp::reply::json(&ErrorBody { error: msg.into() }), status).into_response()
+}
+
+fn ok_reply() -> Response {
+    StatusCode::NO_CONTENT.into_response()
+}
+
+/// Largest body accepted by ordinary routes.
+const BODY_LIMIT: u64 = 1024 * 1024;
+/// Largest body accepted by `POST /devices/{id}/update`. Firmware comes as a JSON array of numbers,
+/// so this leaves room for an image of around 8MB.
+const FIRMWARE_BODY_LIMIT: u64 = 40 * 1024 * 1024;
+
+/// A JSON body of at most `BODY_LIMIT` bytes.
+fn json_body<T: DeserializeOwned + Send>() -> impl Filter<Extract = (T,), Error = warp::Rejection> + Copy {
+    warp::body::content_length_limit(BODY_LIMIT).and(warp::body::json())
+}
+
+/// Path segments arrive percent-encoded, ids and names can hold reserved characters like `#`.
+pub(super) fn decode_segment(segment: String) -> String {
+    urlencoding::decode(&segment).map(|s| s.into_owned()).unwrap_or(segment)
+}
+
+fn with_handles(handles: Handles) -> impl Filter<Extract = (Handles,), Error = Infallible> + Clone {
+    warp::any().map(move || handles.clone())
+}
+
+/// Starts serving the control api on `addr` and returns its handle.
+///
+/// Fails when the api token can't be read from or written to the app root.
+pub async fn start_control_api(
+    addr: SocketAddr,
+    vrc: VrcHandle,
+    map: MapHandle,
+    devices: DeviceHandle,
+) -> std::io::Result<ControlHandle> {
+    let access = Arc::new(auth::Access::new(load_token()?, addr.ip()));
+    if !addr.ip().is_loopback() {
+        log::warn!("Control api is reachable beyond this machine, every request needs the token in {}", TOKEN_FILE);
+    }
+
+    let token = CancellationToken::new();
+    let handles = Handles {
+        vrc,
+        map,
+        devices,
+        sessions: Default::default(),
+    };
+
+    let routes = auth::refused(access).or(routes(handles)).unify();
+    let child = token.child_token();
+    tokio::spawn(async move {
+        warp::serve(routes)
+            .bind(addr).await
+            .graceful(async move {
+                child.cancelled().await;
+            })
+            .run()
+            .await;
+        log::debug!("Control api stopped");
+    });
+
+    log::info!("Control api listening on http://{}", addr);
+    Ok(ControlHandle { shutdown_token: token })
+}
+
+fn routes(handles: Handles) -> impl Filter<Extract = (Response,), Error = warp::Rejection> + Clone {
+    let h = || with_handles(handles.clone());
+
+    let device_list = warp::get()
+        .and(warp::path!("devices"))
+        .and(h())
+        .map(|h: Handles| get_device_list(&h.devices));
+
+    let device_info = warp::put()
+        .and(warp::path!("devices" / String / "info"))
+        .and(json_body())
+        .and(h())
+        .map(|id: String, inf: DeviceInfo, h: Handles| set_device_info(&h.devices, decode_segment(id).into(), inf));
+
+    let device_map = warp::put()
+        .and(warp::path!("devices" / String / "map"))
+        .and(json_body())
+        .and(h())
+        .map(|id: String, map: GameMap, h: Handles| upload_device_map(&h.devices, decode_segment(id), map));
+
+    let device_multiplier = warp::put()
+        .and(warp::path!("devices" / String / "multiplier"))
+        .and(json_body())
+        .map(|id: String, body: ValueBody| update_device_setting(decode_segment(id).into(), |d| d.intensity = body.value));
+
+    let device_offset = warp::put()
+        .and(warp::path!("devices" / String / "offset"))
+        .and(json_body())
+        .map(|id: String, body: ValueBody| update_device_setting(decode_segment(id).into(), |d| d.offset = body.value));
+
+    let device_interp = warp::put()
+        .and(warp::path!("devices" / String / "interp"))
+        .and(json_body())
+        .map(|id: String, algo: InterpAlgo| update_device_setting(decode_segment(id).into(), |d| d.interp_algo = algo));
+
+    let device_post = warp::put()
+        .and(warp::path!("devices" / String / "post_process"))
+        .and(json_body())
+        .map(|id: String, stages: Vec<PostStage>| update_device_setting(decode_segment(id).into(), |d| d.post_process = stages));
+
+    let device_safety = warp::put()
+        .and(warp::path!("devices" / String / "safety"))
+        .and(json_body())
+        .map(|id: String, limits: SafetyLimits| update_device_setting(decode_segment(id).into(), |d| d.safety = limits));
+
+    let device_wifi_rate = warp::put()
+        .and(warp::path!("devices" / String / "wifi_rate"))
+        .and(json_body())
+        .map(|id: String, rate: WifiSendRate| update_device_setting(decode_segment(id).into(), |d| d.wifi_rate = rate));
+
+    let device_swap_nodes = warp::put()
+        .and(warp::path!("devices" / String / "swap_nodes"))
+        .and(json_body())
+        .and(h())
+        .map(|id: String, body: SwapNodesBody, h: Handles| swap_device_nodes(&h.devices, decode_segment(id).into(), body));
+
+    let device_update = warp::post()
+        .and(warp::path!("devices" / String / "update"))
+        .and(warp::body::content_length_limit(FIRMWARE_BODY_LIMIT))
+        .and(warp::body::json())
+        .and(h())
+        .and_then(start_device_update);
+
+    let motors_get = warp::get()
+        .and(warp::path!("devices" / String / "motors"))
+        .map(|id: String| {
+            let (_, dev) = state::get_device(&decode_segment(id).into());
+            warp::reply::json(&dev.load().motors).into_response()
+        });
+
+    let motor_set = warp::put()
+        .and(warp::path!("devices" / String / "motors" / usize))
+        .and(json_body())
+        .map(|id: String, idx: usize, motor: MotorOverride| {
+            update_device_setting(decode_segment(id).into(), |d| {
+                d.motors.insert(idx, motor);
+            })
+        });
+
+    let motor_reset = warp::delete()
+        .and(warp::path!("devices" / String / "motors" / usize))
+        .map(|id: String, idx: usize| {
+            update_device_setting(decode_segment(id).into(), |d| {
+                d.motors.remove(&idx);
+            })
+        });
+
+    let device_esp = warp::get()
+        .and(warp::path!("devices" / String / "esp_model"))
+        .and(h())
+        .map(|id: String, h: Handles| {
+            match h.devices.with_device(&decode_segment(id).into(), |d| d.info().get_esp32()) {
+                Some(model) => warp::reply::json(&model).into_response(),
+                None => error_reply(StatusCode::NOT_FOUND, "unable to find device with id"),
+            }
+        });
+
+    let core_map = warp::get()
+        .and(warp::path!("map"))
+        .and(h())
+        .map(|h: Handles| warp::reply::json(&h.map.get_state()).into_response());
+
+    let play_point = warp::post()
+        .and(warp::path!("map" / "play_point"))
+        .and(json_body())
+        .and(h())
+        .map(|body: PlayPointBody, h: Handles| play_point(&h.map, body));
+
+    let node_radius = warp::put()
+        .and(warp::path!("map" / "nodes" / String / "radius"))
+        .and(json_body())
+        .and(h())
+        .map(|id: String, body: ValueBody, h: Handles| {
+            match h.map.with_node_mut(&decode_segment(id).into(), |n| n.set_radius(body.value)) {
+                Some(_) => ok_reply(),
+                None => error_reply(StatusCode::NOT_FOUND, "Failed to get node"),
+            }
+        });
+
+    let tag_radius = warp::put()
+        .and(warp::path!("map" / "tags" / String / "radius"))
+        .and(json_body())
+        .and(h())
+        .map(|tag: String, body: ValueBody, h: Handles| {
+            let tag = decode_segment(tag);
+            h.map.has_tag_mut(&tag, |n| n.set_radius(body.value));
+            ok_reply()
+        });
+
+    let vrc_info = warp::get()
+        .and(warp::path!("vrc"))
+        .and(h())
+        .map(|h: Handles| warp::reply::json(&h.vrc.get_info()).into_response());
+
+    let vrc_set = warp::put()
+        .and(warp::path!("vrc"))
+        .and(json_body())
+        .map(|body: VrcBody| set_vrc(body));
+
+    let layers_get = warp::get()
+        .and(warp::path!("layers"))
+        .map(|| warp::reply::json(&state::get_config().mapping_menu.load().source_layers).into_response());
+
+    let layers_set = warp::put()
+        .and(warp::path!("layers"))
+        .and(json_body())
+        .map(|layers: HashMap<String, Layering>| {
+            let shared = &state::get_config().mapping_menu;
+            let mut new = StandardMenu::clone(&shared.load());
+            new.source_layers = layers;
+            shared.swap(Arc::new(new));
+            state::mark_dirty();
+            ok_reply()
+        });
+
+    let interp_get = warp::get()
+        .and(warp::path!("interp"))
+        .map(|| warp::reply::json(&state::get_config().mapping_menu.load().source_interp).into_response());
+
+    let interp_set = warp::put()
+        .and(warp::path!("interp"))
+        .and(json_body())
+        .map(|interp: HashMap<String, EventInterp>| {
+            let shared = &state::get_config().mapping_menu;
+            let mut new = StandardMenu::clone(&shared.load());
+            new.source_interp = interp;
+            shared.swap(Arc::new(new));
+            state::mark_dirty();
+            ok_reply()
+        });
+
+    let repos_get = warp::get()
+        .and(warp::path!("repositories"))
+        .map(|| warp::reply::json(&*state::get_config().devices.ota_repositories.lock()).into_response());
+
+    let repos_set = warp::put()
+        .and(warp::path!("repositories"))
+        .and(json_body())
+        .map(|repos: Vec<GitRepo>| {
+            *state::get_config().devices.ota_repositories.lock() = repos;
+            state::mark_dirty();
+            ok_reply()
+        });
+
+    let timeout_get = warp::get()
+        .and(warp::path!("wifi_timeout"))
+        .map(|| warp::reply::json(&**state::get_config().devices.wifi_device_timeout.load()).into_response());
+
+    let timeout_set = warp::put()
+        .and(warp::path!("wifi_timeout"))
+        .and(json_body())
+        .map(|body: ValueBody| {
+            state::get_config().devices.wifi_device_timeout.store(Arc::new(body.value));
+            state::mark_dirty();
+            ok_reply()
+        });
+
+    let recordings_list = warp::get()
+        .and(warp::path!("recordings"))
+        .and_then(recording::list);
+
+    let recording_start = warp::post()
+        .and(warp::path!("recordings" / "start"))
+        .and(json_body())
+        .and(h())
+        .and_then(recording::start);
+
+    let recording_stop = warp::post()
+        .and(warp::path!("recordings" / "stop"))
+        .and(h())
+        .and_then(recording::stop);
+
+    let playback_start = warp::post()
+        .and(warp::path!("recordings" / String / "play"))
+        .and(json_body())
+        .and(h())
+        .and_then(recording::play);
+
+    let playback_stop = warp::delete()
+        .and(warp::path!("playback"))
+        .and(h())
+        .and_then(recording::stop_playback);
+
+    let patterns_list = warp::get()
+        .and(warp::path!("patterns"))
+        .and_then(patterns::list);
+
+    let pattern_play = warp::post()
+        .and(warp::path!("patterns" / String / "play"))
+        .and(json_body())
+        .and(h())
+        .and_then(patterns::play);
+
+    let patterns_stop = warp::delete()
+        .and(warp::path!("patterns"))
+        .and(h())
+        .and_then(patterns::stop);
+
+    let mappings_list = warp::get()
+        .and(warp::path!("bhaptics" / "mappings"))
+        .map(mappings::list);
+
+    let mapping_pin = warp::put()
+        .and(warp::path!("bhaptics" / "mappings" / String / "pin"))
+        .and(json_body())
+        .map(mappings::pin);
+
+    let mappings_export = warp::get()
+        .and(warp::path!("bhaptics" / "mappings" / "export"))
+        .and(warp::query::<HashMap<String, String>>())
+        .map(mappings::export);
+
+    let mappings_import = warp::post()
+        .and(warp::path!("bhaptics" / "mappings" / "import"))
+        .and(json_body())
+        .map(mappings::import);
+
+    let stream = warp::path!("ws")
+        .and(warp::ws())
+        .and(h())
+        .map(|upgrade: warp::ws::Ws, h: Handles| {
+            upgrade
+                .on_upgrade(move |socket| ws::run_socket(socket, h.map, h.devices))
+                .into_response()
+        });
+
+    device_list
+        .or(device_info).unify()
+        .or(device_map).unify()
+        .or(device_multiplier).unify()
+        .or(device_offset).unify()
+        .or(device_interp).unify()
+        .or(device_post).unify()
+        .or(device_safety).unify()
+        .or(device_wifi_rate).unify()
+        .or(device_swap_nodes).unify()
+        .or(device_update).unify()
+        .or(motors_get).unify()
+        .or(motor_set).unify()
+        .or(motor_reset).unify()
+        .or(device_esp).unify()
+        .or(core_map).unify()
+        .or(play_point).unify()
+        .or(node_radius).unify()
+        .or(tag_radius).unify()
+        .or(vrc_info).unify()
+        .or(vrc_set).unify()
+        .or(layers_get).unify()
+        .or(layers_set).unify()
+        .or(interp_get).unify()
+        .or(interp_set).unify()
+        .or(repos_get).unify()
+        .or(repos_set).unify()
+        .or(timeout_get).unify()
+        .or(timeout_set).unify()
+        .or(recordings_list).unify()
+        .or(recording_start).unify()
+        .or(recording_stop).unify()
+        .or(playback_start).unify()
+        .or(playback_stop).unify()
+        .or(patterns_list).unify()
+        .or(pattern_play).unify()
+        .or(patterns_stop).unify()
+        .or(mappings_list).unify()
+        .or(mapping_pin).unify()
+        .or(mappings_export).unify()
+        .or(mappings_import).unify()
+        .or(stream).unify()
+}
+
+/// Body for any endpoint that only sets a single number.
+#[derive(Debug, Deserialize)]
+struct ValueBody {
+    value: f32,
+}
+
+#[derive(Debug, Deserialize)]
+struct PlayPointBody {
+    /// xyz location to insert point
+    location: Vec3,
+    /// the power percentage to play 1 = no change
+    power: f32,
+    /// seconds until the point is removed.
+    duration: f32,
+    /// Overrides the interpolation set for the `API` source.
+    #[serde(default)]
+    interp: Option<EventInterp>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SwapNodesBody {
+    pos1: Vec3,
+    pos2: Vec3,
+}
+
+/// Firmware for `POST /devices/{id}/update`, the device comes from the path.
+#[derive(Deserialize)]
+struct UpdateBody {
+    method: UpdateMethod,
+    /// Raw bytes of the .bin fw file.
+    bytes: Vec<u8>,
+}
+
+#[derive(Debug, Deserialize)]
+struct VrcBody {
+    mult: f32,
+    ratio: f32,
+    samples: usize,
+    smooth_s: Duration,
+}
+
+fn get_device_list(dev: &DeviceHandle) -> Response {
+    let devices: Vec<(DeviceId, Option<DeviceInfo>)> = dev
+        .devices()
+        .into_iter()
+        .map(|id| {
+            let info = dev.with_device(&id, |d| d.info());
+            (id, info)
+        })
+        .collect();
+    warp::reply::json(&devices).into_response()
+}
+
+fn set_device_info(dev: &DeviceHandle, id: DeviceId, inf: DeviceInfo) -> Response {
+    match dev.with_device_mut(&id, |f| f.update_info(inf)) {
+        Some(_) => ok_reply(),
+        None => error_reply(StatusCode::NOT_FOUND, format!("No Device with id: {}", id.0)),
+    }
+}
+
+fn upload_device_map(dev: &DeviceHandle, id: String, upload: GameMap) -> Response {
+    // Extract a plain list of HapticNode from the config while preserving the indices.
+    let haptic_nodes: Vec<HapticNode> = upload.nodes.into_iter().map(|node| node.node_data).collect();
+
+    let res = dev.with_device_mut(&id.clone().into(), |d| {
+        let mut info = d.info();
+        info.set_nodes(haptic_nodes);
+        d.update_info(info);
+    });
+
+    state::mark_dirty();
+    match res {
+        Some(_) => ok_reply(),
+        None => error_reply(StatusCode::NOT_FOUND, format!("No Device with id: {}", id)),
+    }
+}
+
+fn swap_device_nodes(dev: &DeviceHandle, id: DeviceId, body: SwapNodesBody) -> Response {
+    let res = dev.with_device_mut(&id, |d| {
+        let mut info = d.info();
+        info.swap_nodes(body.pos1, body.pos2)?;
+        d.update_info(info);
+        Ok(())
+    });
+
+    match res {
+        Some(Ok(())) => ok_reply(),
+        Some(Err(e)) => error_reply(StatusCode::BAD_REQUEST, e),
+        None => error_reply(StatusCode::NOT_FOUND, format!("No Device with id: {}", id.0)),
+    }
+}
+
+/// Flashes the device, replying once the update has finished.
+async fn start_device_update(id: String, body: UpdateBody, h: Handles) -> Result<Response, warp::Rejection> {
+    let fw = Firmware::new(body.bytes, body.method, decode_segment(id));
+    let res = tokio::task::spawn_blocking(move || {
+        log::trace!("Starting OTA Update");
+        h.devices.with_device(&fw.id.clone().into(), |d| fw.do_update(d))
+    })
+    .await;
+
+    Ok(match res {
+        Ok(Some(Ok(()))) => ok_reply(),
+        Ok(Some(Err(e))) => error_reply(StatusCode::INTERNAL_SERVER_ERROR, e),
+        Ok(None) => error_reply(StatusCode::NOT_FOUND, "unable to find device with id"),
+        Err(e) => error_reply(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    })
+}
+
+fn update_device_setting(id: DeviceId, edit: impl FnOnce(&mut PerDevice)) -> Response {
+    let (_, dev) = state::get_device(&id);
+    let mut new = PerDevice::clone(&dev.load());
+    edit(&mut new);
+    state::update_device(Arc::new(new));
+    state::mark_dirty();
+    ok_reply()
+}
+
+fn play_point(map: &MapHandle, body: PlayPointBody) -> Response {
+    let duration = match Duration::try_from_secs_f32(body.duration.max(0.)) {
+        Ok(d) => d,
+        Err(e) => return error_reply(StatusCode::BAD_REQUEST, format!("invalid duration: {}", e)),
+    };
+    let event = match Event::new(
+        "Play Point".to_string(),
+        EventEffectType::Location(body.location),
+        vec![body.power],
+        duration,
+        vec!["API".to_string()],
+    ) {
+        Ok(e) => e,
+        Err(e) => return error_reply(StatusCode::BAD_REQUEST, format!("{:?}", e)),
+    };
+    let event = match body.interp {
+        Some(interp) => event.with_interp(interp),
+        None => event,
+    };
+
+    match map.send_event_blocking(InputEventMessage::StartEvent(event)) {
+        Ok(_) => ok_reply(),
+        Err(e) => {
+            log::warn!("Unable to queue play point: {}", e);
+            error_reply(StatusCode::SERVICE_UNAVAILABLE, e.to_string())
+        }
+    }
+}
+
+fn set_vrc(body: VrcBody) -> Response {
+    let shared = &state::get_config().vrc_settings;
+    let mut new = VrcSettings::clone(&shared.load());
+    new.velocity_mult = body.mult;
+    new.velocity_ratio = body.ratio;
+    new.sample_cache = body.samples;
+    new.smoothing_time = body.smooth_s;
+
+    shared.swap(Arc::new(new));
+    state::mark_dirty();
+    ok_reply()
+}
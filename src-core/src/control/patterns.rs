@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+use warp::http::StatusCode;
+use warp::reply::{Reply, Response};
+
+use super::{decode_segment, error_reply, ok_reply, Handles};
+use crate::bhaptics::patterns::{self, PatternError, RotationOption, ScaleOption, TactPattern};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(super) struct PlayBody {
+    scale: ScaleOption,
+    rotation: RotationOption,
+}
+
+#[derive(Debug, Serialize)]
+struct PlayedBody {
+    events: usize,
+}
+
+fn pattern_error(e: PatternError) -> Response {
+    let status = match e {
+        PatternError::InvalidName(_) | PatternError::Parse(_) | PatternError::NoTracks => StatusCode::BAD_REQUEST,
+        PatternError::Io(_) => StatusCode::NOT_FOUND,
+        PatternError::MapClosed => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    error_reply(status, format!("{:?}", e))
+}
+
+pub(super) async fn list() -> Result<Response, warp::Rejection> {
+    Ok(warp::reply::json(&patterns::list_patterns().await).into_response())
+}
+
+pub(super) async fn play(name: String, body: PlayBody, h: Handles) -> Result<Response, warp::Rejection> {
+    let name = decode_segment(name);
+    let pattern = match TactPattern::load(&name).await {
+        Ok(p) => p,
+        Err(e) => return Ok(pattern_error(e)),
+    };
+
+    match patterns::play_pattern(&h.map, &pattern, body.scale, body.rotation).await {
+        Ok(events) => Ok(warp::reply::json(&PlayedBody { events }).into_response()),
+        Err(e) => Ok(pattern_error(e)),
+    }
+}
+
+pub(super) async fn stop(h: Handles) -> Result<Response, warp::Rejection> {
+    match patterns::stop_patterns(&h.map).await {
+        Ok(()) => Ok(ok_reply()),
+        Err(e) => Ok(pattern_error(e)),
+    }
+}
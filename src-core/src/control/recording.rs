@@ -0,0 +1,96 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use warp::http::StatusCode;
+use warp::reply::{Reply, Response};
+
+use super::{decode_segment, error_reply, ok_reply, Handles};
+use crate::file::{list_named_files, named_file, Directory};
+use crate::mapping::recording::{
+    start_playback, start_recording, PlaybackHandle, PlaybackOptions, RecordOptions, Recording,
+    RecordingHandle, RECORDING_EXTENSION,
+};
+
+/// The recording and playback currently running through the api, at most one of each.
+#[derive(Default)]
+pub(super) struct Sessions {
+    recording: Option<RecordingHandle>,
+    playback: Option<PlaybackHandle>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct StartBody {
+    name: String,
+    #[serde(flatten)]
+    options: RecordOptions,
+}
+
+#[derive(Debug, Serialize)]
+struct StoppedBody {
+    frames: usize,
+}
+
+fn recording_path(name: &str) -> Option<PathBuf> {
+    named_file(Directory::Recordings, name, RECORDING_EXTENSION)
+}
+
+pub(super) async fn list() -> Result<Response, warp::Rejection> {
+    let names = list_named_files(Directory::Recordings, RECORDING_EXTENSION).await;
+    Ok(warp::reply::json(&names).into_response())
+}
+
+pub(super) async fn start(body: StartBody, h: Handles) -> Result<Response, warp::Rejection> {
+    let Some(path) = recording_path(&body.name) else {
+        return Ok(error_reply(StatusCode::BAD_REQUEST, "Invalid recording name"));
+    };
+
+    let mut sessions = h.sessions.lock().await;
+    if sessions.recording.is_some() {
+        return Ok(error_reply(StatusCode::CONFLICT, "Already recording"));
+    }
+
+    match start_recording(path, h.map.clone(), h.devices.clone(), body.options).await {
+        Ok(handle) => {
+            sessions.recording = Some(handle);
+            Ok(ok_reply())
+        }
+        Err(e) => Ok(error_reply(StatusCode::INTERNAL_SERVER_ERROR, format!("{:?}", e))),
+    }
+}
+
+pub(super) async fn stop(h: Handles) -> Result<Response, warp::Rejection> {
+    let Some(handle) = h.sessions.lock().await.recording.take() else {
+        return Ok(error_reply(StatusCode::NOT_FOUND, "Not recording"));
+    };
+
+    match handle.stop().await {
+        Ok(frames) => Ok(warp::reply::json(&StoppedBody { frames }).into_response()),
+        Err(e) => Ok(error_reply(StatusCode::INTERNAL_SERVER_ERROR, format!("{:?}", e))),
+    }
+}
+
+pub(super) async fn play(name: String, options: PlaybackOptions, h: Handles) -> Result<Response, warp::Rejection> {
+    let name = decode_segment(name);
+    let Some(path) = recording_path(&name) else {
+        return Ok(error_reply(StatusCode::BAD_REQUEST, "Invalid recording name"));
+    };
+
+    let recording = match Recording::load(&path).await {
+        Ok(r) => r,
+        Err(e) => return Ok(error_reply(StatusCode::NOT_FOUND, format!("{:?}", e))),
+    };
+
+    // replacing the handle stops whatever was playing before.
+    h.sessions.lock().await.playback = Some(start_playback(h.map.clone(), recording, options));
+    Ok(ok_reply())
+}
+
+pub(super) async fn stop_playback(h: Handles) -> Result<Response, warp::Rejection> {
+    match h.sessions.lock().await.playback.take() {
+        Some(playback) => {
+            playback.stop();
+            Ok(ok_reply())
+        }
+        None => Ok(error_reply(StatusCode::NOT_FOUND, "Nothing playing")),
+    }
+}
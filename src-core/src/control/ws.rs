@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use serde::Serialize;
+use tokio::sync::mpsc;
+use warp::ws::{Message, WebSocket};
+
+use crate::devices::{DeviceHandle, DeviceOutEvents};
+use crate::mapping::{MapHandle, MapInfo};
+
+/// How often a map snapshot is pushed to each socket.
+const MAP_SNAPSHOT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Everything pushed down the control websocket.
+#[derive(Serialize)]
+#[serde(tag = "type", content = "value")]
+enum StreamMessage<'a> {
+    Device(&'a DeviceOutEvents),
+    Map(&'a MapInfo),
+}
+
+/// Streams device events and periodic map snapshots until the client disconnects.
+pub(super) async fn run_socket(socket: WebSocket, map: MapHandle, devices: DeviceHandle) {
+    let (mut tx, mut rx) = socket.split();
+
+    let (dev_tx, mut dev_rx) = mpsc::channel(10);
+    devices.register(dev_tx);
+
+    let mut interval = tokio::time::interval(MAP_SNAPSHOT_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+    loop {
+        let text = tokio::select! {
+            event = dev_rx.recv() => {
+                let Some(event) = event else { break };
+                serde_json::to_string(&StreamMessage::Device(&event))
+            }
+            _ = interval.tick() => {
+                serde_json::to_string(&StreamMessage::Map(&map.get_state()))
+            }
+            incoming = rx.next() => {
+                match incoming {
+                    // we don't take commands over the socket, only watch for close.
+                    Some(Ok(msg)) if !msg.is_close() => continue,
+                    _ => break,
+                }
+            }
+        };
+
+        let text = match text {
+            Ok(t) => t,
+            Err(e) => {
+                log::error!("Unable to serialize control stream message: {}", e);
+                continue;
+            }
+        };
+
+        if tx.send(Message::text(text)).await.is_err() {
+            break;
+        }
+    }
+
+    log::trace!("Control websocket closed");
+    let _ = tx.close().await;
+}
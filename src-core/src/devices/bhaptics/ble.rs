@@ -1,11 +1,10 @@
 use btleplug::api::{BDAddr, Characteristic, PeripheralProperties};
 use btleplug::api::{Central, Manager as _, Peripheral as _, ScanFilter, WriteType};
 use btleplug::platform::{Adapter, Manager, Peripheral};
-use parking_lot::Mutex;
 use std::collections::BTreeSet;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, LazyLock};
-use std::time::Duration;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::{
     mpsc::{self, Sender},
     OnceCell, RwLock,
@@ -13,6 +12,7 @@ use tokio::sync::{
 use tokio::time;
 use uuid::{uuid, Uuid};
 
+use crate::devices::bhaptics::supervisor::{backoff, BleConnectionState, BlePeripheral, Slots};
 use crate::devices::bhaptics::{BhapticBle, BhapticsModel};
 use crate::devices::{DeviceMessage, HapticDevice};
 use crate::log_err;
@@ -20,8 +20,8 @@ use crate::log_err;
 static IS_SCANNING: AtomicBool = AtomicBool::new(false);
 static BLE_ADAPTER: RwLock<Option<Arc<Adapter>>> = RwLock::const_new(None);
 static BLE_MANAGER: OnceCell<Manager> = OnceCell::const_new();
-pub(crate) static CONNECTED_DEVICES: LazyLock<boxcar::Vec<Mutex<Option<Arc<Peripheral>>>>> =
-    LazyLock::new(|| boxcar::Vec::new());
+/// Every BLE connection, `BhapticBle` devices refer to theirs by index.
+pub(crate) static SLOTS: Slots<Peripheral> = Slots::new();
 
 /// If this fails to initialize the manager and another instance is called it will try to initailize every time.
 /// Be careful of repeated failed calling across tasks.
@@ -34,23 +34,20 @@ async fn ble_manager() -> Result<&'static Manager, BleError> {
         .await
 }
 
+/// Disconnects the device in this slot and frees the slot for reuse.
 pub async fn disconnect(idx: usize) {
-    if let Some(dev) = CONNECTED_DEVICES.get(idx) {
-        let peripheral = dev.lock().clone();
-        if let Some(p) = peripheral {
-            log_err!(p.disconnect().await);
-        }
-        *dev.lock() = None;
+    if let Some(p) = SLOTS.release(idx) {
+        log_err!(p.disconnect_motor().await);
     }
 }
 
 pub async fn send(idx: usize, data: &[u8], char: &Characteristic) -> Result<(), BleError> {
-    let peripheral = CONNECTED_DEVICES
-        .get(idx)
-        .and_then(|d| d.lock().clone())
-        .ok_or(BleError::DeviceNotAvailable)?;
-    peripheral.write(char, data, WriteType::WithoutResponse).await?;
-    Ok(())
+    SLOTS.send(idx, data, char).await
+}
+
+/// The connection state of the device in this slot.
+pub fn connection_state(idx: usize) -> BleConnectionState {
+    SLOTS.state(idx)
 }
 
 async fn connect(per: &Peripheral) -> Result<BTreeSet<Characteristic>, BleError> {
@@ -59,6 +56,30 @@ async fn connect(per: &Peripheral) -> Result<BTreeSet<Characteristic>, BleError>
     Ok(per.characteristics())
 }
 
+impl BlePeripheral for Peripheral {
+    fn ble_address(&self) -> BDAddr {
+        self.address()
+    }
+
+    async fn connect_motor(&self) -> Result<Characteristic, BleError> {
+        let chars = connect(self).await?;
+        DeviceChar::BhapticsStableMotor
+            .is_present(&chars)
+            .cloned()
+            .ok_or(BleError::MissingCharacteristic)
+    }
+
+    async fn write_motor(&self, char: &Characteristic, data: &[u8]) -> Result<(), BleError> {
+        self.write(char, data, WriteType::WithoutResponse).await?;
+        Ok(())
+    }
+
+    async fn disconnect_motor(&self) -> Result<(), BleError> {
+        self.disconnect().await?;
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct BleHandle {
     channel: Sender<HandleMsg>,
@@ -70,6 +91,10 @@ impl BleHandle {
     }
 
     pub fn send(&self, idx: usize, data: Box<[u8]>, char: Arc<Characteristic>) {
+        // frames for lost devices are dropped rather than queued.
+        if SLOTS.state(idx) != BleConnectionState::Connected {
+            return;
+        }
         log_err!(self.channel.try_send(HandleMsg::Send(data, idx, char)));
     }
 }
@@ -80,6 +105,9 @@ pub enum HandleMsg {
 }
 
 /// Should only be called once. Initializes all internal values.
+///
+/// Spawns the supervisor, which scans every `collection_interval` for new devices,
+/// reconnects lost ones with backoff and recovers from the adapter disappearing.
 pub async fn start_ble(
     sender: Sender<DeviceMessage>,
     collection_interval: Duration,
@@ -87,105 +115,123 @@ pub async fn start_ble(
     choose_new_adapter().await?;
     start_ble_scan(None).await?;
 
-    let local_dev = sender.clone();
     let (tx, rx) = mpsc::channel::<HandleMsg>(10);
     let handle = BleHandle { channel: tx };
 
-    // spawn task that feeds devices back through channel.
     let loop_handle = handle.clone();
     tokio::task::spawn(async move {
-        // I hate how nested this is but it'll do for now.
         let mut rx = rx;
+        let mut adapter_failures = 0u32;
+        let mut next_scan = time::Instant::now();
         loop {
             tokio::select! {
-                        Some(msg) = rx.recv() => {
-                            match msg {
-                                HandleMsg::Disconnect(idx) => {
-                                    let addr = CONNECTED_DEVICES
-                                        .get(idx)
-                                        .and_then(|slot| slot.lock().as_ref().map(|p| p.address()));
-                                    if let Some(addr) = addr {
-                                        log_err!(local_dev.send(DeviceMessage::Remove(addr.to_string().into())).await);
-                                    }
-
-                                    disconnect(idx).await;
-                                }
-                                HandleMsg::Send(data, idx, char) => {
-                                    let e = send(idx, &data, &char).await;
-                                    match e {
-                                        Err(BleError::DeviceNotAvailable) | Err(BleError::BtlePlug(_)) => {
-                                            let addr = CONNECTED_DEVICES
-                                                .get(idx)
-                                                .and_then(|slot| slot.lock().as_ref().map(|p| p.address()));
-                                            if let Some(addr) = addr {
-                                                log_err!(local_dev.send(DeviceMessage::Remove(addr.to_string().into())).await);
-                                            }
-                                            disconnect(idx).await;
-                                        },
-                                        _ => log_err!(e)
-                                    }
-                                }
+                Some(msg) = rx.recv() => {
+                    match msg {
+                        HandleMsg::Disconnect(idx) => {
+                            if let Some(addr) = SLOTS.address(idx) {
+                                log_err!(sender.send(DeviceMessage::Remove(addr.to_string().into())).await);
+                            }
+                            disconnect(idx).await;
+                        }
+                        HandleMsg::Send(data, idx, char) => {
+                            if let Err(e) = send(idx, &data, &char).await {
+                                log::warn!("BLE device in slot {idx} lost: {e:?}");
+                                notify_state(&sender, idx).await;
                             }
                         }
-                        _ = tokio::time::sleep(collection_interval) => {
-                            match get_adapter().await {
-                                Ok(adapter) => {
-                                    match adapter.peripherals().await {
-                                        Ok(peripherals) => {
-                                            // connect to bhaptics peripherials.
-                                            for p in peripherals {
-                                                let addr = p.address();
-                                                let already_connected = {
-                                                    CONNECTED_DEVICES.iter().any(|(_, slot)| {
-                                                        slot.lock().as_ref().map_or(false, |per| per.address() == addr)
-                                                    })
-                                                };
-                                                if already_connected {
-                                                    log::trace!("ALready filled slot");
-                                                    log::trace!("{:?}", CONNECTED_DEVICES);
-                                                    continue;
-                                                }
-                                                if let Some(prop) = try_get_properties(&p).await {
-                                                    let ident = get_ident(&prop).await;
-                                                    if let Some((name, addr)) = ident {
-                                                        if let Some(mdl)  = BhapticsModel::from_name(name.as_str()) {
-                                                            match connect(&p).await {
-                                                                Ok(chars) => {
-                                                                    let idx = CONNECTED_DEVICES.push(Some(p.into()).into());
-                                                                    if let Some(char) = DeviceChar::BhapticsStableMotor.is_present(&chars) {
-                                                                        log::trace!("Found BLE haptic device: {:?}", addr);
-                                                                        let dev = BhapticBle::new(mdl, loop_handle.clone(), sender.clone(), *addr, idx, char.clone());
-                                                                        log_err!(local_dev.send(DeviceMessage::Register(HapticDevice::BhapticBle(dev))).await);
-                                                                    }
-                                                                },
-                                                                Err(e) => {
-                                                                    log::error!("BLE connecting error: {e:?}");
-                                                                }
-                                                            }
-                                                        }
-                                                    }
-                                                }
-                                            }
-                                        }
-                                        Err(e) => {
-                                            log::error!("Bhaptics BLE found error: {e:?}");
-                                            break;
-                                        }
-                                    }
-                                }
-                                Err(e) => {
-                                    log::error!("Bhaptics BLE found error: {e:?}");
-                                    break;
-                                }
-                            };
+                    }
+                }
+                _ = time::sleep_until(next_scan) => {
+                    match supervise(&sender, &loop_handle).await {
+                        Ok(()) => {
+                            adapter_failures = 0;
+                            next_scan = time::Instant::now() + collection_interval;
+                        }
+                        Err(e) => {
+                            adapter_failures = adapter_failures.saturating_add(1);
+                            let delay = backoff(adapter_failures).max(collection_interval);
+                            log::error!("Bhaptics BLE adapter error, retrying in {delay:?}: {e:?}");
+                            recover_adapter().await;
+                            next_scan = time::Instant::now() + delay;
                         }
                     }
+                }
+            }
         }
     });
 
     Ok(handle)
 }
 
+/// One pass over the peripherals the adapter can see.
+///
+/// New bHaptics devices are connected and registered, lost ones are reconnected once their backoff has passed.
+async fn supervise(sender: &Sender<DeviceMessage>, handle: &BleHandle) -> Result<(), BleError> {
+    let adapter = get_adapter().await?;
+    let peripherals = adapter.peripherals().await?;
+
+    for p in peripherals {
+        let addr = p.address();
+        let idx = match SLOTS.find(addr) {
+            Some(idx) => idx,
+            None => {
+                let Some(prop) = try_get_properties(&p).await else {
+                    continue;
+                };
+                let Some((name, _)) = get_ident(&prop).await else {
+                    continue;
+                };
+                let Some(mdl) = BhapticsModel::from_name(name.as_str()) else {
+                    continue;
+                };
+                log::trace!("Found BLE haptic device: {:?} ({name})", addr);
+                SLOTS.claim(addr, mdl)
+            }
+        };
+
+        if !SLOTS.is_due(idx, Instant::now()) {
+            continue;
+        }
+
+        match SLOTS.try_connect(idx, Arc::new(p)).await {
+            Ok((char, true)) => {
+                let Some(mdl) = SLOTS.model(idx) else {
+                    continue;
+                };
+                let dev = BhapticBle::new(mdl, handle.clone(), sender.clone(), addr, idx, char);
+                log_err!(sender.send(DeviceMessage::Register(HapticDevice::BhapticBle(dev))).await);
+            }
+            Ok((_, false)) => {
+                log::info!("Reconnected BLE device {:?}", addr);
+                notify_state(sender, idx).await;
+            }
+            Err(e) => {
+                log::warn!("BLE connecting error for {:?}, {:?}: {e:?}", addr, SLOTS.state(idx));
+                notify_state(sender, idx).await;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Lets subscribers know a devices connection state changed.
+async fn notify_state(sender: &Sender<DeviceMessage>, idx: usize) {
+    if let Some(addr) = SLOTS.address(idx) {
+        log_err!(sender.send(DeviceMessage::InfoDirty(addr.to_string().into())).await);
+    }
+}
+
+/// Picks an adapter again and restarts scanning, for when the current one was unplugged or errored.
+async fn recover_adapter() {
+    IS_SCANNING.store(false, Ordering::SeqCst);
+    if let Err(e) = choose_new_adapter().await {
+        log::warn!("No BLE adapter available: {e:?}");
+        return;
+    }
+    log_err!(start_ble_scan(None).await);
+}
+
 /// Starts a bluetooth scan and chooses an adapter if not already set.
 /// Optional timeout will stop scanning after a certain duration.
 /// Note: Will return immediatly regardless of duration.
@@ -297,6 +343,8 @@ pub enum BleError {
     AdapterUnavailable,
     /// A scan has already been started.
     AlreadyScanning,
+    /// Connected, but the device has no bHaptics motor characteristic.
+    MissingCharacteristic,
     /// Generic BtlePlug Error
     ///
     /// Typically signals; "This operation failed unrecoverably"
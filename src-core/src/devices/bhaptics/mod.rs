@@ -1,20 +1,24 @@
 mod ble;
+mod supervisor;
 
-pub use ble::{send, start_ble};
+pub use ble::{send, start_ble, BleError};
+pub use supervisor::{BleConnectionState, BlePeripheral, Slots};
 
 use btleplug::api::{BDAddr, Characteristic};
 use parking_lot::RwLock;
 use std::{
+    collections::HashMap,
     sync::{Arc, LazyLock},
     time::Instant,
 };
+use strum::IntoEnumIterator;
 use tokio::sync::mpsc::Sender;
 
 use crate::{
-    bhaptics::maps::x40_vest::{x40_vest_back, x40_vest_front},
+    bhaptics::game::network::event_map::PatternLocation,
     devices::{bhaptics::ble::BleHandle, DeviceId, DeviceInfo, DeviceMessage},
     log_err,
-    mapping::{haptic_node::HapticNode, NodeGroup},
+    mapping::haptic_node::HapticNode,
 };
 
 #[cfg_attr(feature = "specta", derive(specta::Type))]
@@ -23,6 +27,8 @@ pub struct BhapticInfo {
     pub id: DeviceId,
     pub nodes: Vec<HapticNode>,
     pub model: BhapticsModel,
+    #[serde(default)]
+    pub connection: BleConnectionState,
 }
 
 /// Describes where on teh 40nibble array each buffer index gets put.
@@ -42,87 +48,165 @@ const X16_NODE_INDICES: [usize; 16] = [
     32, 33, 34, 35,
 ];
 
+/// Which of the Tactal's head motors each TactVisor motor sits closest to, Left -> Right.
+const VISOR_HEAD_INDICES: [usize; 4] = [0, 2, 5, 3];
+
+/// Every model writes a 20 byte frame to the motor characteristic.
+const FRAME_SIZE: usize = 20;
+
 #[cfg_attr(feature = "specta", derive(specta::Type))]
-#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize, strum::EnumIter)]
 pub enum BhapticsModel {
     TacsuitX16,
+    TactsuitX40,
+    /// Head, 6 motors.
+    Tactal,
+    /// Head, 4 motors on the headset strap.
+    TactVisor,
+    TactosyArmL,
+    TactosyArmR,
+    TactosyHandL,
+    TactosyHandR,
+    TactosyFootL,
+    TactosyFootR,
+    TactGloveL,
+    TactGloveR,
 }
 
 impl BhapticsModel {
+    /// Matches the advertised BLE name.
+    ///
+    /// Paired devices advertise their side as a suffix, `Tactosy2_L`, `TactosyH_R` etc.
     pub fn from_name(name: &str) -> Option<Self> {
         match name {
-            "TactSuitX16" => Some(BhapticsModel::TacsuitX16),
-            _ => None,
+            "TactSuitX16" => return Some(BhapticsModel::TacsuitX16),
+            "TactSuitX40" | "Tactot" => return Some(BhapticsModel::TactsuitX40),
+            "Tactal" => return Some(BhapticsModel::Tactal),
+            "TactVisor" => return Some(BhapticsModel::TactVisor),
+            _ => {}
         }
+
+        let (base, left) = if let Some(base) = name.strip_suffix("_L").or_else(|| name.strip_suffix('L')) {
+            (base, true)
+        } else if let Some(base) = name.strip_suffix("_R").or_else(|| name.strip_suffix('R')) {
+            (base, false)
+        } else {
+            return None;
+        };
+
+        let (l, r) = match base {
+            "Tactosy" | "Tactosy2" => (BhapticsModel::TactosyArmL, BhapticsModel::TactosyArmR),
+            "TactosyH" => (BhapticsModel::TactosyHandL, BhapticsModel::TactosyHandR),
+            "TactosyF" => (BhapticsModel::TactosyFootL, BhapticsModel::TactosyFootR),
+            "TactGlove" => (BhapticsModel::TactGloveL, BhapticsModel::TactGloveR),
+            _ => return None,
+        };
+        Some(if left { l } else { r })
     }
 
     /// Number of motors to allocate in the input map
     pub fn motor_num(&self) -> usize {
         match self {
             BhapticsModel::TacsuitX16 => 16,
+            BhapticsModel::TactsuitX40 => 40,
+            BhapticsModel::Tactal => 6,
+            BhapticsModel::TactVisor => 4,
+            BhapticsModel::TactosyArmL | BhapticsModel::TactosyArmR => 6,
+            BhapticsModel::TactosyHandL | BhapticsModel::TactosyHandR => 6,
+            BhapticsModel::TactosyFootL | BhapticsModel::TactosyFootR => 3,
+            BhapticsModel::TactGloveL | BhapticsModel::TactGloveR => 6,
         }
     }
 
     // how big the output buffer we transmit over bluetooth is
     pub fn buffer_size(&self) -> usize {
-        match self {
-            BhapticsModel::TacsuitX16 => 20,
-        }
+        FRAME_SIZE
     }
 
     /// Encode motor feedback into the BLE write buffer.
-    /// `feedback` should have exactly `self.buffer_size()` elements, each 0.0..=1.0.
+    /// `feedback` should have `self.motor_num()` elements, each 0.0..=1.0.
+    ///
+    /// Vests pack two motors per byte (0-15), everything else takes one byte per motor (0-100).
     pub fn encode_feedback(&self, buf: &mut [u8], feedback: &[f32]) {
-        if buf.len() < (feedback.len() / 2) {
-            log::error!("Output buffer is smaller than feedback length");
+        if buf.len() < FRAME_SIZE {
+            log::error!("Output buffer must be {} bytes", FRAME_SIZE);
             return;
-        };
+        }
+        buf[..FRAME_SIZE].fill(0);
         match self {
-            BhapticsModel::TacsuitX16 => {
-                if buf.len() < 20 {
-                    log::error!("Output buffer must be 20 bytes");
-                    return;
-                }
-                buf[..20].fill(0);
-                for (motor, &nibble_idx) in X16_NIBBLE_INDICES.iter().enumerate() {
-                    let val = feedback
-                        .get(motor)
-                        .map(|v| (v.clamp(0.0, 1.0) * 15.0).round() as u8)
-                        .unwrap_or(0);
-                    let byte_idx = nibble_idx / 2;
-                    if nibble_idx % 2 == 0 {
-                        buf[byte_idx] |= val << 4;
-                    } else {
-                        buf[byte_idx] |= val;
-                    }
+            BhapticsModel::TacsuitX16 => encode_nibbles(buf, feedback, X16_NIBBLE_INDICES.iter().copied()),
+            BhapticsModel::TactsuitX40 => encode_nibbles(buf, feedback, 0..40),
+            _ => {
+                for (byte, v) in buf.iter_mut().zip(feedback.iter().take(self.motor_num())) {
+                    *byte = (v.clamp(0.0, 1.0) * 100.0).round() as u8;
                 }
             }
         }
     }
 
-    pub fn nodes(&self) -> &'static [HapticNode] {
+    /// The bHaptics location and motor each of our motors stands in for, in buffer order.
+    fn motor_locations(&self) -> Vec<(PatternLocation, usize)> {
+        let all = |loc: PatternLocation| (0..loc.motor_count()).map(move |i| (loc, i));
         match self {
-            BhapticsModel::TacsuitX16 => {
-                static NODES: LazyLock<Vec<HapticNode>> = LazyLock::new(|| {
-                    let front = x40_vest_front().rows;
-                    let back = x40_vest_back().rows;
-                    let all: Vec<_> = front.iter().chain(back.iter()).collect();
-
-                    X16_NODE_INDICES
-                        .iter()
-                        .map(|&i| {
-                            let v = all[i];
+            BhapticsModel::TacsuitX16 => X16_NODE_INDICES.iter().map(|&i| vest_motor(i)).collect(),
+            BhapticsModel::TactsuitX40 => (0..40).map(vest_motor).collect(),
+            BhapticsModel::Tactal => all(PatternLocation::Head).collect(),
+            BhapticsModel::TactVisor => VISOR_HEAD_INDICES.iter().map(|&i| (PatternLocation::Head, i)).collect(),
+            BhapticsModel::TactosyArmL => all(PatternLocation::ForearmL).collect(),
+            BhapticsModel::TactosyArmR => all(PatternLocation::ForearmR).collect(),
+            BhapticsModel::TactosyHandL | BhapticsModel::TactGloveL => all(PatternLocation::HandL).collect(),
+            BhapticsModel::TactosyHandR | BhapticsModel::TactGloveR => all(PatternLocation::HandR).collect(),
+            BhapticsModel::TactosyFootL => all(PatternLocation::FootL).collect(),
+            BhapticsModel::TactosyFootR => all(PatternLocation::FootR).collect(),
+        }
+    }
+
+    pub fn nodes(&self) -> &'static [HapticNode] {
+        static NODES: LazyLock<HashMap<BhapticsModel, Vec<HapticNode>>> = LazyLock::new(|| {
+            BhapticsModel::iter()
+                .map(|model| {
+                    let nodes = model
+                        .motor_locations()
+                        .into_iter()
+                        .map(|(loc, i)| {
+                            let v = loc.to_position(i);
                             HapticNode {
                                 x: v.x,
                                 y: v.y,
                                 z: v.z,
-                                groups: vec![NodeGroup::All],
+                                groups: loc.node_groups(i),
                             }
                         })
-                        .collect()
-                });
-                &NODES
-            }
+                        .collect();
+                    (model, nodes)
+                })
+                .collect()
+        });
+        &NODES[self]
+    }
+}
+
+/// Index into the combined x40 list, front then back.
+fn vest_motor(index: usize) -> (PatternLocation, usize) {
+    if index < 20 {
+        (PatternLocation::VestFront, index)
+    } else {
+        (PatternLocation::VestBack, index - 20)
+    }
+}
+
+/// Packs 0-15 values into the 40 nibble vest frame, high nibble first.
+fn encode_nibbles(buf: &mut [u8], feedback: &[f32], nibbles: impl Iterator<Item = usize>) {
+    for (motor, nibble_idx) in nibbles.enumerate() {
+        let val = feedback
+            .get(motor)
+            .map(|v| (v.clamp(0.0, 1.0) * 15.0).round() as u8)
+            .unwrap_or(0);
+        let byte_idx = nibble_idx / 2;
+        if nibble_idx % 2 == 0 {
+            buf[byte_idx] |= val << 4;
+        } else {
+            buf[byte_idx] |= val;
         }
     }
 }
@@ -179,6 +263,7 @@ impl super::Device for BhapticBle {
             id: self.id.clone(),
             nodes: self.model.nodes().to_vec(),
             model: self.model.clone(),
+            connection: ble::connection_state(self.connected_idx),
         })
     }
 
@@ -187,7 +272,7 @@ impl super::Device for BhapticBle {
             return;
         };
 
-        let BhapticInfo { id, nodes, model } = new;
+        let BhapticInfo { id, nodes, model, .. } = new;
 
         return;
     }
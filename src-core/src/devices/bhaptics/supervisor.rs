@@ -0,0 +1,368 @@
+//! Connection bookkeeping for BLE devices.
+//!
+//! Each connected device owns a slot, addressed by index from its `BhapticBle`. Lost devices keep their slot
+//! and are retried with exponential backoff whenever the adapter can see them, slots are reused once released.
+//!
+//! Everything here is generic over `BlePeripheral` so it can run against a mock instead of btleplug.
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use btleplug::api::{BDAddr, Characteristic};
+use parking_lot::Mutex;
+
+use super::ble::BleError;
+use super::BhapticsModel;
+
+/// First retry delay, doubled each failed attempt.
+const BACKOFF_BASE: Duration = Duration::from_secs(1);
+const BACKOFF_MAX: Duration = Duration::from_secs(60);
+
+/// What the supervisor needs from a peripheral.
+pub trait BlePeripheral: Send + Sync + 'static {
+    fn ble_address(&self) -> BDAddr;
+
+    /// Connects, discovers services and returns the motor characteristic.
+    fn connect_motor(&self) -> impl Future<Output = Result<Characteristic, BleError>> + Send;
+
+    fn write_motor(&self, char: &Characteristic, data: &[u8]) -> impl Future<Output = Result<(), BleError>> + Send;
+
+    fn disconnect_motor(&self) -> impl Future<Output = Result<(), BleError>> + Send;
+}
+
+/// Where a BLE device is in its connection lifecycle.
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(tag = "state", content = "value")]
+pub enum BleConnectionState {
+    /// First connection attempt in progress.
+    Connecting,
+    Connected,
+    /// The device was lost, retried whenever it is in range and the backoff has passed.
+    Reconnecting {
+        /// Failed attempts so far.
+        attempt: u32,
+        /// Delay before the next attempt.
+        retry_in_ms: u64,
+    },
+    /// Disconnected on purpose, the slot is free for reuse.
+    #[default]
+    Disconnected,
+}
+
+/// Delay before retrying after `attempt` failures.
+pub fn backoff(attempt: u32) -> Duration {
+    let factor = 1u32.checked_shl(attempt.saturating_sub(1)).unwrap_or(u32::MAX);
+    BACKOFF_BASE.saturating_mul(factor).min(BACKOFF_MAX)
+}
+
+struct Slot<P> {
+    address: BDAddr,
+    model: BhapticsModel,
+    peripheral: Option<Arc<P>>,
+    state: BleConnectionState,
+    attempt: u32,
+    next_retry: Instant,
+    /// Whether a `BhapticBle` has been registered for this slot yet.
+    registered: bool,
+}
+
+/// All BLE connections, indexed by slot.
+pub struct Slots<P> {
+    slots: Mutex<Vec<Slot<P>>>,
+}
+
+impl<P: BlePeripheral> Slots<P> {
+    pub const fn new() -> Self {
+        Slots { slots: Mutex::new(Vec::new()) }
+    }
+
+    /// Takes a free slot for a new device, reusing released ones before growing.
+    pub fn claim(&self, address: BDAddr, model: BhapticsModel) -> usize {
+        let slot = Slot {
+            address,
+            model,
+            peripheral: None,
+            state: BleConnectionState::Connecting,
+            attempt: 0,
+            next_retry: Instant::now(),
+            registered: false,
+        };
+        let mut slots = self.slots.lock();
+        match slots.iter().position(|s| s.state == BleConnectionState::Disconnected) {
+            Some(idx) => {
+                slots[idx] = slot;
+                idx
+            }
+            None => {
+                slots.push(slot);
+                slots.len() - 1
+            }
+        }
+    }
+
+    /// The slot tracking `address`, if it hasn't been released.
+    pub fn find(&self, address: BDAddr) -> Option<usize> {
+        self.slots
+            .lock()
+            .iter()
+            .position(|s| s.address == address && s.state != BleConnectionState::Disconnected)
+    }
+
+    pub fn state(&self, idx: usize) -> BleConnectionState {
+        self.slots.lock().get(idx).map_or(BleConnectionState::Disconnected, |s| s.state.clone())
+    }
+
+    pub fn address(&self, idx: usize) -> Option<BDAddr> {
+        self.slots.lock().get(idx).map(|s| s.address)
+    }
+
+    pub fn model(&self, idx: usize) -> Option<BhapticsModel> {
+        self.slots.lock().get(idx).map(|s| s.model.clone())
+    }
+
+    /// The peripheral to write to, only while connected.
+    pub fn connected(&self, idx: usize) -> Option<Arc<P>> {
+        let slots = self.slots.lock();
+        let slot = slots.get(idx)?;
+        (slot.state == BleConnectionState::Connected).then(|| slot.peripheral.clone()).flatten()
+    }
+
+    /// Whether a connection attempt for this slot is allowed now.
+    pub fn is_due(&self, idx: usize, now: Instant) -> bool {
+        self.slots.lock().get(idx).is_some_and(|s| match s.state {
+            BleConnectionState::Connecting => true,
+            BleConnectionState::Reconnecting { .. } => now >= s.next_retry,
+            _ => false,
+        })
+    }
+
+    /// Records a successful connection. Returns true the first time, when the device still needs registering.
+    pub fn connected_with(&self, idx: usize, peripheral: Arc<P>) -> bool {
+        let mut slots = self.slots.lock();
+        let Some(slot) = slots.get_mut(idx) else {
+            return false;
+        };
+        slot.peripheral = Some(peripheral);
+        slot.state = BleConnectionState::Connected;
+        slot.attempt = 0;
+        !std::mem::replace(&mut slot.registered, true)
+    }
+
+    /// Records a lost connection or failed attempt and schedules the next retry.
+    pub fn lost(&self, idx: usize, now: Instant) {
+        let mut slots = self.slots.lock();
+        let Some(slot) = slots.get_mut(idx) else {
+            return;
+        };
+        if slot.state == BleConnectionState::Disconnected {
+            return;
+        }
+        slot.attempt = slot.attempt.saturating_add(1);
+        let delay = backoff(slot.attempt);
+        slot.next_retry = now + delay;
+        slot.state = BleConnectionState::Reconnecting {
+            attempt: slot.attempt,
+            retry_in_ms: delay.as_millis() as u64,
+        };
+    }
+
+    /// Frees the slot, returning the peripheral so the caller can disconnect it.
+    pub fn release(&self, idx: usize) -> Option<Arc<P>> {
+        let mut slots = self.slots.lock();
+        let slot = slots.get_mut(idx)?;
+        slot.state = BleConnectionState::Disconnected;
+        slot.registered = false;
+        slot.peripheral.take()
+    }
+
+    /// Writes to the device in `idx`. A failed write marks it lost, returning the error.
+    pub async fn send(&self, idx: usize, data: &[u8], char: &Characteristic) -> Result<(), BleError> {
+        let peripheral = self.connected(idx).ok_or(BleError::DeviceNotAvailable)?;
+        let res = peripheral.write_motor(char, data).await;
+        if res.is_err() {
+            self.lost(idx, Instant::now());
+        }
+        res
+    }
+
+    /// Attempts to (re)connect slot `idx` through `peripheral`.
+    ///
+    /// Returns the motor characteristic and whether the device still needs registering.
+    pub async fn try_connect(&self, idx: usize, peripheral: Arc<P>) -> Result<(Characteristic, bool), BleError> {
+        match peripheral.connect_motor().await {
+            Ok(char) => Ok((char, self.connected_with(idx, peripheral))),
+            Err(e) => {
+                self.lost(idx, Instant::now());
+                Err(e)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    use btleplug::api::CharPropFlags;
+    use uuid::Uuid;
+
+    use super::*;
+
+    /// Stands in for a btleplug peripheral, failing on demand.
+    struct FakePeripheral {
+        address: BDAddr,
+        fail: AtomicBool,
+        connects: AtomicUsize,
+    }
+
+    impl FakePeripheral {
+        fn new(last: u8) -> Arc<Self> {
+            Arc::new(FakePeripheral {
+                address: BDAddr::from([0, 0, 0, 0, 0, last]),
+                fail: AtomicBool::new(false),
+                connects: AtomicUsize::new(0),
+            })
+        }
+
+        fn result(&self) -> Result<(), BleError> {
+            if self.fail.load(Ordering::Relaxed) {
+                Err(BleError::DeviceNotAvailable)
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    fn motor_char() -> Characteristic {
+        Characteristic {
+            uuid: Uuid::nil(),
+            service_uuid: Uuid::nil(),
+            properties: CharPropFlags::WRITE_WITHOUT_RESPONSE,
+            descriptors: BTreeSet::new(),
+        }
+    }
+
+    impl BlePeripheral for FakePeripheral {
+        fn ble_address(&self) -> BDAddr {
+            self.address
+        }
+
+        async fn connect_motor(&self) -> Result<Characteristic, BleError> {
+            self.connects.fetch_add(1, Ordering::Relaxed);
+            self.result().map(|_| motor_char())
+        }
+
+        async fn write_motor(&self, _char: &Characteristic, _data: &[u8]) -> Result<(), BleError> {
+            self.result()
+        }
+
+        async fn disconnect_motor(&self) -> Result<(), BleError> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        assert_eq!(backoff(0), BACKOFF_BASE);
+        assert_eq!(backoff(1), Duration::from_secs(1));
+        assert_eq!(backoff(2), Duration::from_secs(2));
+        assert_eq!(backoff(4), Duration::from_secs(8));
+        assert_eq!(backoff(7), BACKOFF_MAX);
+        assert_eq!(backoff(u32::MAX), BACKOFF_MAX);
+    }
+
+    #[test]
+    fn released_slots_are_reused() {
+        let slots = Slots::<FakePeripheral>::new();
+        let first = FakePeripheral::new(1);
+        let a = slots.claim(first.ble_address(), BhapticsModel::TactosyArmL);
+        let b = slots.claim(FakePeripheral::new(2).ble_address(), BhapticsModel::TactosyArmR);
+        assert_eq!((a, b), (0, 1));
+        slots.connected_with(a, first.clone());
+
+        let released = slots.release(a).expect("a connected slot hands back its peripheral");
+        assert!(Arc::ptr_eq(&released, &first));
+        assert_eq!(slots.state(a), BleConnectionState::Disconnected);
+        assert_eq!(slots.find(first.ble_address()), None);
+
+        let third = FakePeripheral::new(3);
+        assert_eq!(slots.claim(third.ble_address(), BhapticsModel::Tactal), a);
+        assert_eq!(slots.find(third.ble_address()), Some(a));
+        assert_eq!(slots.model(a), Some(BhapticsModel::Tactal));
+        // the other device keeps its slot, new ones grow the list once nothing is free.
+        assert_eq!(slots.claim(FakePeripheral::new(4).ble_address(), BhapticsModel::Tactal), 2);
+    }
+
+    #[test]
+    fn lost_devices_wait_out_their_backoff() {
+        let slots = Slots::<FakePeripheral>::new();
+        let now = Instant::now();
+        let idx = slots.claim(FakePeripheral::new(1).ble_address(), BhapticsModel::TactosyArmL);
+        assert!(slots.is_due(idx, now), "the first attempt shouldn't wait");
+
+        slots.lost(idx, now);
+        assert_eq!(
+            slots.state(idx),
+            BleConnectionState::Reconnecting { attempt: 1, retry_in_ms: 1000 }
+        );
+        assert!(!slots.is_due(idx, now));
+        assert!(!slots.is_due(idx, now + Duration::from_millis(999)));
+        assert!(slots.is_due(idx, now + Duration::from_secs(1)));
+
+        let later = now + Duration::from_secs(1);
+        slots.lost(idx, later);
+        assert_eq!(
+            slots.state(idx),
+            BleConnectionState::Reconnecting { attempt: 2, retry_in_ms: 2000 }
+        );
+        assert!(!slots.is_due(idx, later + Duration::from_millis(1999)));
+        assert!(slots.is_due(idx, later + Duration::from_secs(2)));
+
+        // released slots are never retried, and losing them again doesn't revive them.
+        slots.release(idx);
+        slots.lost(idx, later);
+        assert_eq!(slots.state(idx), BleConnectionState::Disconnected);
+        assert!(!slots.is_due(idx, later + BACKOFF_MAX));
+    }
+
+    #[tokio::test]
+    async fn reconnecting_does_not_register_twice() {
+        let slots = Slots::<FakePeripheral>::new();
+        let peripheral = FakePeripheral::new(1);
+        let idx = slots.claim(peripheral.ble_address(), BhapticsModel::TactosyArmL);
+
+        let (_, register) = slots.try_connect(idx, peripheral.clone()).await.unwrap();
+        assert!(register, "the first connection should register the device");
+        assert!(slots.connected(idx).is_some());
+        slots.send(idx, &[0], &motor_char()).await.unwrap();
+
+        // a failed write drops the device into reconnecting, and frames aren't sent until it's back.
+        peripheral.fail.store(true, Ordering::Relaxed);
+        assert!(slots.send(idx, &[0], &motor_char()).await.is_err());
+        assert!(matches!(slots.state(idx), BleConnectionState::Reconnecting { attempt: 1, .. }));
+        assert!(slots.connected(idx).is_none());
+        assert!(matches!(
+            slots.send(idx, &[0], &motor_char()).await,
+            Err(BleError::DeviceNotAvailable)
+        ));
+
+        // failed attempts keep backing off.
+        assert!(slots.try_connect(idx, peripheral.clone()).await.is_err());
+        assert!(matches!(slots.state(idx), BleConnectionState::Reconnecting { attempt: 2, .. }));
+
+        peripheral.fail.store(false, Ordering::Relaxed);
+        let (_, register) = slots.try_connect(idx, peripheral.clone()).await.unwrap();
+        assert!(!register, "a reconnected device is already registered");
+        assert_eq!(slots.state(idx), BleConnectionState::Connected);
+        assert_eq!(peripheral.connects.load(Ordering::Relaxed), 3);
+
+        // once released the slot belongs to a new device, which registers again.
+        slots.release(idx);
+        let next = FakePeripheral::new(2);
+        let idx = slots.claim(next.ble_address(), BhapticsModel::TactosyArmR);
+        let (_, register) = slots.try_connect(idx, next).await.unwrap();
+        assert!(register);
+    }
+}
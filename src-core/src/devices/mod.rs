@@ -3,24 +3,30 @@ pub mod serial;
 //mod traits;
 pub mod bhaptics;
 pub mod update;
+pub mod virtual_device;
 pub mod wifi;
 //pub mod device;
 
 use dashmap::DashMap;
 use enum_dispatch::enum_dispatch;
+use glam::Vec3;
 use parking_lot::{Mutex, RwLock};
 use std::ops::Deref;
 use std::sync::Arc;
 use tokio::sync::mpsc;
 use tokio_util::sync::CancellationToken;
 
+use virtual_device::{VirtualDevice, VirtualDeviceInfo};
 use wifi::{WifiDevice, WifiDeviceInfo};
 
 use crate::{
     devices::{bhaptics::{BhapticBle, BhapticInfo}, wifi::start_wifi_devices},
-    mapping::haptic_node::HapticNode,
+    mapping::{haptic_node::HapticNode, safety::SafetyChange},
 };
 
+/// How close a position has to be to a node to pick it in `DeviceInfo::swap_nodes`.
+const NODE_EPSILON: f32 = 0.001;
+
 pub type EditCallback<T> = dyn FnOnce(&HapticDevice) -> T;
 
 #[enum_dispatch]
@@ -33,6 +39,8 @@ pub type EditCallback<T> = dyn FnOnce(&HapticDevice) -> T;
 pub enum HapticDevice {
     Wifi(WifiDevice),
     BhapticBle(BhapticBle),
+    /// In-memory device that records its output, for tests and development without hardware.
+    Virtual(VirtualDevice),
 }
 
 /// Info container for each device type
@@ -44,7 +52,8 @@ pub enum HapticDevice {
 #[serde(tag = "variant", content = "value")]
 pub enum DeviceInfo {
     Wifi(WifiDeviceInfo),
-    BhapticBle(BhapticInfo)
+    BhapticBle(BhapticInfo),
+    Virtual(VirtualDeviceInfo),
 }
 
 impl DeviceInfo {
@@ -55,6 +64,9 @@ impl DeviceInfo {
             },
             DeviceInfo::BhapticBle(inf) => {
                 return &inf.nodes;
+            },
+            DeviceInfo::Virtual(inf) => {
+                return &inf.nodes;
             }
         }
     }
@@ -67,7 +79,40 @@ impl DeviceInfo {
             },
             DeviceInfo::BhapticBle(ref mut inf) => {
                 inf.nodes = new;
+            },
+            DeviceInfo::Virtual(ref mut inf) => {
+                inf.nodes = new;
+            }
+        }
+    }
+
+    /// Swaps the indices of the nodes sitting at `pos1` and `pos2`. Like `set_nodes` this only edits the info.
+    pub fn swap_nodes(&mut self, pos1: Vec3, pos2: Vec3) -> Result<(), String> {
+        let mut nodes = self.get_nodes().to_owned();
+
+        let mut index1: Option<usize> = None;
+        let mut index2: Option<usize> = None;
+
+        for (index, node) in nodes.iter().enumerate() {
+            if node.to_vec3().abs_diff_eq(pos1, NODE_EPSILON) {
+                index1 = Some(index);
+                log::debug!("Found node 1 at index: {}", index);
+            } else if node.to_vec3().abs_diff_eq(pos2, NODE_EPSILON) {
+                index2 = Some(index);
+                log::debug!("Found node 2 at index: {}", index);
+            }
+        }
+
+        match (index1, index2) {
+            (Some(i1), Some(i2)) => {
+                nodes.swap(i1, i2);
+                self.set_nodes(nodes);
+                Ok(())
             }
+            _ => Err(format!(
+                "Could not find both nodes at {:?} and {:?}",
+                pos1, pos2
+            )),
         }
     }
 
@@ -76,7 +121,7 @@ impl DeviceInfo {
             DeviceInfo::Wifi(wif) => {
                 wif.esp_model.clone()
             },
-            DeviceInfo::BhapticBle(_) => ESP32Model::Unknown,
+            DeviceInfo::BhapticBle(_) | DeviceInfo::Virtual(_) => ESP32Model::Unknown,
         }
     }
 }
@@ -110,9 +155,12 @@ pub enum DeviceMessage {
     /// Marks the device info for this ID as dirty, will update all subscribers.
     InfoDirty(DeviceId),
     Register(HapticDevice),
+    /// The output safety limiter engaged or released on one of this devices motors.
+    SafetyLimit(DeviceId, SafetyChange),
 }
 
 /// Events that will be passed to subscribers.
+#[derive(serde::Serialize, Debug, Clone)]
 pub enum DeviceOutEvents {
     /// New device was added to list, most likely info not available.
     NewDevice(DeviceId),
@@ -120,6 +168,8 @@ pub enum DeviceOutEvents {
     RemovedDevice(DeviceId),
     /// Info for a device has changed
     DeviceInfoDirty(DeviceId),
+    /// The safety limiter engaged or released on a motor of this device.
+    SafetyLimit(DeviceId, SafetyChange),
 }
 
 #[derive(Debug)]
@@ -128,6 +178,7 @@ pub struct DeviceHandle {
     devices: Arc<DashMap<DeviceId, HapticDevice>>,
     subscribers: Arc<Mutex<Vec<mpsc::Sender<DeviceOutEvents>>>>,
     device_sender: mpsc::Sender<DeviceMessage>,
+    shutdown: CancellationToken,
 }
 
 impl Clone for DeviceHandle {
@@ -136,6 +187,7 @@ impl Clone for DeviceHandle {
             devices: Arc::clone(&self.devices),
             subscribers: Arc::clone(&self.subscribers),
             device_sender: self.device_sender.clone(),
+            shutdown: self.shutdown.clone(),
         }
     }
 }
@@ -186,6 +238,29 @@ impl DeviceHandle {
     {
         self.devices.get_mut(id).map(|mut d| fun(&mut d))
     }
+
+    /// Stops the manager loop and disconnects every device.
+    ///
+    /// Same as `DeviceManager::shutdown`, for callers that only hold a handle.
+    pub async fn shutdown(&self) {
+        shutdown_devices(&self.shutdown, &self.devices, &self.subscribers);
+    }
+}
+
+fn shutdown_devices(
+    token: &CancellationToken,
+    devices: &DashMap<DeviceId, HapticDevice>,
+    subscribers: &Mutex<Vec<mpsc::Sender<DeviceOutEvents>>>,
+) {
+    // take the devices out first, disconnecting can reach back into the map.
+    let ids: Vec<DeviceId> = devices.iter().map(|entry| entry.key().clone()).collect();
+    let removed: Vec<(DeviceId, HapticDevice)> = ids.iter().filter_map(|id| devices.remove(id)).collect();
+    for (id, mut device) in removed {
+        device.disconnect();
+        // the manager loop is stopped below and won't see the Remove the device queued, tell subscribers here.
+        handle_device_message(DeviceMessage::Remove(id), devices, subscribers);
+    }
+    token.cancel();
 }
 
 /// A thin, thread safe abstraction layer over physical devices,
@@ -219,16 +294,16 @@ impl DeviceManager {
     }
 
     pub fn get_handle(&self) -> DeviceHandle {
-        DeviceHandle { devices: Arc::clone(&self.devices), subscribers: Arc::clone(&self.subscribers), device_sender: self.device_sender.clone() }
+        DeviceHandle {
+            devices: Arc::clone(&self.devices),
+            subscribers: Arc::clone(&self.subscribers),
+            device_sender: self.device_sender.clone(),
+            shutdown: self.shutdown.clone(),
+        }
     }
 
     pub async fn shutdown(&self) {
-        self.shutdown.cancel();
-        // TODO: Figure out why this does what it doess
-        let _ = self.devices.iter_mut().map(|mut pair| {
-            let this = pair.value_mut();
-            this.disconnect()
-        });
+        shutdown_devices(&self.shutdown, &self.devices, &self.subscribers);
     }
 }
 
@@ -269,11 +344,13 @@ fn handle_device_message(
     map: &DashMap<DeviceId, HapticDevice>,
     subscribers: &Mutex<Vec<mpsc::Sender<DeviceOutEvents>>>,
 ) {
-    let lock = subscribers.lock();
+    let mut lock = subscribers.lock();
+    // drop subscribers that have gone away.
+    lock.retain(|sub| !sub.is_closed());
 
     match event {
         DeviceMessage::Remove(id) => {
-            log::trace!("removing wifi device: {:?}", id);
+            log::trace!("removing device: {:?}", id);
             map.remove(&id);
             for sub in lock.iter() {
                 let _ = sub.try_send(DeviceOutEvents::RemovedDevice(id.clone()));
@@ -291,6 +368,11 @@ fn handle_device_message(
                 let _ = sub.try_send(DeviceOutEvents::DeviceInfoDirty(id.clone()));
             }
         }
+        DeviceMessage::SafetyLimit(id, change) => {
+            for sub in lock.iter() {
+                let _ = sub.try_send(DeviceOutEvents::SafetyLimit(id.clone(), change));
+            }
+        }
     };
 }
 
@@ -9,7 +9,7 @@ use std::{
 pub fn is_updateable(dtype: &HapticDevice) -> bool {
     match dtype {
         HapticDevice::Wifi(_) => true,
-        HapticDevice::BhapticBle(_) => false,
+        HapticDevice::BhapticBle(_) | HapticDevice::Virtual(_) => false,
     }
 }
 
@@ -0,0 +1,177 @@
+//! A device that only exists in memory.
+//!
+//! Behaves like any other `HapticDevice` to the rest of the crate, but instead of sending its feedback anywhere
+//! it records every buffer update. Useful for driving the whole map end to end without hardware.
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use parking_lot::{Mutex, RwLock};
+use tokio::sync::mpsc;
+
+use super::{Device, DeviceId, DeviceInfo, DeviceMessage, HapticDevice};
+use crate::{log_err, mapping::haptic_node::HapticNode};
+
+/// Default number of buffer updates kept, older ones are dropped first.
+const DEFAULT_HISTORY: usize = 1024;
+
+/// Info container for virtual devices.
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct VirtualDeviceInfo {
+    pub id: DeviceId,
+    pub name: String,
+    pub nodes: Vec<HapticNode>,
+    /// Whether the device is currently registered with the manager.
+    pub connected: bool,
+}
+
+/// An in-memory haptic device.
+///
+/// Clones share all state, so keep one around after `connect` to inspect the output history.
+#[derive(Debug, Clone)]
+pub struct VirtualDevice {
+    id: DeviceId,
+    name: String,
+    nodes: Arc<RwLock<Vec<HapticNode>>>,
+    buffer: Arc<RwLock<Vec<f32>>>,
+    /// Snapshot of `buffer` at every `buffer_updated`, oldest first.
+    history: Arc<Mutex<VecDeque<Vec<f32>>>>,
+    history_limit: usize,
+    connected: Arc<AtomicBool>,
+    manager: Arc<Mutex<Option<mpsc::Sender<DeviceMessage>>>>,
+}
+
+impl VirtualDevice {
+    /// Creates a disconnected device with one motor per node.
+    pub fn new(id: impl Into<DeviceId>, nodes: Vec<HapticNode>) -> Self {
+        let id = id.into();
+        VirtualDevice {
+            name: id.0.clone(),
+            id,
+            buffer: Arc::new(RwLock::new(vec![0.0; nodes.len()])),
+            nodes: Arc::new(RwLock::new(nodes)),
+            history: Arc::new(Mutex::new(VecDeque::new())),
+            history_limit: DEFAULT_HISTORY,
+            connected: Arc::new(AtomicBool::new(false)),
+            manager: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Sets the user facing name, defaults to the id.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Sets how many buffer updates are kept before the oldest are dropped.
+    pub fn with_history_limit(mut self, limit: usize) -> Self {
+        self.history_limit = limit.max(1);
+        self
+    }
+
+    /// Simulates the device connecting, registering it with the manager behind `tx`.
+    ///
+    /// Use `DeviceHandle::get_device_channel` for `tx`.
+    pub async fn connect(&self, tx: mpsc::Sender<DeviceMessage>) {
+        *self.manager.lock() = Some(tx.clone());
+        self.connected.store(true, Ordering::SeqCst);
+        log_err!(tx.send(DeviceMessage::Register(HapticDevice::Virtual(self.clone()))).await);
+    }
+
+    /// Simulates the device dropping out, the manager removes it like any other lost device.
+    pub async fn drop_connection(&self) {
+        if !self.connected.swap(false, Ordering::SeqCst) {
+            return;
+        }
+        let tx = self.manager.lock().clone();
+        if let Some(tx) = tx {
+            log_err!(tx.send(DeviceMessage::Remove(self.id.clone())).await);
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::SeqCst)
+    }
+
+    /// Replaces the node map, same as a user editing it through `update_info`.
+    pub fn set_nodes(&self, nodes: Vec<HapticNode>) {
+        self.buffer.write().resize(nodes.len(), 0.0);
+        *self.nodes.write() = nodes;
+        self.info_dirty();
+    }
+
+    /// Every recorded buffer update, oldest first.
+    pub fn history(&self) -> Vec<Vec<f32>> {
+        self.history.lock().iter().cloned().collect()
+    }
+
+    /// The most recent buffer update, if there has been one.
+    pub fn last_output(&self) -> Option<Vec<f32>> {
+        self.history.lock().back().cloned()
+    }
+
+    pub fn clear_history(&self) {
+        self.history.lock().clear();
+    }
+
+    fn info_dirty(&self) {
+        if let Some(tx) = self.manager.lock().as_ref() {
+            log_err!(tx.try_send(DeviceMessage::InfoDirty(self.id.clone())));
+        }
+    }
+}
+
+impl Device for VirtualDevice {
+    fn get_id(&self) -> DeviceId {
+        self.id.clone()
+    }
+
+    fn info(&self) -> DeviceInfo {
+        DeviceInfo::Virtual(VirtualDeviceInfo {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            nodes: self.nodes.read().clone(),
+            connected: self.is_connected(),
+        })
+    }
+
+    /// Only the nodes can be changed.
+    fn update_info(&self, new: DeviceInfo) {
+        let DeviceInfo::Virtual(new) = new else {
+            log::warn!("Updated with wrong info type on virtual device: {:?}", self.id);
+            return;
+        };
+        self.set_nodes(new.nodes);
+    }
+
+    fn get_feedback_buffer(&self) -> Arc<RwLock<Vec<f32>>> {
+        Arc::clone(&self.buffer)
+    }
+
+    /// Records the current buffer, nothing is recorded while disconnected.
+    fn buffer_updated(&self) {
+        if !self.is_connected() {
+            return;
+        }
+        let frame = self.buffer.read().clone();
+        let mut history = self.history.lock();
+        while history.len() >= self.history_limit {
+            history.pop_front();
+        }
+        history.push_back(frame);
+    }
+
+    async fn set_manager_channel(&mut self, tx: mpsc::Sender<DeviceMessage>) {
+        *self.manager.lock() = Some(tx);
+    }
+
+    fn disconnect(&mut self) {
+        if !self.connected.swap(false, Ordering::SeqCst) {
+            return;
+        }
+        if let Some(tx) = self.manager.lock().as_ref() {
+            log_err!(tx.try_send(DeviceMessage::Remove(self.id.clone())));
+        }
+    }
+}
@@ -19,6 +19,10 @@ pub struct WifiConfig {
     pub motor_map_ledc_num: u32,
     pub motor_map_ledc: Vec<u32>,
     pub config_version: u32,
+    /// Motor frame capabilities of the firmware: the binary formats it accepts, see
+    /// `frame::FrameFormat::from_capabilities`, and `frame::HOLD_CAPABILITY`. Missing on older firmware.
+    #[serde(default)]
+    pub frame_formats: Vec<String>,
 }
 
 /// Takes a string and converts it into a Vec<HapticNode>.
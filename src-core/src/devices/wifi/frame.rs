@@ -0,0 +1,215 @@
+//! Motor frames sent to Wi-Fi devices every tick.
+//!
+//! Old firmware takes `/h` with an OSC string of 4 hex digits per motor.
+//! Firmware that lists a binary format in the `frame_formats` of its config takes `/hb` with an OSC blob instead,
+//! little-endian:
+//!
+//! | bytes | field |
+//! |---|---|
+//! | 0 | frame format, currently 1 |
+//! | 1 | bytes per sample, 1 or 2 |
+//! | 2..4 | sequence number, u16, wraps |
+//! | 4..6 | motor count, u16 |
+//! | 6.. | one sample per motor, 0 is off and the max value is full intensity |
+//!
+//! `"hb16"` boards get u16 samples, boards listing only `"hb8"` drive 8 bit PWM and get u8 samples.
+//! `config_version` is the schema of the stored config and says nothing about frames, so it isn't used here.
+use rosc::{encoder, OscMessage, OscPacket, OscType};
+
+pub const HEX_ADDR: &str = "/h";
+pub const BINARY_ADDR: &str = "/hb";
+pub const BINARY_FORMAT: u8 = 1;
+/// `frame_formats` entries for u16 and u8 samples.
+pub const BINARY16_CAPABILITY: &str = "hb16";
+pub const BINARY8_CAPABILITY: &str = "hb8";
+/// Listed in `frame_formats` by firmware that keeps driving its last frame until a new one arrives,
+/// so unchanged frames only need resending now and then.
+pub const HOLD_CAPABILITY: &str = "hold";
+const HEADER_LEN: usize = 6;
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+/// How a device wants its motor frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameFormat {
+    Hex,
+    Binary16,
+    Binary8,
+}
+
+impl FrameFormat {
+    /// Picks the widest format the firmware lists in `frame_formats`, hex when it lists none.
+    pub fn from_capabilities(formats: &[String]) -> Self {
+        if formats.iter().any(|f| f == BINARY16_CAPABILITY) {
+            FrameFormat::Binary16
+        } else if formats.iter().any(|f| f == BINARY8_CAPABILITY) {
+            FrameFormat::Binary8
+        } else {
+            FrameFormat::Hex
+        }
+    }
+}
+
+/// Scales `output` to the wire resolution, motors missing from `output` are off.
+///
+/// Frames are compared at this resolution, so changes too small to be sent don't count.
+pub fn quantize(output: &[f32], count: usize) -> Vec<u16> {
+    (0..count)
+        .map(|i| (output.get(i).copied().unwrap_or(0.0).clamp(0.0, 1.0) * u16::MAX as f32).round() as u16)
+        .collect()
+}
+
+/// Builds the OSC packet for one quantized frame.
+pub fn encode(format: FrameFormat, frame: &[u16], seq: u16) -> Vec<u8> {
+    let message = match format {
+        FrameFormat::Hex => OscMessage {
+            addr: HEX_ADDR.to_string(),
+            args: vec![OscType::String(encode_hex(frame))],
+        },
+        FrameFormat::Binary16 | FrameFormat::Binary8 => OscMessage {
+            addr: BINARY_ADDR.to_string(),
+            args: vec![OscType::Blob(encode_binary(format, frame, seq))],
+        },
+    };
+    encoder::encode(&OscPacket::Message(message)).expect("Failed to build motor frame")
+}
+
+fn encode_hex(frame: &[u16]) -> String {
+    let mut hex = String::with_capacity(frame.len() * 4);
+    for value in frame {
+        for shift in [12, 8, 4, 0] {
+            hex.push(HEX_DIGITS[((*value >> shift) & 0xf) as usize] as char);
+        }
+    }
+    hex
+}
+
+fn encode_binary(format: FrameFormat, frame: &[u16], seq: u16) -> Vec<u8> {
+    let width = if format == FrameFormat::Binary8 { 1 } else { 2 };
+    let frame = &frame[..frame.len().min(u16::MAX as usize)];
+    let mut buf = Vec::with_capacity(HEADER_LEN + frame.len() * width);
+    buf.push(BINARY_FORMAT);
+    buf.push(width as u8);
+    buf.extend_from_slice(&seq.to_le_bytes());
+    buf.extend_from_slice(&(frame.len() as u16).to_le_bytes());
+    for value in frame {
+        if width == 1 {
+            // rounded to the nearest of 0-255
+            buf.push(((*value as u32 * u8::MAX as u32 + u16::MAX as u32 / 2) / u16::MAX as u32) as u8);
+        } else {
+            buf.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    buf
+}
+
+/// Reads a `/hb` blob back into its sequence number and 0-1 samples.
+pub fn decode_binary(blob: &[u8]) -> Option<(u16, Vec<f32>)> {
+    if blob.len() < HEADER_LEN || blob[0] != BINARY_FORMAT {
+        return None;
+    }
+    let width = blob[1] as usize;
+    let seq = u16::from_le_bytes([blob[2], blob[3]]);
+    let count = u16::from_le_bytes([blob[4], blob[5]]) as usize;
+    let body = &blob[HEADER_LEN..];
+    if body.len() != count * width {
+        return None;
+    }
+    let samples = match width {
+        1 => body.iter().map(|b| *b as f32 / u8::MAX as f32).collect(),
+        2 => body
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]) as f32 / u16::MAX as f32)
+            .collect(),
+        _ => return None,
+    };
+    Some((seq, samples))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn caps(formats: &[&str]) -> Vec<String> {
+        formats.iter().map(|f| f.to_string()).collect()
+    }
+
+    /// Unwraps the OSC message `encode` built.
+    fn message(bytes: &[u8]) -> OscMessage {
+        match rosc::decoder::decode_udp(bytes) {
+            Ok((_, OscPacket::Message(msg))) => msg,
+            other => panic!("encode built {other:?}"),
+        }
+    }
+
+    fn blob(bytes: &[u8]) -> Vec<u8> {
+        let msg = message(bytes);
+        assert_eq!(msg.addr, BINARY_ADDR);
+        match msg.args.as_slice() {
+            [OscType::Blob(blob)] => blob.clone(),
+            args => panic!("binary frame carried {args:?}"),
+        }
+    }
+
+    #[test]
+    fn format_comes_from_the_listed_capabilities() {
+        assert_eq!(FrameFormat::from_capabilities(&[]), FrameFormat::Hex);
+        assert_eq!(FrameFormat::from_capabilities(&caps(&["h64"])), FrameFormat::Hex);
+        assert_eq!(FrameFormat::from_capabilities(&caps(&["hb8"])), FrameFormat::Binary8);
+        assert_eq!(FrameFormat::from_capabilities(&caps(&["hb8", "hb16"])), FrameFormat::Binary16);
+    }
+
+    #[test]
+    fn hex_frames_carry_four_digits_per_motor() {
+        let msg = message(&encode(FrameFormat::Hex, &[0, 0xabcd, u16::MAX], 7));
+        assert_eq!(msg.addr, HEX_ADDR);
+        assert_eq!(msg.args, vec![OscType::String("0000abcdffff".to_string())]);
+    }
+
+    #[test]
+    fn binary16_round_trips() {
+        let output = [0.0, 0.25, 0.5, 1.0];
+        let frame = quantize(&output, 5);
+        let (seq, samples) = decode_binary(&blob(&encode(FrameFormat::Binary16, &frame, 513))).unwrap();
+        assert_eq!(seq, 513);
+        assert_eq!(samples.len(), 5, "motors missing from the output are sent as off");
+        for (sample, expected) in samples.iter().zip(output.iter().chain([&0.0])) {
+            assert!((sample - expected).abs() <= 1.0 / u16::MAX as f32, "{sample} != {expected}");
+        }
+    }
+
+    #[test]
+    fn binary8_round_trips() {
+        let output = [0.0, 0.1, 0.5, 0.9, 1.0];
+        let frame = quantize(&output, output.len());
+        let (seq, samples) = decode_binary(&blob(&encode(FrameFormat::Binary8, &frame, u16::MAX))).unwrap();
+        assert_eq!(seq, u16::MAX);
+        for (sample, expected) in samples.iter().zip(output) {
+            assert!((sample - expected).abs() <= 0.5 / u8::MAX as f32, "{sample} != {expected}");
+        }
+    }
+
+    #[test]
+    fn binary8_rounds_to_the_nearest_step() {
+        // one u8 step is 257 u16 steps, so the halfway point sits between 128 and 129.
+        let buf = encode_binary(FrameFormat::Binary8, &[0, 128, 129, 385, 386, u16::MAX], 0);
+        assert_eq!(&buf[..HEADER_LEN], &[BINARY_FORMAT, 1, 0, 0, 6, 0]);
+        assert_eq!(&buf[HEADER_LEN..], &[0, 0, 1, 1, 2, u8::MAX]);
+    }
+
+    #[test]
+    fn malformed_blobs_are_rejected() {
+        let good = encode_binary(FrameFormat::Binary16, &[1, 2], 3);
+        assert!(decode_binary(&good).is_some());
+        assert!(decode_binary(&good[..HEADER_LEN - 1]).is_none());
+        assert!(decode_binary(&good[..good.len() - 1]).is_none());
+
+        let mut wrong_format = good.clone();
+        wrong_format[0] = BINARY_FORMAT + 1;
+        assert!(decode_binary(&wrong_format).is_none());
+
+        let mut wrong_width = good;
+        wrong_width[1] = 4;
+        wrong_width[4] = 1;
+        assert!(decode_binary(&wrong_width).is_none());
+    }
+}
@@ -7,7 +7,8 @@ use std::{
     sync::Arc,
     time::{Duration, Instant},
 };
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, Notify};
+use tokio::time::MissedTickBehavior;
 use tokio_util::sync::CancellationToken;
 
 use crate::{devices::{
@@ -15,12 +16,15 @@ use crate::{devices::{
 }, log_err, state::{self, PerDevice}};
 use crate::mapping::haptic_node::HapticNode;
 use crate::util::next_free_port;
+use frame::FrameFormat;
 use udp::{broadcast::start_listen_broadcast, send_udp};
 
 mod config;
 mod connection_manager;
+mod frame;
 mod udp;
 pub(crate) mod ota;
+pub mod simulator;
 
 pub async fn start_wifi_devices(manager: &mut DeviceHandle) {
     log::trace!("Starting wifi devices");
@@ -37,6 +41,8 @@ pub struct WifiDevice {
     manager: mpsc::Sender<DeviceMessage>,
     live_state: Arc<Mutex<WifiDeviceState>>,
     connection: WifiConnManager,
+    /// Wakes the tick task early when the map has new output.
+    wake: Arc<Notify>,
 }
 
 #[derive(Debug)]
@@ -51,6 +57,13 @@ pub struct WifiDeviceState {
     config: Option<WifiConfig>,
     last_heartbeat: Instant,
     been_platform_query: bool,
+    /// Sequence number of the next binary motor frame.
+    frame_seq: u16,
+    /// Last frame sent, unchanged frames are only resent as keep-alives.
+    last_frame: Vec<u16>,
+    last_sent: Option<Instant>,
+    /// Times `last_frame` has been resent unchanged.
+    resends: u32,
 }
 
 impl Default for WifiDeviceState {
@@ -66,10 +79,59 @@ impl Default for WifiDeviceState {
             config: None,
             last_heartbeat: Instant::now(),
             been_platform_query: false,
+            frame_seq: 0,
+            last_frame: vec![],
+            last_sent: None,
+            resends: 0,
         }
     }
 }
 
+/// How often motor frames go out to a Wi-Fi device, stored per device in `PerDevice`.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct WifiSendRate {
+    /// Device tick period, and the fastest changed frames are sent.
+    pub tick: Duration,
+    /// Unchanged frames are resent this often so the firmware knows we're still here.
+    ///
+    /// `None` picks for the firmware. Firmware listing `frame::HOLD_CAPABILITY` keeps driving its last frame,
+    /// so it gets `HOLD_KEEP_ALIVE`. Other firmware times out its motors between frames, so it gets every tick.
+    pub keep_alive: Option<Duration>,
+}
+
+impl Default for WifiSendRate {
+    fn default() -> Self {
+        Self {
+            tick: Duration::from_millis(20),
+            keep_alive: None,
+        }
+    }
+}
+
+/// Shortest tick allowed, anything quicker just floods the network.
+const MIN_TICK: Duration = Duration::from_millis(5);
+const SEND_SLACK: Duration = Duration::from_millis(2);
+/// Default keep-alive for firmware that holds its last frame.
+const HOLD_KEEP_ALIVE: Duration = Duration::from_secs(1);
+/// Silent frames resent before the device is left alone until the output changes.
+const SILENT_RESENDS: u32 = 3;
+
+impl WifiSendRate {
+    fn from_settings(settings: &PerDevice) -> Self {
+        let mut rate = settings.wifi_rate.clone();
+        rate.tick = rate.tick.max(MIN_TICK);
+        rate
+    }
+
+    /// How often unchanged frames are resent to firmware that does, or doesn't, hold its last frame.
+    fn keep_alive_for(&self, holds: bool) -> Duration {
+        let keep_alive = self.keep_alive.unwrap_or(if holds { HOLD_KEEP_ALIVE } else { self.tick });
+        // a keep-alive under the tick would resend on every map wake and skip the cap.
+        keep_alive.max(self.tick)
+    }
+}
+
 #[cfg_attr(feature = "specta", derive(specta::Type))]
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 pub struct WifiDeviceInfo {
@@ -134,6 +196,8 @@ impl WifiDevice {
                                 {
                                 let mut lock = state_clone.lock();
                                 lock.config = None;
+                                // fetch the new config on the next tick
+                                lock.been_query = None;
                                 }
                                 let _ = tx_clone.send(DeviceMessage::InfoDirty(id_clone.clone())).await;
                             },
@@ -160,11 +224,14 @@ impl WifiDevice {
         });
 
         let addr = SocketAddr::V4(SocketAddrV4::new(ip, port));
+        let wake = Arc::new(Notify::new());
         start_tick(
             is_alive.clone(),
             addr.clone(),
             Arc::clone(&state),
             recv_port,
+            DeviceId(mac.clone()),
+            Arc::clone(&wake),
         )
         .await;
 
@@ -176,6 +243,7 @@ impl WifiDevice {
             manager: tx,
             cancel: is_alive,
             connection: con,
+            wake,
         })
     }
 
@@ -203,25 +271,44 @@ async fn start_tick(
     addr: SocketAddr,
     state: Arc<Mutex<WifiDeviceState>>,
     recieve_port: u16,
+    id: DeviceId,
+    wake: Arc<Notify>,
 ) {
     tokio::task::spawn(async move {
-        let period = Duration::from_millis(20);
-        let mut interval = tokio::time::interval(period);
+        // looked up once, the entry is updated in place when the settings change.
+        let (_, settings) = state::get_device(&id);
+        let mut settings = Cache::new(settings);
+        let mut seen = Arc::clone(settings.load());
+        let mut rate = WifiSendRate::from_settings(&seen);
+        let mut interval = tokio::time::interval(rate.tick);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
         loop {
             tokio::select! {
-                _ = interval.tick() => {
-                    let start = Instant::now();
-                    tick(&addr, &state, recieve_port, cancel.clone()).await;
-                    let elapsed = start.elapsed();
-                    if elapsed > period {
-                        log::warn!("Device tick overran: {:?} (limit {:?})", elapsed, period);
-                    }
-                }
-
+                _ = interval.tick() => {}
+                // new output from the map, changed frames go out without waiting for the tick.
+                _ = wake.notified() => {}
                 _ = cancel.cancelled() => {
                     break;
                 }
             };
+
+            let current = settings.load();
+            if !Arc::ptr_eq(current, &seen) {
+                let new = WifiSendRate::from_settings(current);
+                if new.tick != rate.tick {
+                    interval = tokio::time::interval(new.tick);
+                    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                }
+                rate = new;
+                seen = Arc::clone(current);
+            }
+
+            let start = Instant::now();
+            tick(&addr, &state, recieve_port, cancel.clone(), &rate).await;
+            let elapsed = start.elapsed();
+            if elapsed > rate.tick {
+                log::warn!("Device tick overran: {:?} (limit {:?})", elapsed, rate.tick);
+            }
         }
     });
 }
@@ -241,6 +328,7 @@ async fn tick(
     state: &Arc<Mutex<WifiDeviceState>>,
     recieve_port: u16,
     cancel: CancellationToken,
+    rate: &WifiSendRate,
 ) {
     let action = {
         let mut state = state.lock();
@@ -313,18 +401,27 @@ async fn tick(
             log::trace!("Query platform: {addr:?}");
             TickAction::QueryPlatform(msg)
         } else if let Some(conf) = &state.config {
-            let mut hex = String::new();
-            for mtr_idx in 0..conf.node_map.len() {
-                let output = state.output.read();
-                let num = output.get(mtr_idx).unwrap_or(&0.0);
-                let scaled = (num.clamp(0.0, 1.0) * 0xffff as f32).round() as u16;
-                hex.push_str(&format!("{:04x}", scaled));
+            let format = FrameFormat::from_capabilities(&conf.frame_formats);
+            let frame = frame::quantize(&state.output.read(), conf.node_map.len());
+            let since_sent = state.last_sent.map_or(Duration::MAX, |t| t.elapsed());
+            let changed = frame != state.last_frame;
+            // slack so timer jitter doesn't push a frame back a whole tick.
+            let cap = rate.tick.saturating_sub(SEND_SLACK);
+            let holds = conf.frame_formats.iter().any(|f| f == frame::HOLD_CAPABILITY);
+            let keep_alive = rate.keep_alive_for(holds).saturating_sub(SEND_SLACK);
+            // once the motors have been told to stop a few times, silence needs no keep-alive.
+            let settled = frame.iter().all(|v| *v == 0) && state.resends >= SILENT_RESENDS;
+            if (changed && since_sent >= cap) || (!changed && !settled && since_sent >= keep_alive) {
+                state.resends = if changed { 0 } else { state.resends + 1 };
+                let seq = state.frame_seq;
+                state.frame_seq = seq.wrapping_add(1);
+                state.last_sent = Some(Instant::now());
+                let bytes = frame::encode(format, &frame, seq);
+                state.last_frame = frame;
+                TickAction::Drive(bytes)
+            } else {
+                TickAction::None
             }
-            let bytes = rosc::encoder::encode(&rosc::OscPacket::Message(rosc::OscMessage {
-                addr: "/h".to_string(),
-                args: vec![OscType::String(hex)],
-            })).unwrap();
-            TickAction::Drive(bytes)
         }else {
             TickAction::None
         }
@@ -430,9 +527,9 @@ impl Device for WifiDevice {
         Arc::clone(&state.output)
     }
 
-    /// Does nothing since device continously updated.
+    /// Wakes the tick so changed output is sent right away, unchanged output is skipped there.
     fn buffer_updated(&self) {
-        
+        self.wake.notify_one();
     }
 
     async fn set_manager_channel(&mut self, tx: mpsc::Sender<DeviceMessage>) {
@@ -0,0 +1,354 @@
+//! Simulated VRCH firmware, speaking the Wi-Fi protocol on localhost.
+//!
+//! Answers the same OSC commands a real board does: `/ping`, `/command "get all"`, `GET PLATFORM`, `SET NODE_MAP`,
+//! and records every `/h` or `/hb` motor frame it is sent. Heartbeats can be paused to exercise timeouts.
+//! Lets `WifiDevice` be driven without an ESP32 on the network.
+use std::collections::VecDeque;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use rosc::{encoder, OscMessage, OscPacket, OscType};
+use tokio::net::UdpSocket;
+use tokio_util::sync::CancellationToken;
+
+use super::frame::{self, BINARY_ADDR, HEX_ADDR};
+use super::udp::broadcast::DISCOVERY_PORT;
+use crate::{log_err, mapping::haptic_node::HapticNode};
+
+/// Motor frames kept before the oldest are dropped.
+const FRAME_HISTORY: usize = 1024;
+
+/// What the simulated board reports about itself.
+#[derive(Debug, Clone)]
+pub struct SimulatorConfig {
+    pub mac: String,
+    pub name: String,
+    /// Sent back for `GET PLATFORM`, without the `PLATFORM ` prefix.
+    pub platform: String,
+    /// Node map stored on the board, one motor per node.
+    pub nodes: Vec<HapticNode>,
+    pub heartbeat: Duration,
+    /// Reported in `get all`.
+    pub config_version: u32,
+    /// Reported in `get all`, listing `"hb16"` or `"hb8"` makes the manager send binary motor frames
+    /// and `"hold"` makes it resend unchanged frames less often.
+    pub frame_formats: Vec<String>,
+    /// Where discovery announcements are sent, the manager listens on the multicast port.
+    pub announce_to: SocketAddr,
+}
+
+impl Default for SimulatorConfig {
+    fn default() -> Self {
+        SimulatorConfig {
+            mac: "SI:MU:LA:TE:D0:01".to_string(),
+            name: "Simulated VRCH".to_string(),
+            platform: "ESP32-S3".to_string(),
+            nodes: vec![],
+            heartbeat: Duration::from_secs(1),
+            config_version: 1,
+            frame_formats: vec![],
+            announce_to: SocketAddr::from((Ipv4Addr::LOCALHOST, DISCOVERY_PORT as u16)),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct SimState {
+    config: SimulatorConfig,
+    /// Where replies go, learned from the `/ping` receive port.
+    host: Option<SocketAddr>,
+    heartbeat_enabled: bool,
+    frames: VecDeque<Vec<f32>>,
+    /// Sequence number of the last binary frame.
+    last_seq: Option<u16>,
+    /// Every `/command` string received, in order.
+    commands: Vec<String>,
+}
+
+impl SimState {
+    fn record(&mut self, frame: Vec<f32>) {
+        while self.frames.len() >= FRAME_HISTORY {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(frame);
+    }
+}
+
+/// A running simulated board, stops when dropped.
+#[derive(Debug)]
+pub struct SimulatedFirmware {
+    addr: SocketAddr,
+    socket: Arc<UdpSocket>,
+    state: Arc<Mutex<SimState>>,
+    cancel: CancellationToken,
+}
+
+impl SimulatedFirmware {
+    /// Binds a localhost port and starts answering the protocol on it.
+    pub async fn start(config: SimulatorConfig) -> std::io::Result<SimulatedFirmware> {
+        let socket = Arc::new(UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await?);
+        let addr = socket.local_addr()?;
+        let heartbeat = config.heartbeat;
+        let state = Arc::new(Mutex::new(SimState {
+            config,
+            host: None,
+            heartbeat_enabled: true,
+            frames: VecDeque::new(),
+            last_seq: None,
+            commands: vec![],
+        }));
+        let cancel = CancellationToken::new();
+
+        tokio::spawn(run(Arc::clone(&socket), Arc::clone(&state), cancel.clone(), heartbeat));
+
+        Ok(SimulatedFirmware { addr, socket, state, cancel })
+    }
+
+    /// The address the board listens on, what it announces as `ip` and `port`.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn mac(&self) -> String {
+        self.state.lock().config.mac.clone()
+    }
+
+    /// Sends the discovery JSON the manager uses to find new boards.
+    pub async fn announce(&self) -> std::io::Result<()> {
+        let (json, target) = {
+            let state = self.state.lock();
+            let json = serde_json::json!({
+                "mac": state.config.mac,
+                "ip": self.addr.ip().to_string(),
+                "name": state.config.name,
+                "port": self.addr.port(),
+            });
+            (json.to_string(), state.config.announce_to)
+        };
+        self.socket.send_to(json.as_bytes(), target).await?;
+        Ok(())
+    }
+
+    /// Whether the manager has pinged us yet.
+    pub fn pinged(&self) -> bool {
+        self.state.lock().host.is_some()
+    }
+
+    /// The node map currently stored on the board.
+    pub fn nodes(&self) -> Vec<HapticNode> {
+        self.state.lock().config.nodes.clone()
+    }
+
+    /// Every motor frame received, oldest first, scaled back to 0-1.
+    pub fn frames(&self) -> Vec<Vec<f32>> {
+        self.state.lock().frames.iter().cloned().collect()
+    }
+
+    pub fn last_frame(&self) -> Option<Vec<f32>> {
+        self.state.lock().frames.back().cloned()
+    }
+
+    /// Sequence number of the last binary frame, None while the manager sends hex.
+    pub fn last_seq(&self) -> Option<u16> {
+        self.state.lock().last_seq
+    }
+
+    pub fn clear_frames(&self) {
+        self.state.lock().frames.clear();
+    }
+
+    /// Every `/command` received, in order.
+    pub fn commands(&self) -> Vec<String> {
+        self.state.lock().commands.clone()
+    }
+
+    /// Stops or resumes heartbeats, stopping them long enough makes the manager drop the board.
+    pub fn set_heartbeat(&self, enabled: bool) {
+        self.state.lock().heartbeat_enabled = enabled;
+    }
+
+    /// Sends a `/log` line like the firmware does on errors.
+    pub async fn send_log(&self, line: &str) -> std::io::Result<()> {
+        let host = self.state.lock().host;
+        if let Some(host) = host {
+            send(&self.socket, host, "/log", vec![OscType::String(line.to_string())]).await?;
+        }
+        Ok(())
+    }
+
+    /// Powers the board off, it stops answering and sending heartbeats.
+    pub fn stop(&self) {
+        self.cancel.cancel();
+    }
+}
+
+impl Drop for SimulatedFirmware {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+    }
+}
+
+async fn run(socket: Arc<UdpSocket>, state: Arc<Mutex<SimState>>, cancel: CancellationToken, heartbeat: Duration) {
+    let mut buf = [0u8; rosc::decoder::MTU];
+    let mut beat = tokio::time::interval(heartbeat);
+    loop {
+        tokio::select! {
+            res = socket.recv_from(&mut buf) => {
+                let (size, src) = match res {
+                    Ok(r) => r,
+                    Err(e) => {
+                        log::warn!("Simulated firmware receive error: {:?}", e);
+                        continue;
+                    }
+                };
+                let Ok((_, OscPacket::Message(msg))) = rosc::decoder::decode_udp(&buf[..size]) else {
+                    log::warn!("Simulated firmware received a non OSC message packet from {src}");
+                    continue;
+                };
+                for (addr, args) in handle_message(&state, msg, src) {
+                    let host = state.lock().host;
+                    if let Some(host) = host {
+                        log_err!(send(&socket, host, addr, args).await);
+                    }
+                }
+            }
+
+            _ = beat.tick() => {
+                let host = {
+                    let state = state.lock();
+                    state.host.filter(|_| state.heartbeat_enabled)
+                };
+                if let Some(host) = host {
+                    log_err!(send(&socket, host, "/hrtbt", vec![]).await);
+                }
+            }
+
+            _ = cancel.cancelled() => break,
+        }
+    }
+}
+
+/// Applies one message to the board, returning the replies to send.
+fn handle_message(state: &Mutex<SimState>, msg: OscMessage, src: SocketAddr) -> Vec<(&'static str, Vec<OscType>)> {
+    let mut state = state.lock();
+    match (msg.addr.as_str(), msg.args.first()) {
+        ("/ping", Some(OscType::Int(port))) => {
+            state.host = Some(SocketAddr::new(src.ip(), *port as u16));
+            vec![("/ping", vec![])]
+        }
+        (HEX_ADDR, Some(OscType::String(hex))) => {
+            match decode_frame(hex) {
+                Some(frame) => state.record(frame),
+                None => log::warn!("Simulated firmware received a malformed motor frame: {hex}"),
+            }
+            vec![]
+        }
+        (BINARY_ADDR, Some(OscType::Blob(blob))) => {
+            match frame::decode_binary(blob) {
+                Some((seq, frame)) => {
+                    state.last_seq = Some(seq);
+                    state.record(frame);
+                }
+                None => log::warn!("Simulated firmware received a malformed binary motor frame: {blob:?}"),
+            }
+            vec![]
+        }
+        ("/command", Some(OscType::String(cmd))) => {
+            state.commands.push(cmd.clone());
+            let reply = match cmd.as_str() {
+                "get all" => config_json(&state.config).to_string(),
+                "GET PLATFORM" => format!("PLATFORM {}", state.config.platform),
+                cmd => match cmd.strip_prefix("SET NODE_MAP ") {
+                    Some(hex) => match decode_nodes(hex) {
+                        Some(nodes) => {
+                            state.config.nodes = nodes;
+                            format!("NODE_MAP set to {hex}")
+                        }
+                        None => format!("Invalid NODE_MAP: {hex}"),
+                    },
+                    None => {
+                        log::debug!("Simulated firmware ignoring unknown command: {cmd}");
+                        return vec![];
+                    }
+                },
+            };
+            vec![("/command", vec![OscType::String(reply)])]
+        }
+        _ => {
+            log::debug!("Simulated firmware ignoring message: {} {:?}", msg.addr, msg.args);
+            vec![]
+        }
+    }
+}
+
+async fn send(socket: &UdpSocket, to: SocketAddr, addr: &str, args: Vec<OscType>) -> std::io::Result<()> {
+    let buf = encoder::encode(&OscPacket::Message(OscMessage { addr: addr.to_string(), args }))
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{:?}", e)))?;
+    socket.send_to(&buf, to).await?;
+    Ok(())
+}
+
+/// The `get all` reply, `node_map` goes over the wire as hex.
+fn config_json(config: &SimulatorConfig) -> serde_json::Value {
+    let motors: Vec<u32> = (0..config.nodes.len() as u32).collect();
+    serde_json::json!({
+        "wifi_ssid": "simulated",
+        "wifi_password": "",
+        "mdns_name": config.name,
+        "node_map": encode_nodes(&config.nodes),
+        "i2c_scl": 0,
+        "i2c_sda": 0,
+        "i2c_speed": 0,
+        "motor_map_i2c_num": 0,
+        "motor_map_i2c": [],
+        "motor_map_ledc_num": motors.len(),
+        "motor_map_ledc": motors,
+        "config_version": config.config_version,
+        "frame_formats": config.frame_formats,
+    })
+}
+
+fn encode_nodes(nodes: &[HapticNode]) -> String {
+    nodes
+        .iter()
+        .flat_map(|n| n.to_bytes())
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn decode_nodes(hex: &str) -> Option<Vec<HapticNode>> {
+    let bytes = decode_hex(hex)?;
+    if bytes.len() % 8 != 0 {
+        return None;
+    }
+    bytes
+        .chunks_exact(8)
+        .map(|chunk| chunk.try_into().ok().map(HapticNode::from_bytes))
+        .collect()
+}
+
+/// Hex frames are 4 hex digits per motor, 0-0xffff.
+fn decode_frame(hex: &str) -> Option<Vec<f32>> {
+    if hex.len() % 4 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(4)
+        .map(|i| {
+            let value = u16::from_str_radix(hex.get(i..i + 4)?, 16).ok()?;
+            Some(value as f32 / 0xffff as f32)
+        })
+        .collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
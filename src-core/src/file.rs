@@ -45,10 +45,51 @@ pub enum Directory {
     BhapticsCache,
     Logs,
     Maps,
+    Patterns,
+    Recordings,
     Security,
     Sidecars,
 }
 
+/// `<name>.<extension>`, or `None` when `name` could reach outside the folder it's meant for.
+///
+/// The extension is appended rather than set, so dotted names like `hit.v2` keep their dots.
+fn file_name(name: &str, extension: &str) -> Option<String> {
+    if name.is_empty() || name.contains(['/', '\\']) || name.contains("..") {
+        return None;
+    }
+    Some(format!("{name}.{extension}"))
+}
+
+/// The name a file was saved under by `named_file`, if it has exactly this extension.
+fn name_from_file<'a>(file_name: &'a str, extension: &str) -> Option<&'a str> {
+    file_name
+        .strip_suffix(extension)?
+        .strip_suffix('.')
+        .filter(|name| !name.is_empty())
+}
+
+/// Path of the file saved as `name` directly inside `folder`. `None` when the name isn't valid.
+pub fn named_file(folder: Directory, name: &str, extension: &str) -> Option<PathBuf> {
+    Some(resolve_dir(folder).join(file_name(name, extension)?))
+}
+
+/// Names of every file in `folder` with this extension, as `named_file` takes them. Sorted.
+pub async fn list_named_files(folder: Directory, extension: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let Ok(mut entries) = tokio::fs::read_dir(resolve_dir(folder)).await else {
+        return names;
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let file = entry.file_name();
+        if let Some(name) = file.to_str().and_then(|f| name_from_file(f, extension)) {
+            names.push(name.to_string());
+        }
+    }
+    names.sort();
+    names
+}
+
 pub fn resolve_dir(folder: Directory) -> PathBuf {
     let root = ROOT_DIR.get().expect("root directory hasn't been set yet");
     let root = root.0.clone();
@@ -56,7 +97,37 @@ pub fn resolve_dir(folder: Directory) -> PathBuf {
         Directory::BhapticsCache => root.join("data"),
         Directory::Logs => root.join("logs"),
         Directory::Maps => root.join("map_configs"),
+        Directory::Patterns => root.join("patterns"),
+        Directory::Recordings => root.join("recordings"),
         Directory::Security => root.join("security"),
         Directory::Sidecars => root.join("sidecars"),
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dotted_names_keep_their_dots() {
+        let file = file_name("hit.v2", "tact").unwrap();
+        assert_eq!(file, "hit.v2.tact");
+        assert_eq!(name_from_file(&file, "tact"), Some("hit.v2"));
+        assert_ne!(file_name("session.1", "vrchrec"), file_name("session.2", "vrchrec"));
+    }
+
+    #[test]
+    fn only_the_exact_extension_is_listed() {
+        assert_eq!(name_from_file("Foo.tact", "tact"), Some("Foo"));
+        assert_eq!(name_from_file("Foo.TACT", "tact"), None);
+        assert_eq!(name_from_file("Footact", "tact"), None);
+        assert_eq!(name_from_file(".tact", "tact"), None);
+    }
+
+    #[test]
+    fn names_stay_inside_their_folder() {
+        for name in ["", "../hit", "a/b", "a\\b", ".."] {
+            assert_eq!(file_name(name, "tact"), None, "{name}");
+        }
+    }
+}
@@ -7,6 +7,7 @@ pub mod api;
 pub mod file;
 mod network;
 pub mod bhaptics;
+pub mod control;
 pub mod devices;
 pub mod mapping;
 pub mod osc;
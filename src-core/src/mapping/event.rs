@@ -1,6 +1,7 @@
-use super::{haptic_node::HapticNode, input_node::InputNode, NodeId, NodeGroup};
+use super::{haptic_node::HapticNode, input_node::InputNode, layer::Layering, NodeId, NodeGroup};
 use crate::mapping::input_node::InputType;
 use glam::Vec3;
+use std::ops::{Add, Mul, Sub};
 use std::time::{Duration, SystemTime};
 
 /// Describes what effect an event should have.
@@ -20,6 +21,65 @@ pub enum EventEffectType {
     MovingLocation(Vec<Vec3>),
 }
 
+/// How an event moves between its steps (and waypoints for `EventEffectType::MovingLocation`).
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EventInterp {
+    /// Jumps to each step when its time comes. The original behaviour.
+    #[default]
+    Step,
+    /// Straight line between steps.
+    Linear,
+    /// Catmull-Rom spline through the steps, smooth but can overshoot slightly.
+    Cubic,
+    /// Eases in and out of each step (smoothstep).
+    EaseInOut,
+}
+
+impl EventInterp {
+    /// The interpolation configured for the first of `tags` found in `StandardMenu::source_interp`, `Step` otherwise.
+    pub fn for_tags(tags: &[String]) -> Self {
+        let menu = crate::state::get_config().mapping_menu.load();
+        tags.iter()
+            .find_map(|t| menu.source_interp.get(t).copied())
+            .unwrap_or_default()
+    }
+
+    /// Samples `points` at fractional index `t`, points are evenly spaced one index apart.
+    fn sample<T>(&self, points: &[T], t: f32) -> T
+    where
+        T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f32, Output = T>,
+    {
+        let last = points.len() - 1;
+        let t = t.clamp(0.0, last as f32);
+        let i = (t.floor() as usize).min(last);
+        let frac = t - i as f32;
+        let a = points[i];
+        let b = points[(i + 1).min(last)];
+
+        match self {
+            EventInterp::Step => a,
+            EventInterp::Linear => a + (b - a) * frac,
+            EventInterp::EaseInOut => {
+                let eased = frac * frac * (3.0 - 2.0 * frac);
+                a + (b - a) * eased
+            }
+            EventInterp::Cubic => {
+                let p0 = points[i.saturating_sub(1)];
+                let p3 = points[(i + 2).min(last)];
+                let f2 = frac * frac;
+                let f3 = f2 * frac;
+                // catmull-rom
+                (a * 2.0
+                    + (b - p0) * frac
+                    + (p0 * 2.0 - a * 5.0 + b * 4.0 - p3) * f2
+                    + (a * 3.0 - p0 - b * 3.0 + p3) * f3)
+                    * 0.5
+            }
+        }
+    }
+}
+
 /// Represents a haptic event that takes place over time.
 /// 
 /// Depends on `EventEffectType`
@@ -40,7 +100,15 @@ pub struct Event {
     pub tags: Vec<String>,
     /// radius of effect this event will have
     pub radius: f32,
+    /// How values and positions move between steps.
+    #[serde(default)]
+    pub interp: EventInterp,
+    /// Priority and blending of the nodes this event creates, when None it comes from the source tag settings.
+    #[serde(default)]
+    pub layer: Option<Layering>,
     managed_nodes: Vec<NodeId>, // nodes we have control over.
+    /// The intensity this event last set, so cleanup can tell whether another event has written since.
+    last_value: Option<f32>,
     time_step: Duration,
     steps_completed: usize,
     start_time: Option<SystemTime>,
@@ -58,7 +126,7 @@ impl Event {
     /// `duration`: The duration this event will be spread over (num_steps/duration must be > 10ms)
     ///
     /// `tags`: Any special tags to add to the event during operation. (atleast one required) Useful for clearing all events
-    /// associated with a given event source.
+    /// associated with a given event source. Also picks the events interpolation, see `EventInterp::for_tags`.
     pub fn new(
         name: String,
         effect: EventEffectType,
@@ -80,6 +148,7 @@ impl Event {
             log::warn!("Event without tags are not recommended: {}", name);
         }
 
+        let interp = EventInterp::for_tags(&tags);
         let ev = Event {
             name: name,
             effect: effect,
@@ -87,7 +156,10 @@ impl Event {
             duration: duration,
             tags: tags,
             radius: 0.10,
+            interp: interp,
+            layer: None,
             managed_nodes: Vec::new(),
+            last_value: None,
             time_step: time_step,
             steps_completed: 0,
             start_time: None,
@@ -96,6 +168,29 @@ impl Event {
         return Ok(ev);
     }
 
+    /// Sets how this event interpolates between its steps.
+    pub fn with_interp(mut self, interp: EventInterp) -> Self {
+        self.interp = interp;
+        self
+    }
+
+    /// Spreads the steps over a new `duration`, same limits as `new`.
+    pub fn with_duration(mut self, duration: Duration) -> Result<Self, CreateEventError> {
+        let time_step = duration.div_f32(self.steps.len() as f32);
+        if time_step.as_millis() < 9 {
+            return Err(CreateEventError::TooSmallTimestep);
+        }
+        self.duration = duration;
+        self.time_step = time_step;
+        Ok(self)
+    }
+
+    /// Sets the priority and blending of the nodes this event creates.
+    pub fn with_layer(mut self, layer: Layering) -> Self {
+        self.layer = Some(layer);
+        self
+    }
+
     /// Propogates the changes this event represents into the gameMap at this time.
     ///
     /// Returns whether this event should be removed from the pool.
@@ -112,13 +207,25 @@ impl Event {
             Err(_) => Duration::ZERO,
         };
 
-        let should_have_fired = (elapsed.as_nanos() / self.time_step.as_nanos()) as usize;
+        if self.interp == EventInterp::Step {
+            let should_have_fired = (elapsed.as_nanos() / self.time_step.as_nanos()) as usize;
 
-        // apply effects if we need to.
-        while self.steps_completed <= should_have_fired && self.steps_completed < self.steps.len() {
-            let value = self.steps[self.steps_completed];
-            self.apply_effect(value, &mut input_nodes);
-            self.steps_completed += 1;
+            // apply effects if we need to.
+            while self.steps_completed <= should_have_fired && self.steps_completed < self.steps.len() {
+                let value = self.steps[self.steps_completed];
+                let position = self.waypoint(self.steps_completed as f32);
+                self.apply_effect(value, position, &mut input_nodes);
+                self.last_value = Some(value);
+                self.steps_completed += 1;
+            }
+        } else if elapsed < self.duration {
+            // continuous modes update every tick.
+            let t = elapsed.as_secs_f32() / self.time_step.as_secs_f32();
+            let value = self.interp.sample(&self.steps, t);
+            let position = self.waypoint(t);
+            self.apply_effect(value, position, &mut input_nodes);
+            self.last_value = Some(value);
+            self.steps_completed = (t as usize + 1).min(self.steps.len());
         }
 
         // if the final effects have happened, clean up our stuff.
@@ -155,7 +262,8 @@ impl Event {
                         id.clone(),
                         self.radius,
                         InputType::INTERP,
-                    ),
+                    )
+                    .with_layer(self.layer.clone()),
                 );
                 self.managed_nodes.push(id);
             }
@@ -175,7 +283,8 @@ impl Event {
                         id.clone(),
                         self.radius,
                         InputType::INTERP,
-                    ),
+                    )
+                    .with_layer(self.layer.clone()),
                 );
                 self.managed_nodes.push(id);
             }
@@ -185,8 +294,18 @@ impl Event {
         self.start_time = Some(SystemTime::now());
     }
 
-    /// Applies the described effect at for a given value.
-    fn apply_effect(&self, value: f32, input_nodes: &mut Vec<InputNode>) {
+    /// Position of a `MovingLocation` event at fractional step `t`, None for other effects.
+    fn waypoint(&self, t: f32) -> Option<Vec3> {
+        match &self.effect {
+            EventEffectType::MovingLocation(waypoints) if !waypoints.is_empty() => {
+                Some(self.interp.sample(waypoints, t))
+            }
+            _ => None,
+        }
+    }
+
+    /// Applies the described effect at for a given value, `position` moves `MovingLocation` nodes.
+    fn apply_effect(&self, value: f32, position: Option<Vec3>, input_nodes: &mut Vec<InputNode>) {
         match &self.effect {
             EventEffectType::SingleNode(id) => {
                 if let Some(mut node) = input_nodes.iter_mut().find(|d| d.get_id() == id) {
@@ -212,20 +331,20 @@ impl Event {
                     node.set_intensity(value);
                 }
             }
-            EventEffectType::MovingLocation(waypoints) => {
-                let idx = self.steps_completed.min(waypoints.len() - 1);
-
-                let id = self.managed_nodes.first().unwrap(); // initiate is called first, which garuntees atleast one managed node.
+            EventEffectType::MovingLocation(_) => {
+                let Some(id) = self.managed_nodes.first() else { return };
                 if let Some(node) = input_nodes.iter_mut().find(|d| d.get_id() == id) {
-                    node.set_position(waypoints[idx]);
+                    if let Some(pos) = position {
+                        node.set_position(pos);
+                    }
                     node.set_intensity(value);
                 }
             }
         }
     }
 
-    /// cleans up the leftover nodes when an event is finished.
-    fn cleanup(&self, input_nodes: &mut Vec<InputNode>) {
+    /// cleans up the leftover nodes when an event is finished or canceled.
+    pub(super) fn cleanup(&self, input_nodes: &mut Vec<InputNode>) {
         //log::trace!("Finished event: {}", self.name);
         match &self.effect {
             EventEffectType::Location(_) | EventEffectType::MovingLocation(_) => {
@@ -235,8 +354,11 @@ impl Event {
                 }
             }
             EventEffectType::SingleNode(id) => {
+                // nodes are shared between events, only silence it if nothing else has written since we did.
                 if let Some(node) = input_nodes.iter_mut().find(|n| n.get_id() == id ) {
-                    node.set_intensity(0.);
+                    if self.last_value == Some(node.get_intensity()) {
+                        node.set_intensity(0.);
+                    }
                 }
             }
             _ => { /* nothing to remove */ }
@@ -253,3 +375,52 @@ pub enum CreateEventError {
     /// empty tags are not allowed, mainly for debugging.
     EmptyTags,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn motor(id: &NodeId) -> InputNode {
+        InputNode::new(
+            HapticNode::new(Vec3::ZERO, vec![NodeGroup::All]),
+            vec!["Test".to_string()],
+            id.clone(),
+            0.1,
+            InputType::ADDITIVE,
+        )
+    }
+
+    fn single(id: &NodeId, value: f32) -> Event {
+        Event::new(
+            "single".to_string(),
+            EventEffectType::SingleNode(id.clone()),
+            vec![value],
+            Duration::from_secs(10),
+            vec!["Test".to_string()],
+        )
+        .unwrap()
+    }
+
+    fn intensity(nodes: &[InputNode], id: &NodeId) -> f32 {
+        nodes.iter().find(|n| n.get_id() == id).unwrap().get_intensity()
+    }
+
+    #[test]
+    fn cleanup_leaves_a_node_another_event_wrote() {
+        let id = NodeId::new();
+        let mut nodes = vec![motor(&id)];
+        let mut first = single(&id, 0.5);
+        let mut second = single(&id, 0.8);
+
+        first.tick(&mut nodes);
+        second.tick(&mut nodes);
+        assert_eq!(intensity(&nodes, &id), 0.8);
+
+        first.cleanup(&mut nodes);
+        assert_eq!(intensity(&nodes, &id), 0.8, "cancelling the first event silenced the second");
+
+        second.cleanup(&mut nodes);
+        assert_eq!(intensity(&nodes, &id), 0.0);
+    }
+}
+
@@ -1,6 +1,7 @@
 use glam::Vec3;
 
 use super::haptic_node::HapticNode;
+use super::layer::Layering;
 use super::NodeId;
 
 #[cfg_attr(feature = "specta", derive(specta::Type))]
@@ -19,6 +20,9 @@ pub struct InputNode {
     pub tags: Vec<String>,
     /// how this input node should be interpreted
     pub input_type: InputType,
+    /// Priority and blending, when None it comes from the source tag settings.
+    #[serde(default)]
+    pub layer: Option<Layering>,
 }
 
 #[cfg_attr(feature = "specta", derive(specta::Type))]
@@ -65,6 +69,7 @@ impl InputNode {
             radius: radius,
             tags: tags,
             input_type: input_type,
+            layer: None,
         };
     }
 
@@ -78,6 +83,12 @@ impl InputNode {
         self.haptic_node.z = pos.z;
     }
 
+    /// Sets the priority and blending of this node, overriding its source tags.
+    pub fn with_layer(mut self, layer: Option<Layering>) -> Self {
+        self.layer = layer;
+        self
+    }
+
     pub fn set_radius(&mut self, radius: f32) {
         self.radius = radius;
     }
@@ -2,12 +2,17 @@ use enum_dispatch::enum_dispatch;
 
 use crate::{mapping::input_node::InputType, state::PerDevice};
 
-use super::{haptic_node::HapticNode, input_node::InputNode};
+use super::{
+    haptic_node::HapticNode,
+    input_node::InputNode,
+    layer::{BlendMode, NodeMix},
+    spatial::InputView,
+};
 
 #[enum_dispatch(InterpAlgo)]
 pub trait Interpolate {
     /// Implementations need to take into account that all lengths could be different.
-    fn interp(&self, haptic_nodes: &[HapticNode], output: &mut[f32], in_nodes: &[InputNode], settings: &PerDevice);
+    fn interp(&self, haptic_nodes: &[HapticNode], output: &mut[f32], in_nodes: InputView, settings: &PerDevice);
 }
 
 #[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
@@ -20,6 +25,175 @@ pub trait Interpolate {
 pub enum InterpAlgo {
     /// Uses a gaussian distribution on the array of input nodes an weights them to determine output.
     Gaussian(GaussianState),
+    /// Snaps to the closest input node in range.
+    Nearest(NearestState),
+    /// Inverse distance weighting (Shepard) of the input nodes in range.
+    InverseDistance(InverseDistanceState),
+    /// Sums each input node scaled by a radial falloff curve.
+    Falloff(FalloffState),
+    /// Takes the strongest falloff scaled influence, no averaging.
+    MaxInfluence(MaxInfluenceState),
+}
+
+/// Output values below this are treated as silent.
+const MIN_OUTPUT: f32 = 0.02;
+
+/// An input node that can influence an output node.
+struct Influence<'a> {
+    node: &'a InputNode,
+    distance: f32,
+    /// The nodes intensity after ducking.
+    intensity: f32,
+    mix: NodeMix,
+}
+
+impl Influence<'_> {
+    #[inline]
+    fn radius(&self) -> f32 {
+        self.node.get_radius()
+    }
+}
+
+/// Yields every input node that can influence `node`, along with its distance and layering.
+///
+/// `scratch` holds the candidate list between calls to avoid allocating per node.
+#[inline]
+fn influences<'a>(
+    node: &'a HapticNode,
+    in_nodes: InputView<'a>,
+    scratch: &'a mut Vec<usize>,
+) -> impl Iterator<Item = Influence<'a>> + 'a {
+    in_nodes.candidates(node, scratch);
+    let nodes = in_nodes.nodes();
+    scratch.iter().filter_map(move |idx| {
+        let in_node = &nodes[*idx];
+        let distance = node.dist(&in_node.haptic_node);
+        // if below our threshold, and the game node should influence the device node
+        if !distance.is_nan() && distance < in_node.get_radius() && node.interacts(&in_node.haptic_node) {
+            let mix = in_nodes.mix(*idx);
+            Some(Influence {
+                node: in_node,
+                distance,
+                intensity: in_node.get_intensity() * mix.scale,
+                mix,
+            })
+        } else {
+            None
+        }
+    })
+}
+
+/// Accumulates everything that is applied on top of whatever the algorithm made of the `InputType::INTERP` layer.
+///
+/// That is the `InputType::ADDITIVE` and `InputType::SUBTRACTIVE` layers, and any node with a `BlendMode` other than Normal.
+#[derive(Default)]
+struct Layers {
+    numerator: f32,
+    denominator: f32,
+    /// Highest priority `BlendMode::Override` seen, (priority, value).
+    overridden: Option<(u8, f32)>,
+    /// `BlendMode::Add` and `BlendMode::Max` contributions, (priority, blend, value).
+    blended: Vec<(u8, BlendMode, f32)>,
+}
+
+impl Layers {
+    /// Takes the node if it is not a plain interp node, returns false for Normal `InputType::INTERP` nodes.
+    ///
+    /// `weight` is only used for the additive layers, blended nodes use a linear falloff over their radius.
+    #[inline]
+    fn push(&mut self, inf: &Influence, weight: f32) -> bool {
+        let sign = match inf.node.input_type {
+            InputType::SUBTRACTIVE => -1.0,
+            InputType::INTERP | InputType::ADDITIVE => 1.0,
+        };
+
+        match inf.mix.blend {
+            BlendMode::Normal => {
+                if matches!(inf.node.input_type, InputType::INTERP) {
+                    return false;
+                }
+                self.numerator += weight * sign * inf.intensity;
+                self.denominator += weight;
+            }
+            blend => {
+                let value = sign * inf.intensity * FalloffCurve::Linear.weight(inf.distance, inf.radius());
+                let priority = inf.mix.priority;
+                if blend == BlendMode::Override {
+                    let wins = self
+                        .overridden
+                        .map_or(true, |(p, v)| priority > p || (priority == p && value > v));
+                    if wins {
+                        self.overridden = Some((priority, value));
+                    }
+                } else {
+                    self.blended.push((priority, blend, value));
+                }
+            }
+        }
+        true
+    }
+
+    /// Combines the layers with the interp result (`None` if nothing on the interp layer had influence).
+    fn resolve(&self, interp: Option<f32>) -> f32 {
+        if interp.is_none() && self.denominator <= 0.0 && self.overridden.is_none() && self.blended.is_empty() {
+            return 0.0;
+        }
+
+        let interp_result = interp.unwrap_or(0.0);
+        let mut result = if self.denominator != 0.0 {
+            (self.numerator / self.denominator) + interp_result
+        } else {
+            interp_result
+        };
+
+        // an override drops everything beneath it.
+        let floor = self.overridden.map(|(priority, value)| {
+            result = value;
+            priority
+        });
+        for (priority, blend, value) in self.blended.iter() {
+            if floor.is_some_and(|p| *priority < p) {
+                continue;
+            }
+            match blend {
+                BlendMode::Add => result += value,
+                BlendMode::Max => result = result.max(*value),
+                BlendMode::Normal | BlendMode::Override => {}
+            }
+        }
+
+        if result > 1.0 {
+            1.0
+        } else if result > MIN_OUTPUT {
+            result
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Scales the raw interpolated value into the devices range.
+///
+/// offset = 0.5, intensity = 0.5, input = 1.0 gives 0.75. Offset gives deadzone, and intensity limits final intensity.
+#[inline]
+fn apply_device(raw: f32, settings: &PerDevice) -> f32 {
+    if raw > 0.0 {
+        settings.offset + (settings.intensity - settings.offset) * raw
+    } else {
+        0.0
+    }
+}
+
+/// Runs `single` for each output node and writes the device scaled result.
+#[inline]
+fn interp_each<F>(haptic_nodes: &[HapticNode], output: &mut [f32], settings: &PerDevice, single: F)
+where
+    F: Fn(&HapticNode, &mut Vec<usize>) -> f32,
+{
+    let mut scratch = Vec::new();
+    for (node, out) in haptic_nodes.iter().zip(output.iter_mut()) {
+        *out = apply_device(single(node, &mut scratch), settings);
+    }
 }
 
 #[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
@@ -58,77 +232,209 @@ impl GaussianState {
     }
 
     /// returns the straight interpolation for the node.
-    fn single_node(&self, node: &HapticNode, in_nodes: &[InputNode]) -> f32 {
+    fn single_node(&self, node: &HapticNode, in_nodes: InputView, scratch: &mut Vec<usize>) -> f32 {
         let mut interp_numerator = 0.0;
         let mut interp_denominator = 0.0;
-        let mut add_numerator = 0.0;
-        let mut add_denominator = 0.0;
-
-        for in_node in in_nodes.iter() {
-            // if the game node should influence the device node
-            if node.interacts(&in_node.haptic_node) {
-                let distance = node.dist(&in_node.haptic_node);
-                // if below our threshold, add influence
-                if !distance.is_nan() && distance < in_node.get_radius() {
-                    // handle different interpolation layers
-                    match in_node.input_type {
-                        InputType::INTERP => {
-                            let weight = self.gaussian_kernel(distance, in_node.get_radius());
-                            interp_numerator += weight * in_node.get_intensity();
-                            interp_denominator += weight;
-                        }
-                        InputType::ADDITIVE => {
-                            let weight = distance / in_node.get_radius();
-                            add_numerator += weight * in_node.get_intensity();
-                            add_denominator += weight;
-                        }
-                        InputType::SUBTRACTIVE => {
-                            let weight = distance / in_node.get_radius();
-                            add_numerator += weight * (-in_node.get_intensity());
-                            add_denominator += weight;
-                        }
-                    }
+        let mut layers = Layers::default();
+
+        for inf in influences(node, in_nodes, scratch) {
+            let distance = inf.distance;
+            // handle different interpolation layers
+            if !layers.push(&inf, distance / inf.radius()) {
+                let weight = self.gaussian_kernel(distance, inf.radius());
+                interp_numerator += weight * inf.intensity;
+                interp_denominator += weight;
+            }
+        }
+
+        let interp = (interp_denominator > 0.0).then(|| interp_numerator / interp_denominator);
+        layers.resolve(interp)
+    }
+}
+
+impl Interpolate for GaussianState {
+    fn interp(&self, haptic_nodes: &[HapticNode], output: &mut[f32], in_nodes: InputView, settings: &PerDevice) {
+        interp_each(haptic_nodes, output, settings, |node, scratch| self.single_node(node, in_nodes, scratch));
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+/// Shape of the weight between an input node and the edge of its radius.
+pub enum FalloffCurve {
+    /// 1 at the input node, straight line to 0 at the radius.
+    Linear,
+    /// Half cosine from 1 to 0, softer at both ends.
+    Cosine,
+}
+
+impl FalloffCurve {
+    /// weight between zero and one, `distance` is expected to be within `radius`.
+    #[inline]
+    pub fn weight(&self, distance: f32, radius: f32) -> f32 {
+        let t = (distance / radius).clamp(0.0, 1.0);
+        match self {
+            FalloffCurve::Linear => 1.0 - t,
+            FalloffCurve::Cosine => 0.5 * (1.0 + (std::f32::consts::PI * t).cos()),
+        }
+    }
+}
+
+impl Default for FalloffCurve {
+    fn default() -> Self {
+        FalloffCurve::Linear
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+/// State for nearest node snapping.
+pub struct NearestState {
+    /// Fraction of each input nodes radius that will be snapped to.
+    pub radius_scale: f32,
+}
+
+impl Default for NearestState {
+    fn default() -> Self {
+        NearestState { radius_scale: 1.0 }
+    }
+}
+
+impl NearestState {
+    fn single_node(&self, node: &HapticNode, in_nodes: InputView, scratch: &mut Vec<usize>) -> f32 {
+        let mut nearest: Option<(f32, f32)> = None; // (distance, intensity)
+        let mut layers = Layers::default();
+
+        for inf in influences(node, in_nodes, scratch) {
+            let distance = inf.distance;
+            let radius = inf.radius() * self.radius_scale;
+            if distance >= radius {
+                continue;
+            }
+
+            if !layers.push(&inf, FalloffCurve::Linear.weight(distance, radius)) {
+                if nearest.map_or(true, |(d, _)| distance < d) {
+                    nearest = Some((distance, inf.intensity));
                 }
             }
         }
 
-        // process if any input nodes have influence over this one
-        if interp_denominator > 0.0 || add_denominator > 0.0 {
-            let interp_result = if interp_denominator != 0.0 {
-                interp_numerator / interp_denominator
-            } else {
-                0.0
-            };
-
-            let add_result = if add_denominator != 0.0 {
-                (add_numerator / add_denominator) + interp_result
-            } else {
-                interp_result
-            };
-
-            if add_result > 1.0 {
-                1.0
-            } else if add_result > 0.02 {
-                add_result
-            } else {
-                0.0
+        layers.resolve(nearest.map(|(_, intensity)| intensity))
+    }
+}
+
+impl Interpolate for NearestState {
+    fn interp(&self, haptic_nodes: &[HapticNode], output: &mut[f32], in_nodes: InputView, settings: &PerDevice) {
+        interp_each(haptic_nodes, output, settings, |node, scratch| self.single_node(node, in_nodes, scratch));
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+/// State for inverse distance weighting.
+pub struct InverseDistanceState {
+    /// Higher powers favour close nodes more, 2 is the usual choice.
+    pub power: f32,
+    /// Distances below this (meters) are treated as sitting on the input node.
+    pub min_distance: f32,
+}
+
+impl Default for InverseDistanceState {
+    fn default() -> Self {
+        InverseDistanceState {
+            power: 2.0,
+            min_distance: 0.001,
+        }
+    }
+}
+
+impl InverseDistanceState {
+    fn single_node(&self, node: &HapticNode, in_nodes: InputView, scratch: &mut Vec<usize>) -> f32 {
+        let mut numerator = 0.0;
+        let mut denominator = 0.0;
+        let mut exact: Option<f32> = None;
+        let mut layers = Layers::default();
+
+        for inf in influences(node, in_nodes, scratch) {
+            let distance = inf.distance;
+            if layers.push(&inf, FalloffCurve::Linear.weight(distance, inf.radius())) {
+                continue;
+            }
+
+            if distance <= self.min_distance {
+                // on top of an input node, its value wins.
+                exact = Some(exact.map_or(inf.intensity, |e| e.max(inf.intensity)));
+                continue;
+            }
+
+            let weight = 1.0 / distance.powf(self.power);
+            numerator += weight * inf.intensity;
+            denominator += weight;
+        }
+
+        let interp = exact.or_else(|| (denominator > 0.0).then(|| numerator / denominator));
+        layers.resolve(interp)
+    }
+}
+
+impl Interpolate for InverseDistanceState {
+    fn interp(&self, haptic_nodes: &[HapticNode], output: &mut[f32], in_nodes: InputView, settings: &PerDevice) {
+        interp_each(haptic_nodes, output, settings, |node, scratch| self.single_node(node, in_nodes, scratch));
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Default)]
+/// State for summed radial falloff.
+pub struct FalloffState {
+    pub curve: FalloffCurve,
+}
+
+impl FalloffState {
+    fn single_node(&self, node: &HapticNode, in_nodes: InputView, scratch: &mut Vec<usize>) -> f32 {
+        let mut sum: Option<f32> = None;
+        let mut layers = Layers::default();
+
+        for inf in influences(node, in_nodes, scratch) {
+            let distance = inf.distance;
+            let weight = self.curve.weight(distance, inf.radius());
+            if !layers.push(&inf, weight) {
+                *sum.get_or_insert(0.0) += weight * inf.intensity;
             }
-        } else {
-            0.0
         }
+
+        layers.resolve(sum)
     }
 }
 
-impl Interpolate for GaussianState {
-    fn interp(&self, haptic_nodes: &[HapticNode], output: &mut[f32], in_nodes: &[InputNode], settings: &PerDevice) {
-        for (i, node) in haptic_nodes.iter().enumerate() {
-            // offset = 0.5, intensity = 0.5, input = 1.0 gives 0.75. Offset gives deadzone, and intensity limits final intensity.
-            let raw = self.single_node(node, in_nodes);
-            output[i] = if raw > 0.0 {
-                settings.offset + (settings.intensity - settings.offset) * raw
-            } else {
-                0.0
-            };
+impl Interpolate for FalloffState {
+    fn interp(&self, haptic_nodes: &[HapticNode], output: &mut[f32], in_nodes: InputView, settings: &PerDevice) {
+        interp_each(haptic_nodes, output, settings, |node, scratch| self.single_node(node, in_nodes, scratch));
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Default)]
+/// State for max-of-influences.
+pub struct MaxInfluenceState {
+    pub curve: FalloffCurve,
+}
+
+impl MaxInfluenceState {
+    fn single_node(&self, node: &HapticNode, in_nodes: InputView, scratch: &mut Vec<usize>) -> f32 {
+        let mut max: Option<f32> = None;
+        let mut layers = Layers::default();
+
+        for inf in influences(node, in_nodes, scratch) {
+            let distance = inf.distance;
+            let weight = self.curve.weight(distance, inf.radius());
+            if !layers.push(&inf, weight) {
+                let value = weight * inf.intensity;
+                max = Some(max.map_or(value, |m| m.max(value)));
+            }
         }
+
+        layers.resolve(max)
+    }
+}
+
+impl Interpolate for MaxInfluenceState {
+    fn interp(&self, haptic_nodes: &[HapticNode], output: &mut[f32], in_nodes: InputView, settings: &PerDevice) {
+        interp_each(haptic_nodes, output, settings, |node, scratch| self.single_node(node, in_nodes, scratch));
     }
 }
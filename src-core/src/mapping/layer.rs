@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+
+use super::input_node::InputNode;
+
+/// How an input node combines with the rest of the map.
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BlendMode {
+    /// Handled by the interpolation algorithm according to the nodes `InputType`. The original behaviour.
+    #[default]
+    Normal,
+    /// Added on top of the result, scaled linearly over the nodes radius.
+    Add,
+    /// Result is at least this nodes value, scaled linearly over the nodes radius.
+    Max,
+    /// Replaces the algorithms result and any Add or Max from lower priority nodes, scaled linearly over the nodes radius.
+    ///
+    /// When several overlap the highest priority wins.
+    Override,
+}
+
+/// Priority and blending for an input node, set directly on nodes and events or per source tag in `StandardMenu::source_layers`.
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq, Default)]
+pub struct Layering {
+    /// Higher priorities win overrides and duck lower ones.
+    #[serde(default)]
+    pub priority: u8,
+    #[serde(default)]
+    pub blend: BlendMode,
+    /// While a node with this layering is active, every lower priority node is scaled by `1 - duck`.
+    #[serde(default)]
+    pub duck: f32,
+}
+
+impl Layering {
+    pub fn new(priority: u8, blend: BlendMode, duck: f32) -> Self {
+        Self {
+            priority,
+            blend,
+            duck,
+        }
+    }
+}
+
+/// Default layering for the built in sources, game impacts duck ambient VRC contacts.
+pub fn default_source_layers() -> HashMap<String, Layering> {
+    HashMap::from([
+        ("VRC".to_string(), Layering::new(0, BlendMode::Normal, 0.0)),
+        ("Bhaptics".to_string(), Layering::new(1, BlendMode::Normal, 0.5)),
+        ("Bhaptics_V1".to_string(), Layering::new(1, BlendMode::Normal, 0.5)),
+        ("Bhaptics_V2".to_string(), Layering::new(1, BlendMode::Normal, 0.5)),
+        ("Bhaptics_V3".to_string(), Layering::new(1, BlendMode::Normal, 0.5)),
+        ("Bhaptics_Pattern".to_string(), Layering::new(1, BlendMode::Normal, 0.5)),
+        ("UI".to_string(), Layering::new(2, BlendMode::Normal, 0.0)),
+    ])
+}
+
+/// Resolved layering of a single input node for one update.
+#[derive(Debug, Clone, Copy)]
+pub struct NodeMix {
+    /// Multiplier on the nodes intensity from ducking.
+    pub scale: f32,
+    pub blend: BlendMode,
+    pub priority: u8,
+}
+
+impl Default for NodeMix {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            blend: BlendMode::Normal,
+            priority: 0,
+        }
+    }
+}
+
+/// Fills `out` with the mix of each node in `nodes`, same order.
+///
+/// Nodes use their own `InputNode::layer` if set, otherwise the highest priority entry in `sources` matching one of their tags.
+pub fn resolve_mix(nodes: &[InputNode], sources: &HashMap<String, Layering>, out: &mut Vec<NodeMix>) {
+    out.clear();
+    // strongest duck held at each priority by a node that is currently active.
+    let mut ducks = [0.0f32; u8::MAX as usize + 1];
+    let mut any_duck = false;
+    let fallback = Layering::default();
+
+    for node in nodes.iter() {
+        let layer = node.layer.as_ref().unwrap_or_else(|| {
+            node.tags
+                .iter()
+                .filter_map(|t| sources.get(t))
+                .max_by_key(|l| l.priority)
+                .unwrap_or(&fallback)
+        });
+
+        if layer.duck > 0.0 && node.get_intensity() > 0.0 {
+            let duck = &mut ducks[layer.priority as usize];
+            *duck = duck.max(layer.duck.clamp(0.0, 1.0));
+            any_duck = true;
+        }
+
+        out.push(NodeMix {
+            scale: 1.0,
+            blend: layer.blend,
+            priority: layer.priority,
+        });
+    }
+
+    if !any_duck {
+        return;
+    }
+
+    // scale at priority p is the product of every duck above it.
+    let mut scales = [1.0f32; u8::MAX as usize + 1];
+    for p in (0..u8::MAX as usize).rev() {
+        scales[p] = scales[p + 1] * (1.0 - ducks[p + 1]);
+    }
+    for mix in out.iter_mut() {
+        mix.scale = scales[mix.priority as usize];
+    }
+}
@@ -3,12 +3,17 @@ pub mod event;
 pub mod haptic_node;
 pub mod input_node;
 pub mod interp;
+pub mod layer;
+pub mod post;
+pub mod recording;
+pub mod safety;
+pub mod spatial;
 
 use crate::log_err;
 use parking_lot::{Mutex, RwLock};
 use std::{
     sync::{atomic::AtomicBool, Arc},
-    time::Duration,
+    time::{Duration, Instant},
 };
 use tokio::sync::{
     mpsc::{
@@ -22,12 +27,16 @@ use event::Event;
 use haptic_node::HapticNode;
 use input_node::InputNode;
 use interp::Interpolate;
+use layer::{resolve_mix, NodeMix};
+use post::{apply_overrides, PostState};
+use safety::{SafetyChange, SafetyState};
+use spatial::{InputIndex, InputView};
 use uuid::Uuid;
 use glam::Vec3;
 
 use crate::{
-    devices::{Device, DeviceHandle, DeviceId, DeviceInfo, DeviceOutEvents},
-    state::{self, PerDevice},
+    devices::{Device, DeviceHandle, DeviceId, DeviceInfo, DeviceMessage, DeviceOutEvents},
+    state::{self, PerDevice, StandardMenu},
 };
 
 /// Snapshot of map state.
@@ -108,6 +117,15 @@ impl MapHandle {
         gather
     }
 
+    /// Runs `f` over the whole input node list under a single read lock.
+    pub fn with_nodes<F, T>(&self, f: F) -> T
+    where
+        F: FnOnce(&[InputNode]) -> T,
+    {
+        let nodes = self.input_nodes.read();
+        f(&nodes)
+    }
+
     /// Performs function f on input node with id: `id`
     pub fn with_node<F, T>(&self, id: &NodeId, f: F) -> Option<T>
     where
@@ -150,6 +168,10 @@ pub struct MappingDevice {
     /// keep in mind locking this also locks the associated devices access to the buffer.
     outputs: Arc<RwLock<Vec<f32>>>,
     nodes: Vec<HapticNode>,
+    /// Runtime state of this devices `PerDevice::post_process` chain.
+    post: PostState,
+    /// Runtime state of this devices `PerDevice::safety` limiter.
+    safety: SafetyState,
 }
 
 impl MappingDevice {
@@ -166,9 +188,12 @@ impl MappingDevice {
 
     /// updates the buffer based on the referenced input nodes.
     ///
+    /// Runs interpolation, then the devices post-processing chain and motor overrides, then `gain` (the global master gain),
+    /// and finally the safety limiter. Returns any changes in the limiters state.
+    ///
     /// NOTE: This does not update the remote device, to force an update remember to use the `crate::devices::Device` trait as specified
     ///
-    pub fn update_buffer(&self, in_nodes: &Vec<InputNode>, settings: &PerDevice) {
+    pub fn update_buffer(&mut self, in_nodes: InputView, settings: &PerDevice, gain: f32) -> Vec<SafetyChange> {
         let mut buf = self.outputs.write();
         if buf.len() != self.nodes.len() {
             log::trace!(
@@ -176,9 +201,58 @@ impl MappingDevice {
                 buf.len(),
                 self.nodes.len()
             );
-            return;
+            return Vec::new();
         }
         settings.interp_algo.interp(&self.nodes, &mut buf, in_nodes, settings);
+        self.post.apply(&settings.post_process, &mut buf);
+        apply_overrides(&settings.motors, &mut buf);
+        if gain < 1.0 {
+            buf.iter_mut().for_each(|v| *v *= gain);
+        }
+        self.safety.apply(&settings.safety, &mut buf)
+    }
+}
+
+/// Master gain and kill switch from the global `StandardMenu`.
+///
+/// Ramps towards the menu value instead of jumping, so toggling haptics fades rather than cuts.
+struct MasterGain {
+    current: f32,
+    last_step: Instant,
+}
+
+impl MasterGain {
+    fn new(menu: &StandardMenu) -> Self {
+        Self {
+            current: Self::target(menu),
+            last_step: Instant::now(),
+        }
+    }
+
+    fn target(menu: &StandardMenu) -> f32 {
+        if menu.enable {
+            menu.intensity.clamp(0.0, 1.0)
+        } else {
+            0.0
+        }
+    }
+
+    /// Moves towards the menu target and returns the gain to apply now.
+    fn step(&mut self, menu: &StandardMenu) -> f32 {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_step).as_secs_f32();
+        self.last_step = now;
+
+        let target = Self::target(menu);
+        let fade = menu.fade_time.as_secs_f32();
+        if fade <= 0.0 {
+            self.current = target;
+        } else {
+            let max_delta = elapsed / fade;
+            let delta = (target - self.current).clamp(-max_delta, max_delta);
+            self.current += delta;
+        }
+        self.current
     }
 }
 
@@ -205,6 +279,11 @@ struct InputMap {
     event_send: mpsc::Sender<InputEventMessage>,
     /// Whether input mapping has changed in a way that should require device output updates
     map_dirty: Arc<Notify>,
+    master: MasterGain,
+    /// Spatial lookup over `input_nodes`, synced before each device update.
+    index: InputIndex,
+    /// Layering of each of `input_nodes`, resolved before each device update.
+    mix: Vec<NodeMix>,
 }
 
 impl InputMap {
@@ -223,6 +302,9 @@ impl InputMap {
             event_recv: rx,
             event_send: tx.clone(),
             map_dirty: Arc::clone(&dirty_flag),
+            master: MasterGain::new(&state::get_config().mapping_menu.load()),
+            index: InputIndex::default(),
+            mix: Vec::new(),
         };
 
         let handle = MapHandle {
@@ -268,12 +350,16 @@ impl InputMap {
                                 id: id,
                                 outputs: buf,
                                 nodes: info.get_nodes().to_vec(),
+                                post: PostState::default(),
+                                safety: SafetyState::default(),
                             });
                         }
                         DeviceOutEvents::RemovedDevice(id) => {
                             let mut devices = devices_clone.lock();
                             devices.retain(|d| d.id != id);
                         }
+                        // we are the ones sending these.
+                        DeviceOutEvents::SafetyLimit(..) => {}
                     },
                     None => {}
                 }
@@ -317,11 +403,22 @@ impl InputMap {
                                 let mut nodes = self.input_nodes.write();
                                 let Some(node) = nodes.iter_mut().find(|d| *d.get_id() == id) else {
                                     log::warn!("Tried to update node that doesn't exist with id: {id:?}");
-                                    return;
+                                    continue;
                                 };
                                 node.intensity = int.unwrap_or(node.intensity);
                                 node.radius = radius.unwrap_or(node.radius);
                             }
+                            InputEventMessage::MoveNode(id, pos) => {
+                                let mut nodes = self.input_nodes.write();
+                                match nodes.iter_mut().find(|d| *d.get_id() == id) {
+                                    Some(node) => node.set_position(pos),
+                                    None => log::warn!("Tried to move node that doesn't exist with id: {id:?}"),
+                                }
+                            }
+                            InputEventMessage::RemoveNodes(ids) => {
+                                let mut nodes = self.input_nodes.write();
+                                nodes.retain(|n| !ids.contains(n.get_id()));
+                            }
                             InputEventMessage::RemoveWithTags(tags) => {
                                 let mut nodes = self.input_nodes.write();
                                 for tag in tags {
@@ -349,23 +446,44 @@ impl InputMap {
     }
 
     /// pushes updates from map to devices
-    fn update_devices(&self) {
-        let devices = self.devices.lock();
+    fn update_devices(&mut self) {
+        let menu = state::get_config().mapping_menu.load();
+        let gain = self.master.step(&menu);
+        let mut devices = self.devices.lock();
         let in_nodes = self.input_nodes.read();
-        for device in devices.iter() {
+        self.index.sync(&in_nodes);
+        resolve_mix(&in_nodes, &menu.source_layers, &mut self.mix);
+        let view = InputView::new(&in_nodes, &self.index).with_mix(&self.mix);
+        for device in devices.iter_mut() {
             // could be done in parallel here. but few devices means not effeicnet (probably)
             let (_, settings) = state::get_device(&device.id);
-            device.update_buffer(&in_nodes, &settings.load());
+            let changes = device.update_buffer(view, &settings.load(), gain);
             self.manager.with_device(&device.id, |d| d.buffer_updated());
+
+            for change in changes {
+                if change.engaged {
+                    log::warn!("Safety limiter engaged on motor {} of device {:?}", change.motor, device.id);
+                } else {
+                    log::info!("Safety limiter released on motor {} of device {:?}", change.motor, device.id);
+                }
+                let _ = self.manager.get_device_channel().try_send(DeviceMessage::SafetyLimit(device.id.clone(), change));
+            }
         }
     }
 
+    /// Cancels every event carrying any of `tags`, releasing the nodes they drive.
     fn cancel_tags(&mut self, tags: &Vec<String>) -> usize {
+        // same lock order as the tick task.
+        let mut nodes = self.input_nodes.write();
         let mut events = self.active_events.write();
         let num = events.len();
-        for tag in tags {
-            events.retain(|e| e.tags.contains(&tag));
-        }
+        events.retain(|e| {
+            let cancel = tags.iter().any(|t| e.tags.contains(t));
+            if cancel {
+                e.cleanup(&mut nodes);
+            }
+            !cancel
+        });
         num - events.len()
     }
 
@@ -409,6 +527,17 @@ fn handle_dirty_info(id: DeviceId, dev: &DeviceHandle, devices: &Mutex<Vec<Mappi
                 if device.nodes.len() != out_len {
                     log::error!("Output buffer not same length on device: {:?}", i.id);
                 }
+            },
+            DeviceInfo::Virtual(i) => {
+                let mut lock = devices.lock();
+                let Some(device) = lock.iter_mut().find(|d| d.id == id) else {
+                    return;
+                };
+                device.nodes = i.nodes;
+                let out_len = device.outputs.read().len();
+                if device.nodes.len() != out_len {
+                    log::error!("Output buffer not same length on device: {:?}", i.id);
+                }
             }
         }
     }
@@ -418,6 +547,10 @@ pub enum InputEventMessage {
     /// Sets node with `NodeId`'s intensity, and radius.
     UpdateNode(NodeId, Option<f32>, Option<f32>),
     InsertNode(InputNode),
+    /// Moves node with `NodeId` to a new position.
+    MoveNode(NodeId, Vec3),
+    /// Removes the `InputNodes` with these ids.
+    RemoveNodes(Vec<NodeId>),
     /// Removes all `InputNodes` with tags. This includes all input nodes created by events.
     RemoveWithTags(Vec<String>),
     StartEvent(Event),
@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// A single step in a devices output post-processing chain.
+///
+/// Stages run in order on the interpolated output, before the global master gain.
+/// Silent motors (0.0) stay silent through every stage except `Slew`, which can release towards 0.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+#[serde(tag = "stage", content = "params")]
+pub enum PostStage {
+    /// `out = in^gamma`. Above 1 softens low values, below 1 boosts them.
+    Gamma {
+        #[serde(deserialize_with = "positive_gamma")]
+        gamma: f32,
+    },
+    /// `out = (e^(k*in) - 1) / (e^k - 1)`. Positive k pushes towards the top, negative towards the bottom.
+    Exponential { k: f32 },
+    /// Keeps active motors within `min..=max`. Useful for motors that stall below a certain drive.
+    ///
+    /// Motors listed in `motors`, by node index, use their own range instead.
+    Clamp {
+        min: f32,
+        max: f32,
+        #[serde(default)]
+        motors: HashMap<usize, ClampRange>,
+    },
+    /// Limits how quickly each motor can change.
+    ///
+    /// `attack` and `release` are the time taken to travel the full 0-1 range up or down.
+    Slew { attack: Duration, release: Duration },
+    /// Values at or below `threshold` are silenced.
+    DeadBand { threshold: f32 },
+}
+
+/// Output range of one motor in a `Clamp` stage.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct ClampRange {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl ClampRange {
+    #[inline]
+    fn clamp(&self, v: f32) -> f32 {
+        v.clamp(self.min, self.max.max(self.min))
+    }
+}
+
+/// At or below 0 every active motor would end up at or above full, so those are refused outright.
+fn positive_gamma<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    let gamma = <f32 as serde::Deserialize>::deserialize(deserializer)?;
+    if gamma.is_finite() && gamma > 0.0 {
+        Ok(gamma)
+    } else {
+        Err(serde::de::Error::custom(format!("gamma must be above 0, got {gamma}")))
+    }
+}
+
+impl PostStage {
+    /// Applies a stateless stage to the value of motor `idx`.
+    #[inline]
+    fn map(&self, idx: usize, v: f32) -> f32 {
+        if v <= 0.0 {
+            return 0.0;
+        }
+        match self {
+            PostStage::Gamma { gamma } => v.powf(*gamma),
+            PostStage::Exponential { k } => {
+                if k.abs() < f32::EPSILON {
+                    v
+                } else {
+                    ((k * v).exp() - 1.0) / (k.exp() - 1.0)
+                }
+            }
+            PostStage::Clamp { min, max, motors } => match motors.get(&idx) {
+                Some(range) => range.clamp(v),
+                None => ClampRange { min: *min, max: *max }.clamp(v),
+            },
+            PostStage::DeadBand { threshold } => {
+                if v <= *threshold {
+                    0.0
+                } else {
+                    v
+                }
+            }
+            PostStage::Slew { .. } => v,
+        }
+    }
+}
+
+/// Runtime state the chain needs between runs, one per mapped device.
+#[derive(Debug, Default)]
+pub struct PostState {
+    /// Last output of each slew stage, indexed by stage position.
+    previous: Vec<Vec<f32>>,
+    last_run: Option<Instant>,
+}
+
+impl PostState {
+    /// Runs `stages` over `buf` in place.
+    pub fn apply(&mut self, stages: &[PostStage], buf: &mut [f32]) {
+        let now = Instant::now();
+        let elapsed = self
+            .last_run
+            .map(|last| now.duration_since(last).as_secs_f32())
+            .unwrap_or(0.0);
+        self.last_run = Some(now);
+
+        if stages.is_empty() {
+            self.previous.clear();
+            return;
+        }
+        self.previous.resize_with(stages.len(), Vec::new);
+
+        for (idx, stage) in stages.iter().enumerate() {
+            match stage {
+                PostStage::Slew { attack, release } => {
+                    let previous = &mut self.previous[idx];
+                    if previous.len() != buf.len() {
+                        // first run or the map changed size, start from where we are.
+                        *previous = buf.to_vec();
+                        continue;
+                    }
+                    let up = max_step(*attack, elapsed);
+                    let down = max_step(*release, elapsed);
+                    for (v, prev) in buf.iter_mut().zip(previous.iter_mut()) {
+                        let delta = (*v - *prev).clamp(-down, up);
+                        *v = (*prev + delta).clamp(0.0, 1.0);
+                        *prev = *v;
+                    }
+                }
+                stage => buf.iter_mut().enumerate().for_each(|(i, v)| *v = stage.map(i, *v)),
+            }
+        }
+    }
+}
+
+/// Largest change allowed in `elapsed` seconds when `full_scale` is the time for a 0-1 sweep.
+#[inline]
+fn max_step(full_scale: Duration, elapsed: f32) -> f32 {
+    let secs = full_scale.as_secs_f32();
+    if secs <= 0.0 {
+        f32::INFINITY
+    } else {
+        elapsed / secs
+    }
+}
+
+/// Per-motor settings, keyed by node index in `PerDevice::motors`.
+///
+/// Applied after the post-processing chain, so they hold regardless of how the chain is set up.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq)]
+pub struct MotorOverride {
+    /// Disabled motors always output 0.
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Multiplier on this motors output, for trimming motors that run strong or weak.
+    #[serde(default = "default_trim")]
+    pub trim: f32,
+    /// When set the motor outputs this instead of the map, for finding and testing motors.
+    #[serde(default)]
+    pub test_value: Option<f32>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_trim() -> f32 {
+    1.0
+}
+
+impl Default for MotorOverride {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            trim: default_trim(),
+            test_value: None,
+        }
+    }
+}
+
+impl MotorOverride {
+    #[inline]
+    fn apply(&self, v: f32) -> f32 {
+        if !self.enabled {
+            return 0.0;
+        }
+        if let Some(test) = self.test_value {
+            return test.clamp(0.0, 1.0);
+        }
+        (v * self.trim).clamp(0.0, 1.0)
+    }
+}
+
+/// Applies each override to the motor at its index. Indices past the end of `buf` are ignored.
+pub fn apply_overrides(overrides: &HashMap<usize, MotorOverride>, buf: &mut [f32]) {
+    for (idx, motor) in overrides.iter() {
+        if let Some(v) = buf.get_mut(*idx) {
+            *v = motor.apply(*v);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stage(json: &str) -> Result<PostStage, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    #[test]
+    fn gamma_must_be_positive() {
+        assert!(stage(r#"{"stage":"Gamma","params":{"gamma":2.2}}"#).is_ok());
+        for gamma in ["0", "0.0", "-1.5"] {
+            let json = format!(r#"{{"stage":"Gamma","params":{{"gamma":{gamma}}}}}"#);
+            assert!(stage(&json).is_err(), "accepted gamma {gamma}");
+        }
+    }
+
+    #[test]
+    fn clamp_uses_each_motors_own_range() {
+        let clamp = stage(r#"{"stage":"Clamp","params":{"min":0.2,"max":0.8,"motors":{"1":{"min":0.5,"max":1.0}}}}"#)
+            .unwrap();
+        let mut buf = [0.1, 0.1, 0.9, 0.0];
+        PostState::default().apply(&[clamp], &mut buf);
+        assert_eq!(buf, [0.2, 0.5, 0.8, 0.0]);
+    }
+
+    #[test]
+    fn clamp_without_motors_still_loads() {
+        let clamp = stage(r#"{"stage":"Clamp","params":{"min":0.1,"max":0.9}}"#).unwrap();
+        let PostStage::Clamp { min, max, motors } = clamp else {
+            panic!("not a clamp stage");
+        };
+        assert_eq!((min, max), (0.1, 0.9));
+        assert!(motors.is_empty());
+    }
+}
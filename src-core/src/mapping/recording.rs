@@ -0,0 +1,518 @@
+//! Recording and playback of the input map.
+//!
+//! Recordings are JSON lines: a `RecordingHeader`, then one `Frame` per tick that had changes.
+//! Frames only hold what changed since the previous one, so a quiet map costs next to nothing.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use glam::Vec3;
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+use tokio_util::sync::CancellationToken;
+
+use super::{input_node::InputNode, layer::Layering, InputEventMessage, MapHandle, NodeId};
+use crate::devices::{Device, DeviceHandle, DeviceId};
+
+/// Bumped whenever the file layout changes in a way older readers can't handle.
+pub const RECORDING_VERSION: u32 = 1;
+/// Extension used for recordings.
+pub const RECORDING_EXTENSION: &str = "vrchrec";
+/// Added to every node inserted by a playback, removing this tag clears all playbacks from the map.
+pub const PLAYBACK_TAG: &str = "Playback";
+/// Same rate the map ticks events at.
+const RECORD_TICK: Duration = Duration::from_millis(10);
+
+/// First line of every recording.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct RecordingHeader {
+    pub version: u32,
+    pub tick: Duration,
+    /// Seconds since the unix epoch the recording started at.
+    pub started: u64,
+    /// Whether input node changes were recorded.
+    pub nodes: bool,
+    /// Whether device output buffers were recorded.
+    pub outputs: bool,
+}
+
+/// Everything that changed since the previous frame.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default)]
+pub struct Frame {
+    /// Milliseconds since the start of the recording.
+    pub t: u64,
+    /// Nodes that appeared, in full.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub added: Vec<InputNode>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed: Vec<NodeId>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub changed: Vec<NodeChange>,
+    /// Output buffers quantized to 0-255, only for devices whose output changed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outputs: Vec<(DeviceId, Vec<u8>)>,
+}
+
+impl Frame {
+    fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty() && self.outputs.is_empty()
+    }
+}
+
+/// The parts of an existing node that changed, unchanged fields are None.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct NodeChange {
+    pub id: NodeId,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub intensity: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub radius: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<Vec3>,
+}
+
+impl NodeChange {
+    fn between(old: &InputNode, new: &InputNode) -> Option<NodeChange> {
+        let old_pos = position(old);
+        let new_pos = position(new);
+        let change = NodeChange {
+            id: new.get_id().clone(),
+            intensity: (old.get_intensity() != new.get_intensity()).then_some(new.get_intensity()),
+            radius: (old.get_radius() != new.get_radius()).then_some(new.get_radius()),
+            position: (old_pos != new_pos).then_some(new_pos),
+        };
+        (change.intensity.is_some() || change.radius.is_some() || change.position.is_some()).then_some(change)
+    }
+
+    fn apply(&self, node: &mut InputNode) {
+        if let Some(intensity) = self.intensity {
+            node.set_intensity(intensity);
+        }
+        if let Some(radius) = self.radius {
+            node.set_radius(radius);
+        }
+        if let Some(pos) = self.position {
+            node.set_position(pos);
+        }
+    }
+}
+
+#[inline]
+fn position(node: &InputNode) -> Vec3 {
+    Vec3::new(node.haptic_node.x, node.haptic_node.y, node.haptic_node.z)
+}
+
+#[derive(Debug)]
+pub enum RecordingError {
+    Io(std::io::Error),
+    /// A line could not be parsed, with its line number.
+    Format(serde_json::Error, usize),
+    /// The file is missing its header line.
+    MissingHeader,
+    UnsupportedVersion(u32),
+}
+
+impl From<std::io::Error> for RecordingError {
+    fn from(value: std::io::Error) -> Self {
+        RecordingError::Io(value)
+    }
+}
+
+/// A recording read back from disk.
+#[derive(Debug, Clone)]
+pub struct Recording {
+    pub header: RecordingHeader,
+    pub frames: Vec<Frame>,
+}
+
+impl Recording {
+    pub async fn load(path: &Path) -> Result<Recording, RecordingError> {
+        let data = tokio::fs::read_to_string(path).await?;
+        Self::parse(&data)
+    }
+
+    pub fn parse(data: &str) -> Result<Recording, RecordingError> {
+        let mut lines = data.lines().enumerate().filter(|(_, l)| !l.trim().is_empty());
+        let (_, first) = lines.next().ok_or(RecordingError::MissingHeader)?;
+        let header: RecordingHeader =
+            serde_json::from_str(first).map_err(|e| RecordingError::Format(e, 1))?;
+        if header.version > RECORDING_VERSION {
+            return Err(RecordingError::UnsupportedVersion(header.version));
+        }
+
+        let frames = lines
+            .map(|(idx, line)| serde_json::from_str(line).map_err(|e| RecordingError::Format(e, idx + 1)))
+            .collect::<Result<Vec<Frame>, _>>()?;
+
+        Ok(Recording { header, frames })
+    }
+
+    /// Length of the recording, the time of its last frame.
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.frames.last().map_or(0, |f| f.t))
+    }
+
+    /// Rebuilds the full input node list at every frame and hands it to `f` with the frames time in milliseconds.
+    ///
+    /// Useful for feeding a recorded session through interpolation outside of a running map.
+    pub fn for_each_state<F>(&self, mut f: F)
+    where
+        F: FnMut(u64, &[InputNode]),
+    {
+        let mut nodes: Vec<InputNode> = Vec::new();
+        for frame in self.frames.iter() {
+            nodes.retain(|n| !frame.removed.contains(n.get_id()));
+            nodes.extend(frame.added.iter().cloned());
+            for change in frame.changed.iter() {
+                if let Some(node) = nodes.iter_mut().find(|n| n.get_id() == &change.id) {
+                    change.apply(node);
+                }
+            }
+            f(frame.t, &nodes);
+        }
+    }
+}
+
+/// What a recorder should capture.
+#[derive(serde::Deserialize, Debug, Clone, Copy)]
+pub struct RecordOptions {
+    /// Input node positions, intensities and tags. Needed for playback.
+    #[serde(default = "default_true")]
+    pub nodes: bool,
+    /// Each devices output buffer.
+    #[serde(default = "default_true")]
+    pub outputs: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for RecordOptions {
+    fn default() -> Self {
+        Self {
+            nodes: true,
+            outputs: true,
+        }
+    }
+}
+
+/// A recording in progress. Dropping it stops the recording.
+pub struct RecordingHandle {
+    pub path: PathBuf,
+    shutdown: CancellationToken,
+    task: Option<JoinHandle<Result<usize, RecordingError>>>,
+}
+
+impl RecordingHandle {
+    /// Stops recording and waits for the file to be flushed. Returns the number of frames written.
+    pub async fn stop(mut self) -> Result<usize, RecordingError> {
+        self.shutdown.cancel();
+        let Some(task) = self.task.take() else {
+            return Ok(0);
+        };
+        match task.await {
+            Ok(res) => res,
+            Err(e) => Err(RecordingError::Io(std::io::Error::other(e))),
+        }
+    }
+}
+
+impl Drop for RecordingHandle {
+    fn drop(&mut self) {
+        self.shutdown.cancel();
+    }
+}
+
+/// Starts recording the map and/or device outputs into `path`, overwriting it.
+pub async fn start_recording(
+    path: PathBuf,
+    map: MapHandle,
+    devices: DeviceHandle,
+    options: RecordOptions,
+) -> Result<RecordingHandle, RecordingError> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let file = tokio::fs::File::create(&path).await?;
+    let mut writer = BufWriter::new(file);
+
+    let header = RecordingHeader {
+        version: RECORDING_VERSION,
+        tick: RECORD_TICK,
+        started: SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs()),
+        nodes: options.nodes,
+        outputs: options.outputs,
+    };
+    write_line(&mut writer, &header).await?;
+
+    let token = CancellationToken::new();
+    let child = token.child_token();
+    log::info!("Recording map to {}", path.display());
+
+    let task = tokio::spawn(async move {
+        let mut recorder = Recorder::default();
+        let start = Instant::now();
+        let mut interval = tokio::time::interval(RECORD_TICK);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        let mut written = 0;
+
+        loop {
+            tokio::select! {
+                _ = child.cancelled() => break,
+                _ = interval.tick() => {}
+            }
+
+            let mut frame = Frame {
+                t: start.elapsed().as_millis() as u64,
+                ..Default::default()
+            };
+            if options.nodes {
+                map.with_nodes(|nodes| recorder.diff_nodes(nodes, &mut frame));
+            }
+            if options.outputs {
+                recorder.diff_outputs(&devices, &mut frame);
+            }
+
+            if !frame.is_empty() {
+                write_line(&mut writer, &frame).await?;
+                written += 1;
+            }
+        }
+
+        writer.flush().await?;
+        log::info!("Recording stopped after {written} frames");
+        Ok(written)
+    });
+
+    Ok(RecordingHandle {
+        path,
+        shutdown: token,
+        task: Some(task),
+    })
+}
+
+async fn write_line<T: serde::Serialize>(
+    writer: &mut BufWriter<tokio::fs::File>,
+    value: &T,
+) -> Result<(), RecordingError> {
+    let mut line = serde_json::to_vec(value).map_err(|e| RecordingError::Format(e, 0))?;
+    line.push(b'\n');
+    writer.write_all(&line).await?;
+    Ok(())
+}
+
+/// What the previous frame left behind, to diff against.
+#[derive(Default)]
+struct Recorder {
+    nodes: HashMap<NodeId, InputNode>,
+    outputs: HashMap<DeviceId, Vec<u8>>,
+}
+
+impl Recorder {
+    fn diff_nodes(&mut self, nodes: &[InputNode], frame: &mut Frame) {
+        let before = self.nodes.len();
+        let mut seen = 0;
+        for node in nodes.iter() {
+            match self.nodes.get_mut(node.get_id()) {
+                Some(old) => {
+                    seen += 1;
+                    if let Some(change) = NodeChange::between(old, node) {
+                        frame.changed.push(change);
+                        *old = node.clone();
+                    }
+                }
+                None => {
+                    frame.added.push(node.clone());
+                    self.nodes.insert(node.get_id().clone(), node.clone());
+                }
+            }
+        }
+
+        if seen != before {
+            self.nodes.retain(|id, _| {
+                let keep = nodes.iter().any(|n| n.get_id() == id);
+                if !keep {
+                    frame.removed.push(id.clone());
+                }
+                keep
+            });
+        }
+    }
+
+    fn diff_outputs(&mut self, devices: &DeviceHandle, frame: &mut Frame) {
+        let ids = devices.devices();
+        self.outputs.retain(|id, _| ids.contains(id));
+
+        for id in ids {
+            let Some(buf) = devices.with_device(&id, |d| d.get_feedback_buffer()) else {
+                continue;
+            };
+            let quantized: Vec<u8> = buf
+                .read()
+                .iter()
+                .map(|v| (v.clamp(0.0, 1.0) * 255.0).round() as u8)
+                .collect();
+
+            if self.outputs.get(&id) != Some(&quantized) {
+                self.outputs.insert(id.clone(), quantized.clone());
+                frame.outputs.push((id, quantized));
+            }
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Copy)]
+pub struct PlaybackOptions {
+    /// 1 plays at the recorded speed, 2 twice as fast.
+    #[serde(default = "default_speed")]
+    pub speed: f32,
+    /// Start over when the end is reached, until stopped.
+    #[serde(default)]
+    pub looping: bool,
+}
+
+fn default_speed() -> f32 {
+    1.0
+}
+
+impl Default for PlaybackOptions {
+    fn default() -> Self {
+        Self {
+            speed: default_speed(),
+            looping: false,
+        }
+    }
+}
+
+/// A playback in progress. Dropping it stops the playback and clears its nodes from the map.
+pub struct PlaybackHandle {
+    shutdown: CancellationToken,
+}
+
+impl PlaybackHandle {
+    pub fn stop(&self) {
+        self.shutdown.cancel();
+    }
+
+    /// True once the playback has finished or been stopped.
+    pub fn is_finished(&self) -> bool {
+        self.shutdown.is_cancelled()
+    }
+}
+
+impl Drop for PlaybackHandle {
+    fn drop(&mut self) {
+        self.shutdown.cancel();
+    }
+}
+
+/// Replays the node changes in `recording` into the live map.
+///
+/// Recorded nodes are inserted with fresh ids and only the `PLAYBACK_TAG`, so they never collide with live nodes
+/// and clearing a live source by its tags leaves the playback alone.
+/// They are removed again when the playback ends or is stopped.
+/// Device outputs in the recording are not replayed, they only reflect what the devices did at the time.
+pub fn start_playback(map: MapHandle, recording: Recording, options: PlaybackOptions) -> PlaybackHandle {
+    let token = CancellationToken::new();
+    let done = token.clone();
+    let speed = if options.speed > 0.0 { options.speed } else { 1.0 };
+    // lets this playback clean up after itself without touching one started after it.
+    let session_tag = format!("{}_{}", PLAYBACK_TAG, NodeId::new().0);
+
+    if !recording.header.nodes {
+        log::warn!("Recording has no input nodes, nothing will be played");
+    }
+
+    tokio::spawn(async move {
+        loop {
+            let finished = tokio::select! {
+                _ = done.cancelled() => true,
+                res = play_once(&map, &recording, speed, &session_tag) => {
+                    if let Err(e) = res {
+                        log::warn!("Playback stopped, map is no longer running: {e}");
+                        true
+                    } else {
+                        !options.looping
+                    }
+                }
+            };
+
+            let clear = InputEventMessage::RemoveWithTags(vec![session_tag.clone()]);
+            crate::log_err!(map.send_event(clear).await, "Unable to clear playback nodes");
+            if finished || recording.frames.is_empty() {
+                break;
+            }
+        }
+        done.cancel();
+    });
+
+    PlaybackHandle { shutdown: token }
+}
+
+async fn play_once(
+    map: &MapHandle,
+    recording: &Recording,
+    speed: f32,
+    session_tag: &str,
+) -> Result<(), tokio::sync::mpsc::error::SendError<InputEventMessage>> {
+    let start = tokio::time::Instant::now();
+    let mut ids: HashMap<NodeId, NodeId> = HashMap::new();
+
+    for frame in recording.frames.iter() {
+        let at = start + Duration::from_millis(frame.t).div_f32(speed);
+        tokio::time::sleep_until(at).await;
+
+        if !frame.removed.is_empty() {
+            let removed = frame.removed.iter().filter_map(|id| ids.remove(id)).collect();
+            map.send_event(InputEventMessage::RemoveNodes(removed)).await?;
+        }
+
+        for node in frame.added.iter() {
+            let id = NodeId(format!("{}-{}", PLAYBACK_TAG, NodeId::new().0));
+            // the recorded source tags are left off, or clearing that source live would take the playback with it.
+            // Its layering is resolved now instead so the playback still mixes like the source did.
+            let layer = node.layer.clone().or_else(|| source_layer(&node.tags));
+            let mut copy = InputNode::new(
+                node.haptic_node.clone(),
+                vec![PLAYBACK_TAG.to_string(), session_tag.to_string()],
+                id.clone(),
+                node.get_radius(),
+                node.input_type.clone(),
+            )
+            .with_layer(layer);
+            copy.set_intensity(node.get_intensity());
+
+            ids.insert(node.get_id().clone(), id);
+            map.send_event(InputEventMessage::InsertNode(copy)).await?;
+        }
+
+        for change in frame.changed.iter() {
+            let Some(id) = ids.get(&change.id) else {
+                continue;
+            };
+            if change.intensity.is_some() || change.radius.is_some() {
+                map.send_event(InputEventMessage::UpdateNode(id.clone(), change.intensity, change.radius))
+                    .await?;
+            }
+            if let Some(pos) = change.position {
+                map.send_event(InputEventMessage::MoveNode(id.clone(), pos)).await?;
+            }
+        }
+        map.mark_dirty();
+    }
+
+    Ok(())
+}
+
+/// The layering `resolve_mix` would pick for a node with `tags` and no layer of its own.
+fn source_layer(tags: &[String]) -> Option<Layering> {
+    let menu = crate::state::get_config().mapping_menu.load();
+    tags.iter()
+        .filter_map(|t| menu.source_layers.get(t))
+        .max_by_key(|l| l.priority)
+        .cloned()
+}
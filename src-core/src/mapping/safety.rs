@@ -0,0 +1,131 @@
+use std::time::{Duration, Instant};
+
+/// Protection against motors being held on for too long.
+///
+/// Tracks, per motor, how long the requested output has been continuously high and a rolling duty cycle.
+/// When either passes its cap the motor is faded down to `limited_level` until the request drops again.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+#[serde(default)]
+pub struct SafetyLimits {
+    pub enabled: bool,
+    /// Requested outputs at or above this count as "on".
+    pub high_threshold: f32,
+    /// Longest a motor may stay continuously on before it is limited.
+    pub max_on_time: Duration,
+    /// Window the duty cycle is averaged over.
+    pub duty_window: Duration,
+    /// Highest fraction of `duty_window` a motor may spend on.
+    pub max_duty: f32,
+    /// Output cap while limited.
+    pub limited_level: f32,
+    /// Time to fade between full output and `limited_level`.
+    pub fade_time: Duration,
+}
+
+impl Default for SafetyLimits {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            high_threshold: 0.8,
+            max_on_time: Duration::from_secs(15),
+            duty_window: Duration::from_secs(60),
+            max_duty: 0.75,
+            limited_level: 0.3,
+            fade_time: Duration::from_secs(1),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct MotorSafety {
+    /// Seconds the request has been continuously at or above the threshold.
+    on_time: f32,
+    /// Exponentially weighted fraction of time spent on.
+    duty: f32,
+    /// Current output cap, ramps between 1 and `limited_level`.
+    cap: f32,
+    engaged: bool,
+}
+
+impl Default for MotorSafety {
+    fn default() -> Self {
+        Self {
+            on_time: 0.0,
+            duty: 0.0,
+            cap: 1.0,
+            engaged: false,
+        }
+    }
+}
+
+/// The limiter changed state on a motor.
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+pub struct SafetyChange {
+    /// index of the motor in the devices output buffer
+    pub motor: usize,
+    /// true when limiting started, false when released.
+    pub engaged: bool,
+}
+
+/// Per device runtime state for `SafetyLimits`.
+#[derive(Debug, Default)]
+pub struct SafetyState {
+    motors: Vec<MotorSafety>,
+    last_run: Option<Instant>,
+}
+
+impl SafetyState {
+    /// Caps `buf` in place and returns any motors whose limiter engaged or released.
+    pub fn apply(&mut self, limits: &SafetyLimits, buf: &mut [f32]) -> Vec<SafetyChange> {
+        let now = Instant::now();
+        let elapsed = self
+            .last_run
+            .map(|last| now.duration_since(last).as_secs_f32())
+            .unwrap_or(0.0);
+        self.last_run = Some(now);
+
+        let mut changes = Vec::new();
+        if !limits.enabled {
+            // release anything still held so subscribers aren't left thinking it is limited.
+            for (motor, state) in self.motors.iter().enumerate() {
+                if state.engaged {
+                    changes.push(SafetyChange { motor, engaged: false });
+                }
+            }
+            self.motors.clear();
+            return changes;
+        }
+
+        self.motors.resize_with(buf.len(), MotorSafety::default);
+
+        let window = limits.duty_window.as_secs_f32();
+        let alpha = if window > 0.0 { 1.0 - (-elapsed / window).exp() } else { 1.0 };
+        let fade = limits.fade_time.as_secs_f32();
+        let max_step = if fade > 0.0 { elapsed / fade } else { f32::INFINITY };
+        let max_on = limits.max_on_time.as_secs_f32();
+
+        for (idx, (v, motor)) in buf.iter_mut().zip(self.motors.iter_mut()).enumerate() {
+            let on = *v >= limits.high_threshold;
+            motor.duty += ((on as u8 as f32) - motor.duty) * alpha;
+            motor.on_time = if on { motor.on_time + elapsed } else { 0.0 };
+
+            let over = motor.on_time > max_on || motor.duty > limits.max_duty;
+            // release once the request drops and the duty cycle has some headroom again.
+            let release = !on && motor.duty < limits.max_duty * 0.8;
+
+            if !motor.engaged && over {
+                motor.engaged = true;
+                changes.push(SafetyChange { motor: idx, engaged: true });
+            } else if motor.engaged && release {
+                motor.engaged = false;
+                changes.push(SafetyChange { motor: idx, engaged: false });
+            }
+
+            let target = if motor.engaged { limits.limited_level.clamp(0.0, 1.0) } else { 1.0 };
+            motor.cap += (target - motor.cap).clamp(-max_step, max_step);
+            *v = v.min(motor.cap);
+        }
+
+        changes
+    }
+}
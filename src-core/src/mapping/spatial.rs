@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+
+use super::{haptic_node::HapticNode, input_node::InputNode, layer::NodeMix, NodeGroup, NodeId};
+
+/// Edge length (meters) of a grid cell. Roughly the radius of a typical input node.
+pub const DEFAULT_CELL_SIZE: f32 = 0.1;
+
+/// Number of bits `NodeGroup::to_bitflag` can set.
+const GROUP_BITS: usize = 15;
+
+type Cell = (i32, i32, i32);
+
+/// Uniform grid over the input nodes, bucketed per `NodeGroup`.
+///
+/// Interpolation asks it for the input nodes near an output node instead of scanning all of them.
+/// Kept in sync with `InputIndex::sync`, which only touches nodes that moved unless the node list itself changed.
+#[derive(Debug)]
+pub struct InputIndex {
+    cell_size: f32,
+    buckets: Vec<GroupBucket>,
+    /// Nodes with `NodeGroup::All`, these interact with everything.
+    always: Vec<usize>,
+    /// What each node was indexed with, same order as the input nodes.
+    entries: Vec<Entry>,
+}
+
+#[derive(Debug, Default)]
+struct GroupBucket {
+    cells: HashMap<Cell, Vec<usize>>,
+    /// Largest radius inserted since the last rebuild, bounds how far a query looks.
+    max_radius: f32,
+}
+
+#[derive(Debug)]
+struct Entry {
+    id: NodeId,
+    placement: Placement,
+}
+
+/// Where a node sits in the index, cheap to rebuild and compare every tick.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Placement {
+    cell: Cell,
+    radius: f32,
+    groups: u16,
+    always: bool,
+}
+
+impl Default for InputIndex {
+    fn default() -> Self {
+        Self::new(DEFAULT_CELL_SIZE)
+    }
+}
+
+impl InputIndex {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            buckets: (0..GROUP_BITS).map(|_| GroupBucket::default()).collect(),
+            always: Vec::new(),
+            entries: Vec::new(),
+        }
+    }
+
+    /// Number of nodes currently indexed.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[inline]
+    fn cell_of(&self, x: f32, y: f32, z: f32) -> Cell {
+        (
+            (x / self.cell_size).floor() as i32,
+            (y / self.cell_size).floor() as i32,
+            (z / self.cell_size).floor() as i32,
+        )
+    }
+
+    fn placement_for(&self, node: &InputNode) -> Placement {
+        let h = &node.haptic_node;
+        Placement {
+            cell: self.cell_of(h.x, h.y, h.z),
+            radius: node.get_radius(),
+            groups: NodeGroup::to_bitflag(&h.groups),
+            always: node.always_apply(),
+        }
+    }
+
+    /// Brings the index up to date with `nodes`.
+    ///
+    /// If the same nodes are present in the same order only the ones that moved cells, changed radius or groups are re-inserted.
+    /// Otherwise the index is rebuilt.
+    pub fn sync(&mut self, nodes: &[InputNode]) {
+        let same_nodes = nodes.len() == self.entries.len()
+            && nodes.iter().zip(self.entries.iter()).all(|(n, e)| *n.get_id() == e.id);
+
+        if !same_nodes {
+            self.rebuild(nodes);
+            return;
+        }
+
+        // ids only get cloned on a rebuild, a steady map compares placements and nothing else.
+        for (idx, node) in nodes.iter().enumerate() {
+            let placement = self.placement_for(node);
+            let old = self.entries[idx].placement;
+            if placement != old {
+                self.entries[idx].placement = placement;
+                self.remove(idx, &old);
+                self.insert(idx, &placement);
+            }
+        }
+    }
+
+    /// Throws away everything and indexes `nodes` from scratch.
+    pub fn rebuild(&mut self, nodes: &[InputNode]) {
+        for bucket in self.buckets.iter_mut() {
+            bucket.cells.clear();
+            bucket.max_radius = 0.0;
+        }
+        self.always.clear();
+        self.entries.clear();
+
+        for (idx, node) in nodes.iter().enumerate() {
+            let placement = self.placement_for(node);
+            self.insert(idx, &placement);
+            self.entries.push(Entry {
+                id: node.get_id().clone(),
+                placement,
+            });
+        }
+    }
+
+    fn insert(&mut self, idx: usize, entry: &Placement) {
+        if entry.always {
+            self.always.push(idx);
+            return;
+        }
+
+        for bit in 0..GROUP_BITS {
+            if entry.groups & (1 << bit) == 0 {
+                continue;
+            }
+            let bucket = &mut self.buckets[bit];
+            bucket.cells.entry(entry.cell).or_default().push(idx);
+            bucket.max_radius = bucket.max_radius.max(entry.radius);
+        }
+    }
+
+    fn remove(&mut self, idx: usize, entry: &Placement) {
+        if entry.always {
+            self.always.retain(|i| *i != idx);
+            return;
+        }
+
+        for bit in 0..GROUP_BITS {
+            if entry.groups & (1 << bit) == 0 {
+                continue;
+            }
+            let bucket = &mut self.buckets[bit];
+            if let Some(cell) = bucket.cells.get_mut(&entry.cell) {
+                cell.retain(|i| *i != idx);
+                if cell.is_empty() {
+                    bucket.cells.remove(&entry.cell);
+                }
+            }
+        }
+    }
+
+    /// Fills `out` with the index of every input node that could influence `node`.
+    ///
+    /// This is a superset, callers still need to check distance and `HapticNode::interacts`.
+    pub fn candidates(&self, node: &HapticNode, out: &mut Vec<usize>) {
+        out.clear();
+
+        if node.groups.contains(&NodeGroup::All) {
+            out.extend(0..self.entries.len());
+            return;
+        }
+
+        out.extend_from_slice(&self.always);
+        let center = self.cell_of(node.x, node.y, node.z);
+        let groups = NodeGroup::to_bitflag(&node.groups);
+
+        for bit in 0..GROUP_BITS {
+            if groups & (1 << bit) == 0 {
+                continue;
+            }
+            let bucket = &self.buckets[bit];
+            if bucket.cells.is_empty() {
+                continue;
+            }
+
+            let reach = (bucket.max_radius / self.cell_size).ceil() as i32;
+            let span = (2 * reach as i64 + 1).checked_pow(3).unwrap_or(i64::MAX);
+            if span >= bucket.cells.len() as i64 {
+                // cheaper to walk every occupied cell than every cell in range.
+                for (cell, ids) in bucket.cells.iter() {
+                    if within(*cell, center, reach) {
+                        out.extend_from_slice(ids);
+                    }
+                }
+            } else {
+                for dx in -reach..=reach {
+                    for dy in -reach..=reach {
+                        for dz in -reach..=reach {
+                            let cell = (center.0 + dx, center.1 + dy, center.2 + dz);
+                            if let Some(ids) = bucket.cells.get(&cell) {
+                                out.extend_from_slice(ids);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        // a node in several groups lands in several buckets.
+        out.sort_unstable();
+        out.dedup();
+    }
+}
+
+#[inline]
+fn within(cell: Cell, center: Cell, reach: i32) -> bool {
+    (cell.0 - center.0).abs() <= reach
+        && (cell.1 - center.1).abs() <= reach
+        && (cell.2 - center.2).abs() <= reach
+}
+
+/// The input nodes handed to an interpolation algorithm, with the index and layering over them if available.
+#[derive(Clone, Copy)]
+pub struct InputView<'a> {
+    nodes: &'a [InputNode],
+    index: Option<&'a InputIndex>,
+    mix: Option<&'a [NodeMix]>,
+}
+
+impl<'a> InputView<'a> {
+    /// `index` must have been synced against `nodes`, otherwise it is ignored.
+    pub fn new(nodes: &'a [InputNode], index: &'a InputIndex) -> Self {
+        let index = (index.len() == nodes.len()).then_some(index);
+        Self { nodes, index, mix: None }
+    }
+
+    /// A view that scans every node, for when no index is kept.
+    pub fn unindexed(nodes: &'a [InputNode]) -> Self {
+        Self { nodes, index: None, mix: None }
+    }
+
+    /// Attaches the resolved layering of each node, see `layer::resolve_mix`. Ignored if the length doesn't match.
+    pub fn with_mix(mut self, mix: &'a [NodeMix]) -> Self {
+        self.mix = (mix.len() == self.nodes.len()).then_some(mix);
+        self
+    }
+
+    pub fn nodes(&self) -> &'a [InputNode] {
+        self.nodes
+    }
+
+    /// Layering of the node at `idx`, plain `BlendMode::Normal` without ducking if none was attached.
+    #[inline]
+    pub fn mix(&self, idx: usize) -> NodeMix {
+        self.mix.and_then(|m| m.get(idx).copied()).unwrap_or_default()
+    }
+
+    /// Fills `out` with the index of every input node that could influence `node`.
+    pub fn candidates(&self, node: &HapticNode, out: &mut Vec<usize>) {
+        match self.index {
+            Some(index) => index.candidates(node, out),
+            None => {
+                out.clear();
+                out.extend(0..self.nodes.len());
+            }
+        }
+    }
+}
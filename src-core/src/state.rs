@@ -2,6 +2,7 @@ use arc_swap::ArcSwap;
 use boxcar::Vec as AppendVec;
 use serde::{Deserialize, Serialize};
 use std::{
+    collections::HashMap,
     fs,
     path::PathBuf,
     sync::{Arc, LazyLock, OnceLock, atomic::AtomicBool},
@@ -9,7 +10,7 @@ use std::{
 };
 
 use crate::{
-    devices::DeviceId, log_err, mapping::interp::{GaussianState, InterpAlgo}
+    devices::{wifi::WifiSendRate, DeviceId}, log_err, mapping::{event::EventInterp, interp::{GaussianState, InterpAlgo}, layer::{default_source_layers, Layering}, post::{MotorOverride, PostStage}, safety::SafetyLimits}
 };
 
 // not intended to be accessed publicly. Use functions below
@@ -113,6 +114,8 @@ pub struct Config {
     pub devices: Devices,
     pub vrc_settings: ArcSwap<VrcSettings>,
     pub ui: ArcSwap<UiSettings>,
+    #[serde(default)]
+    pub bhaptics: ArcSwap<BhapticsSettings>,
 }
 
 #[cfg_attr(feature = "specta", derive(specta::Type))]
@@ -137,6 +140,15 @@ impl Default for UiSettings {
     }
 }
 
+#[cfg_attr(feature = "specta", derive(specta::Type))]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+/// Persistant state for the bHaptics game apis.
+pub struct BhapticsSettings {
+    /// Mapping version to use instead of the latest, by application id.
+    #[serde(default)]
+    pub pinned_versions: HashMap<String, i32>,
+}
+
 /// Handles all app state underneath the Device Manager
 #[derive(Debug)]
 pub struct Devices {
@@ -154,6 +166,19 @@ pub struct StandardMenu {
     pub intensity: f32,
     /// Flat enable or disable all haptics
     pub enable: bool,
+    /// Time taken to ramp from silent to full output when `enable` or `intensity` change.
+    #[serde(default = "default_menu_fade")]
+    pub fade_time: Duration,
+    /// Priority and blending of input nodes by source tag, for nodes and events that don't set their own.
+    #[serde(default = "default_source_layers")]
+    pub source_layers: HashMap<String, Layering>,
+    /// How events from each source tag move between their steps, sources not listed jump from step to step.
+    #[serde(default)]
+    pub source_interp: HashMap<String, EventInterp>,
+}
+
+fn default_menu_fade() -> Duration {
+    Duration::from_millis(250)
 }
 
 impl serde::Serialize for Devices {
@@ -218,7 +243,8 @@ impl Default for Config {
             },
             mapping_menu: ArcSwap::new(Arc::new(StandardMenu::default())),
             vrc_settings: ArcSwap::new(Arc::new(VrcSettings::default())),
-            ui: ArcSwap::new(Arc::new(UiSettings::default()))
+            ui: ArcSwap::new(Arc::new(UiSettings::default())),
+            bhaptics: ArcSwap::new(Arc::new(BhapticsSettings::default())),
         }
     }
 }
@@ -228,6 +254,9 @@ impl Default for StandardMenu {
         StandardMenu {
             intensity: 1.0,
             enable: true,
+            fade_time: default_menu_fade(),
+            source_layers: default_source_layers(),
+            source_interp: HashMap::new(),
         }
     }
 }
@@ -239,6 +268,10 @@ impl PerDevice {
             intensity: 1.0,
             offset: 0.01,
             interp_algo: InterpAlgo::Gaussian(GaussianState::default()),
+            post_process: Vec::new(),
+            motors: HashMap::new(),
+            safety: SafetyLimits::default(),
+            wifi_rate: WifiSendRate::default(),
         }
     }
 }
@@ -282,4 +315,17 @@ pub struct PerDevice {
     pub intensity: f32,
     pub offset: f32,
     pub interp_algo: InterpAlgo,
+    /// Applied in order to the interpolated output, see `PostStage`.
+    #[serde(default)]
+    pub post_process: Vec<PostStage>,
+    /// Overrides for individual motors, keyed by node index.
+    /// Kept here (by device id) so they survive reconnects.
+    #[serde(default)]
+    pub motors: HashMap<usize, MotorOverride>,
+    /// Caps on continuous on-time and duty cycle, applied last.
+    #[serde(default)]
+    pub safety: SafetyLimits,
+    /// Frame and keep-alive rate, only used by Wi-Fi devices.
+    #[serde(default)]
+    pub wifi_rate: WifiSendRate,
 }
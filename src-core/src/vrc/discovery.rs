@@ -1,11 +1,14 @@
 use super::parsing::{parse_incoming, remove_version, OscInfo};
 use super::{Avatar, GameMap, MsgToMainVrc, OscPath, VrcHandle, PREFAB_PREFIX};
 use crate::api::ApiManager;
+use crate::log_err;
 use crate::vrc::AVATAR_ID_PATH;
 
 use dashmap::DashMap;
 use libloading::Library;
+use mdns_sd::{ServiceDaemon, ServiceEvent};
 use std::collections::HashSet;
+use std::net::Ipv4Addr;
 use std::path::Path;
 use std::sync::Arc;
 use std::sync::OnceLock;
@@ -17,6 +20,13 @@ type PortCallback = unsafe extern "C" fn(u16, *const u8);
 type StartListener = unsafe extern "C" fn(PortCallback);
 type StopListener = unsafe extern "C" fn();
 
+/// Service type VRChat advertises its OSCQuery HTTP server under.
+const OSCQUERY_SERVICE: &str = "_oscjson._tcp.local.";
+/// Instance name prefix VRChat uses, e.g. `VRChat-Client-A1B2C3._oscjson._tcp.local.`
+const VRC_INSTANCE_PREFIX: &str = "VRChat-Client";
+/// How long mDNS gets to find a VRChat client before the listen-for-vrc library is tried instead.
+const MDNS_TIMEOUT: Duration = Duration::from_secs(10);
+
 static PORT_SENDER: OnceLock<Mutex<Option<mpsc::Sender<(u16, String)>>>> = OnceLock::new();
 
 unsafe extern "C" fn dispatch_port(port: u16, ip_ptr: *const u8) {
@@ -35,63 +45,186 @@ unsafe extern "C" fn dispatch_port(port: u16, ip_ptr: *const u8) {
     }
 }
 
-pub async fn start_filling_available_parameters(
-    vrc: VrcHandle,
-    api: &'static tokio::sync::Mutex<ApiManager>,
-    params: Arc<DashMap<OscPath, OscInfo>>,
-) {
-    tokio::spawn(async move {
-        let library_path = Path::new("./sidecars/listen-for-vrc.dll");
-        let library = match unsafe { Library::new(library_path) } {
-            Ok(lib) => lib,
-            Err(err) => {
-                log::error!("Failed to load VRC discovery library: {}", err);
-                return;
+/// Whichever discovery backend is currently feeding the `(port, ip)` channel.
+///
+/// Dropping it stops discovery.
+enum Discovery {
+    Mdns(ServiceDaemon),
+    Dll(DllListener),
+}
+
+impl Drop for Discovery {
+    fn drop(&mut self) {
+        match self {
+            Discovery::Mdns(daemon) => {
+                log_err!(daemon.stop_browse(OSCQUERY_SERVICE));
+                log_err!(daemon.shutdown());
             }
-        };
+            Discovery::Dll(_) => {}
+        }
+    }
+}
 
-        let start: libloading::Symbol<StartListener> =
-            match unsafe { library.get(b"vrc_start_listener\0") } {
-                Ok(symbol) => symbol,
-                Err(err) => {
-                    log::error!("Failed to load start symbol: {}", err);
-                    return;
-                }
-            };
+/// Picks the address to poll, VRChat almost always runs on this machine so loopback wins.
+///
+/// Otherwise the lowest address, so the choice doesn't depend on set iteration order.
+fn pick_address(addresses: impl IntoIterator<Item = Ipv4Addr>) -> Option<Ipv4Addr> {
+    addresses.into_iter().min_by_key(|ip| (!ip.is_loopback(), *ip))
+}
 
-        let stop: libloading::Symbol<StopListener> =
-            match unsafe { library.get(b"vrc_stop_listener\0") } {
-                Ok(symbol) => symbol,
-                Err(err) => {
-                    log::error!("Failed to load stop symbol: {}", err);
-                    return;
+/// Browses `_oscjson._tcp.local.` for VRChat clients and forwards their OSCQuery address to `tx`.
+fn start_mdns_browser(tx: mpsc::Sender<(u16, String)>) -> Result<ServiceDaemon, mdns_sd::Error> {
+    let daemon = ServiceDaemon::new()?;
+    let events = daemon.browse(OSCQUERY_SERVICE)?;
+
+    tokio::spawn(async move {
+        // fullnames we have already forwarded, so re-announcements don't restart polling.
+        let mut known: HashSet<String> = HashSet::new();
+
+        while let Ok(event) = events.recv_async().await {
+            match event {
+                ServiceEvent::ServiceResolved(info) => {
+                    let fullname = info.get_fullname().to_string();
+                    if !fullname.starts_with(VRC_INSTANCE_PREFIX) || known.contains(&fullname) {
+                        continue;
+                    }
+
+                    let addresses = info.get_addresses_v4().into_iter().map(|ip| Ipv4Addr::from(ip.octets()));
+                    let ip = match pick_address(addresses) {
+                        Some(ip) => ip,
+                        None => {
+                            log::warn!("VRChat OSCQuery service {} has no IPv4 address, assuming localhost", fullname);
+                            Ipv4Addr::LOCALHOST
+                        }
+                    }
+                    .to_string();
+
+                    log::info!("Resolved VRChat OSCQuery service {} at {}:{}", fullname, ip, info.get_port());
+                    known.insert(fullname);
+                    if tx.send((info.get_port(), ip)).await.is_err() {
+                        break;
+                    }
+                }
+                ServiceEvent::ServiceRemoved(_, fullname) => {
+                    if known.remove(&fullname) {
+                        log::debug!("VRChat OSCQuery service removed: {}", fullname);
+                    }
                 }
-            };
+                ServiceEvent::SearchStopped(_) => break,
+                _ => {}
+            }
+        }
+        log::debug!("mDNS OSCQuery browser exited");
+    });
+
+    Ok(daemon)
+}
 
-        let mut receiver = {
-            let (tx, rx) = mpsc::channel::<(u16, String)>(2);
+/// Legacy discovery through the Windows-only `listen-for-vrc` sidecar.
+struct DllListener {
+    library: Library,
+}
+
+impl DllListener {
+    /// Loads the sidecar and starts it dispatching into `tx`.
+    async fn start(tx: mpsc::Sender<(u16, String)>) -> Result<Self, libloading::Error> {
+        let library_path = Path::new("./sidecars/listen-for-vrc.dll");
+        let library = unsafe { Library::new(library_path) }?;
+
+        {
             let storage = PORT_SENDER.get_or_init(|| Mutex::new(None));
             let mut guard = storage.lock().await;
             *guard = Some(tx);
-            rx
-        };
-
-        unsafe { start(dispatch_port); }
+        }
 
-        while let Some((port, ip)) = receiver.recv().await {
-            log::debug!("VRC discovery: {}:{}", ip, port);
-            run_vrc_http_polling(port, &ip, &params, vrc.clone(), &api).await;
-            vrc.send(MsgToMainVrc::VrcDisconnected).await;
+        {
+            let start: libloading::Symbol<StartListener> =
+                unsafe { library.get(b"vrc_start_listener\0") }?;
+            // make sure stop is available before starting anything.
+            let _: libloading::Symbol<StopListener> = unsafe { library.get(b"vrc_stop_listener\0") }?;
+            unsafe { start(dispatch_port); }
         }
 
-        unsafe {
-            stop();
+        Ok(Self { library })
+    }
+}
+
+impl Drop for DllListener {
+    fn drop(&mut self) {
+        if let Ok(stop) = unsafe { self.library.get::<StopListener>(b"vrc_stop_listener\0") } {
+            unsafe { stop(); }
         }
 
         if let Some(lock) = PORT_SENDER.get() {
-            let mut guard = lock.blocking_lock();
+            if let Ok(mut guard) = lock.try_lock() {
+                *guard = None;
+            }
+        }
+    }
+}
+
+/// Starts discovery, preferring mDNS and falling back to the listen-for-vrc library
+/// when mDNS can't start or resolves no VRChat client within `MDNS_TIMEOUT`.
+///
+/// Returns the running backend, along with the client mDNS found if it found one in time.
+async fn start_discovery(
+    tx: mpsc::Sender<(u16, String)>,
+    receiver: &mut mpsc::Receiver<(u16, String)>,
+) -> Option<(Discovery, Option<(u16, String)>)> {
+    let mdns = match start_mdns_browser(tx.clone()) {
+        Ok(daemon) => Some(Discovery::Mdns(daemon)),
+        Err(err) => {
+            log::warn!("mDNS discovery unavailable ({}), falling back to listen-for-vrc library", err);
+            None
+        }
+    };
 
-            *guard = None;
+    if mdns.is_some() {
+        match tokio::time::timeout(MDNS_TIMEOUT, receiver.recv()).await {
+            Ok(Some(found)) => return mdns.map(|mdns| (mdns, Some(found))),
+            Ok(None) => return None,
+            Err(_) => log::warn!(
+                "mDNS found no VRChat client within {:?}, falling back to listen-for-vrc library",
+                MDNS_TIMEOUT
+            ),
+        }
+    }
+
+    match DllListener::start(tx).await {
+        // replacing mDNS here stops its browser.
+        Ok(listener) => Some((Discovery::Dll(listener), None)),
+        Err(err) => match mdns {
+            Some(mdns) => {
+                log::warn!("Failed to load VRC discovery library ({}), staying on mDNS", err);
+                Some((mdns, None))
+            }
+            None => {
+                log::error!("Failed to load VRC discovery library: {}", err);
+                None
+            }
+        },
+    }
+}
+
+pub async fn start_filling_available_parameters(
+    vrc: VrcHandle,
+    api: &'static tokio::sync::Mutex<ApiManager>,
+    params: Arc<DashMap<OscPath, OscInfo>>,
+) {
+    tokio::spawn(async move {
+        let (tx, mut receiver) = mpsc::channel::<(u16, String)>(2);
+
+        let Some((_discovery, mut pending)) = start_discovery(tx, &mut receiver).await else {
+            return;
+        };
+
+        while let Some((port, ip)) = match pending.take() {
+            Some(found) => Some(found),
+            None => receiver.recv().await,
+        } {
+            log::debug!("VRC discovery: {}:{}", ip, port);
+            run_vrc_http_polling(port, &ip, &params, vrc.clone(), &api).await;
+            vrc.send(MsgToMainVrc::VrcDisconnected).await;
         }
     });
 }
@@ -306,3 +439,18 @@ pub fn get_prefab_info(map: &DashMap<OscPath, OscInfo>) -> Option<Vec<(String, S
         Some(results)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loopback_is_preferred_then_the_lowest_address() {
+        let lan = Ipv4Addr::new(192, 168, 1, 20);
+        let other = Ipv4Addr::new(10, 0, 0, 5);
+        assert_eq!(pick_address([lan, Ipv4Addr::LOCALHOST, other]), Some(Ipv4Addr::LOCALHOST));
+        assert_eq!(pick_address([lan, other]), Some(other));
+        assert_eq!(pick_address([other, lan]), Some(other));
+        assert_eq!(pick_address([]), None);
+    }
+}
@@ -0,0 +1,37 @@
+//! Helpers shared by the integration tests.
+// every test binary pulls in this module, and none of them use all of it.
+#![allow(dead_code)]
+use std::time::Duration;
+
+use haptic_core::glam::Vec3;
+use haptic_core::mapping::haptic_node::HapticNode;
+use haptic_core::mapping::input_node::{InputNode, InputType};
+use haptic_core::mapping::{NodeGroup, NodeId};
+
+/// Polls `f` until it holds, panicking after five seconds.
+pub async fn wait_for(what: &str, mut f: impl FnMut() -> bool) {
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while !f() {
+        assert!(tokio::time::Instant::now() < deadline, "timed out waiting for {what}");
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+}
+
+/// A motor at the origin, reached by every input.
+pub fn motor() -> HapticNode {
+    HapticNode::new(Vec3::ZERO, vec![NodeGroup::All])
+}
+
+/// `count` motors in a row across the front of the torso.
+pub fn motors(count: usize) -> Vec<HapticNode> {
+    (0..count)
+        .map(|i| HapticNode::new(Vec3::new(i as f32 * 0.1, 1.0, 0.0), vec![NodeGroup::TorsoFront]))
+        .collect()
+}
+
+/// An interpolated input on top of `motor()`.
+pub fn input(id: NodeId, tag: &str, radius: f32, intensity: f32) -> InputNode {
+    let mut node = InputNode::new(motor(), vec![tag.to_string()], id, radius, InputType::INTERP);
+    node.set_intensity(intensity);
+    node
+}
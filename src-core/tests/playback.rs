@@ -0,0 +1,106 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{input, wait_for};
+use haptic_core::devices::DeviceManager;
+use haptic_core::mapping::input_node::InputNode;
+use haptic_core::mapping::recording::{
+    start_playback, Frame, NodeChange, PlaybackOptions, Recording, RecordingHeader, PLAYBACK_TAG, RECORDING_VERSION,
+};
+use haptic_core::mapping::{start_interp_map, InputEventMessage, MapHandle, NodeId};
+
+fn node(id: &str, tag: &str, intensity: f32) -> InputNode {
+    input(NodeId(id.to_string()), tag, 0.1, intensity)
+}
+
+fn change(id: &str, intensity: f32) -> NodeChange {
+    NodeChange {
+        id: NodeId(id.to_string()),
+        intensity: Some(intensity),
+        radius: None,
+        position: None,
+    }
+}
+
+/// A VRC node that turns up to full after 200ms, and stays around for a second.
+fn vrc_recording() -> Recording {
+    Recording {
+        header: RecordingHeader {
+            version: RECORDING_VERSION,
+            tick: Duration::from_millis(10),
+            started: 0,
+            nodes: true,
+            outputs: false,
+        },
+        frames: vec![
+            Frame {
+                t: 0,
+                added: vec![node("recorded", "VRC", 0.5)],
+                ..Default::default()
+            },
+            Frame {
+                t: 200,
+                changed: vec![change("recorded", 1.0)],
+                ..Default::default()
+            },
+            Frame {
+                t: 1000,
+                changed: vec![change("recorded", 0.25)],
+                ..Default::default()
+            },
+        ],
+    }
+}
+
+fn playback_intensity(map: &MapHandle) -> Option<f32> {
+    map.with_nodes(|nodes| {
+        nodes
+            .iter()
+            .find(|n| n.tags.iter().any(|t| t == PLAYBACK_TAG))
+            .map(|n| n.get_intensity())
+    })
+}
+
+#[tokio::test]
+async fn playback_survives_removing_its_source_tags() {
+    let manager = DeviceManager::new();
+    let map = start_interp_map(&manager.get_handle()).await;
+    let _playback = start_playback(map.clone(), vrc_recording(), PlaybackOptions::default());
+
+    wait_for("the playback node", || playback_intensity(&map).is_some()).await;
+    assert!(
+        map.with_nodes(|nodes| nodes.iter().all(|n| !n.tags.iter().any(|t| t == "VRC"))),
+        "playback nodes should not carry the recorded source tags"
+    );
+
+    // what VRChat disconnecting does.
+    map.send_event(InputEventMessage::RemoveWithTags(vec!["VRC".to_string()]))
+        .await
+        .unwrap();
+    // events are handled in order, once this lands the removal has been too.
+    map.send_event(InputEventMessage::InsertNode(node("marker", "UI", 0.0)))
+        .await
+        .unwrap();
+    let marker = NodeId("marker".to_string());
+    wait_for("the removal to be handled", || map.with_node(&marker, |_| ()).is_some()).await;
+    assert!(playback_intensity(&map).is_some(), "clearing VRC removed the playback node");
+
+    wait_for("the recorded change to be played", || playback_intensity(&map) == Some(1.0)).await;
+}
+
+#[tokio::test]
+async fn map_keeps_running_after_updating_a_missing_node() {
+    let manager = DeviceManager::new();
+    let map = start_interp_map(&manager.get_handle()).await;
+
+    map.send_event(InputEventMessage::UpdateNode(NodeId("missing".to_string()), Some(1.0), None))
+        .await
+        .unwrap();
+    map.send_event(InputEventMessage::InsertNode(node("live", "VRC", 1.0)))
+        .await
+        .unwrap();
+
+    let id = NodeId("live".to_string());
+    wait_for("the node inserted after the bad update", || map.with_node(&id, |_| ()).is_some()).await;
+}
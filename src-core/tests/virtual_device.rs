@@ -0,0 +1,92 @@
+//! Drives the whole server end to end with `VirtualDevice`s standing in for hardware.
+mod common;
+
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use common::{input, motor, wait_for};
+use haptic_core::devices::virtual_device::VirtualDevice;
+use haptic_core::devices::DeviceHandle;
+use haptic_core::file::AppRoot;
+use haptic_core::mapping::{InputEventMessage, MapHandle, NodeId};
+use tokio::runtime::Runtime;
+use tokio::sync::OnceCell;
+
+/// The server keeps global state, so every test shares one instance on one runtime.
+static RUNTIME: LazyLock<Runtime> = LazyLock::new(|| Runtime::new().expect("failed to start test runtime"));
+static SERVER: OnceCell<(MapHandle, DeviceHandle)> = OnceCell::const_new();
+
+async fn server() -> &'static (MapHandle, DeviceHandle) {
+    SERVER
+        .get_or_init(|| async {
+            let dir = std::env::temp_dir().join(format!("haptic-core-virtual-{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let root = AppRoot::from_path(&dir.to_string_lossy()).unwrap();
+            let (_vrc, map, _bhaptics, devices) = haptic_core::start_server(root).await;
+            (map, devices)
+        })
+        .await
+}
+
+/// A full intensity input sitting right on top of every motor.
+async fn drive(map: &MapHandle, tag: &str) {
+    let node = input(NodeId::new(), tag, 0.5, 1.0);
+    map.send_event(InputEventMessage::InsertNode(node)).await.unwrap();
+    map.mark_dirty();
+}
+
+fn active(output: Option<Vec<f32>>) -> bool {
+    output.is_some_and(|o| !o.is_empty() && o.iter().all(|v| *v > 0.0))
+}
+
+#[test]
+fn map_output_reaches_a_virtual_device() {
+    RUNTIME.block_on(async {
+        let (map, devices) = server().await;
+        let device = VirtualDevice::new("virtual-output", vec![motor(), motor()]);
+        device.connect(devices.get_device_channel()).await;
+        wait_for("the device to register", || devices.exists(&"virtual-output".into())).await;
+
+        drive(map, "VirtualOutput").await;
+        wait_for("output on every motor", || active(device.last_output())).await;
+        assert!(device.history().iter().all(|frame| frame.len() == 2));
+    });
+}
+
+#[test]
+fn dropped_virtual_device_stops_recording() {
+    RUNTIME.block_on(async {
+        let (map, devices) = server().await;
+        let id = "virtual-dropped".into();
+        let device = VirtualDevice::new("virtual-dropped", vec![motor()]);
+        device.connect(devices.get_device_channel()).await;
+        drive(map, "VirtualDropped").await;
+        wait_for("output before dropping", || active(device.last_output())).await;
+
+        device.drop_connection().await;
+        assert!(!device.is_connected());
+        wait_for("the manager to remove the device", || !devices.exists(&id)).await;
+
+        device.clear_history();
+        map.mark_dirty();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(device.history().is_empty(), "a dropped device kept receiving output");
+    });
+}
+
+#[test]
+fn set_nodes_resizes_virtual_device_output() {
+    RUNTIME.block_on(async {
+        let (map, devices) = server().await;
+        let device = VirtualDevice::new("virtual-resized", vec![motor()]);
+        device.connect(devices.get_device_channel()).await;
+        drive(map, "VirtualResized").await;
+        wait_for("output on the single motor", || device.last_output().is_some_and(|o| o.len() == 1)).await;
+
+        device.set_nodes(vec![motor(), motor(), motor()]);
+        wait_for("output on all three motors", || {
+            device.last_output().is_some_and(|o| o.len() == 3) && active(device.last_output())
+        })
+        .await;
+    });
+}
@@ -0,0 +1,182 @@
+//! Runs `WifiDevice` against the simulated firmware over localhost.
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use common::{motors, wait_for};
+use haptic_core::devices::wifi::simulator::{SimulatedFirmware, SimulatorConfig};
+use haptic_core::devices::wifi::WifiDevice;
+use haptic_core::devices::{Device, DeviceId, DeviceInfo, DeviceMessage};
+use haptic_core::state;
+use tokio::sync::mpsc;
+
+async fn simulator(mac: &str, count: usize) -> SimulatedFirmware {
+    simulator_with(SimulatorConfig {
+        mac: mac.to_string(),
+        nodes: motors(count),
+        ..Default::default()
+    })
+    .await
+}
+
+async fn simulator_with(config: SimulatorConfig) -> SimulatedFirmware {
+    SimulatedFirmware::start(SimulatorConfig {
+        heartbeat: Duration::from_millis(100),
+        ..config
+    })
+    .await
+    .unwrap()
+}
+
+/// Connects a device to `sim` the way discovery would, handing back the manager side of its channel.
+async fn connect(sim: &SimulatedFirmware) -> (WifiDevice, mpsc::Receiver<DeviceMessage>) {
+    let (tx, rx) = mpsc::channel(32);
+    let device = WifiDevice::new(
+        sim.mac(),
+        sim.addr().ip().to_string(),
+        sim.addr().port(),
+        "Simulated".to_string(),
+        tx,
+    )
+    .await
+    .expect("device should start");
+    (device, rx)
+}
+
+fn device_nodes(device: &WifiDevice) -> usize {
+    match device.info() {
+        DeviceInfo::Wifi(info) => info.nodes.len(),
+        other => panic!("wifi device reported {other:?}"),
+    }
+}
+
+fn close(a: &[f32], b: &[f32]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-3)
+}
+
+#[tokio::test]
+async fn new_device_pings_and_loads_the_config() {
+    let sim = simulator("SI:MU:LA:TE:00:01", 2).await;
+    let (device, _rx) = connect(&sim).await;
+
+    wait_for("the ping", || sim.pinged()).await;
+    wait_for("the node map to be read", || device_nodes(&device) == 2).await;
+    wait_for("the platform query", || sim.commands().iter().any(|c| c == "GET PLATFORM")).await;
+    assert_eq!(sim.commands().first().map(String::as_str), Some("get all"));
+}
+
+#[tokio::test]
+async fn tick_sends_the_output_buffer() {
+    let sim = simulator("SI:MU:LA:TE:00:02", 2).await;
+    let (device, _rx) = connect(&sim).await;
+    wait_for("the node map to be read", || device_nodes(&device) == 2).await;
+
+    *device.get_feedback_buffer().write() = vec![1.0, 0.5];
+    device.buffer_updated();
+    wait_for("the frame to arrive", || sim.last_frame().is_some_and(|f| close(&f, &[1.0, 0.5]))).await;
+    // boards that list no binary frame formats get hex frames, which carry no sequence number.
+    assert_eq!(sim.last_seq(), None);
+}
+
+#[tokio::test]
+async fn binary_frames_need_the_capability() {
+    let sim = simulator_with(SimulatorConfig {
+        mac: "SI:MU:LA:TE:00:05".to_string(),
+        nodes: motors(2),
+        // the config schema version alone must not switch the frame format.
+        config_version: 3,
+        frame_formats: vec!["hb16".to_string()],
+        ..Default::default()
+    })
+    .await;
+    let (device, _rx) = connect(&sim).await;
+    wait_for("the node map to be read", || device_nodes(&device) == 2).await;
+
+    *device.get_feedback_buffer().write() = vec![0.25, 1.0];
+    device.buffer_updated();
+    wait_for("the frame to arrive", || sim.last_frame().is_some_and(|f| close(&f, &[0.25, 1.0]))).await;
+    assert!(sim.last_seq().is_some(), "a board listing hb16 should get binary frames");
+}
+
+#[tokio::test]
+async fn silence_is_only_resent_a_few_times() {
+    let sim = simulator("SI:MU:LA:TE:00:06", 2).await;
+    let (device, _rx) = connect(&sim).await;
+    wait_for("the node map to be read", || device_nodes(&device) == 2).await;
+
+    *device.get_feedback_buffer().write() = vec![1.0, 0.5];
+    device.buffer_updated();
+    wait_for("the frame to arrive", || sim.last_frame().is_some_and(|f| close(&f, &[1.0, 0.5]))).await;
+    *device.get_feedback_buffer().write() = vec![0.0, 0.0];
+    device.buffer_updated();
+    wait_for("the silent frame to arrive", || sim.last_frame().is_some_and(|f| close(&f, &[0.0, 0.0]))).await;
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    sim.clear_frames();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(sim.frames().is_empty(), "silent frames kept being resent every tick");
+}
+
+#[tokio::test]
+async fn holding_firmware_gets_fewer_keep_alives() {
+    let sim = simulator_with(SimulatorConfig {
+        mac: "SI:MU:LA:TE:00:07".to_string(),
+        nodes: motors(1),
+        frame_formats: vec!["hold".to_string()],
+        ..Default::default()
+    })
+    .await;
+    let (device, _rx) = connect(&sim).await;
+    wait_for("the node map to be read", || device_nodes(&device) == 1).await;
+
+    *device.get_feedback_buffer().write() = vec![0.5];
+    device.buffer_updated();
+    wait_for("the frame to arrive", || sim.last_frame().is_some_and(|f| close(&f, &[0.5]))).await;
+    sim.clear_frames();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(sim.frames().len() <= 1, "unchanged frames were resent every tick");
+}
+
+#[tokio::test]
+async fn missing_heartbeats_remove_the_device() {
+    state::get_config().devices.wifi_device_timeout.store(Arc::new(1.0));
+    let sim = simulator("SI:MU:LA:TE:00:03", 1).await;
+    let (device, mut rx) = connect(&sim).await;
+    wait_for("the node map to be read", || device_nodes(&device) == 1).await;
+
+    sim.set_heartbeat(false);
+    let removed = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            match rx.recv().await {
+                Some(DeviceMessage::Remove(id)) => return id,
+                Some(_) => continue,
+                None => panic!("device dropped its manager channel"),
+            }
+        }
+    })
+    .await
+    .expect("device was never removed after heartbeats stopped");
+    assert_eq!(removed, DeviceId(sim.mac()));
+}
+
+#[tokio::test]
+async fn edited_node_map_is_pushed_and_read_back() {
+    let sim = simulator("SI:MU:LA:TE:00:04", 1).await;
+    let (device, _rx) = connect(&sim).await;
+    wait_for("the node map to be read", || device_nodes(&device) == 1).await;
+
+    let DeviceInfo::Wifi(mut info) = device.info() else {
+        panic!("wifi device reported another info type");
+    };
+    info.nodes = motors(3);
+    device.update_info(DeviceInfo::Wifi(info));
+
+    wait_for("the board to store the map", || sim.nodes().len() == 3).await;
+    // the board confirms with "set to", which makes the device query its config again.
+    wait_for("the config to be read again", || {
+        sim.commands().iter().filter(|c| *c == "get all").count() >= 2
+    })
+    .await;
+    wait_for("the device to report the new map", || device_nodes(&device) == 3).await;
+}
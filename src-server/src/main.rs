@@ -0,0 +1,195 @@
+#![warn(unused_extern_crates)]
+// Keep Futures from being left un-awaited. Use haptic_core::log_err for convenient handling.
+#![deny(unused_must_use)]
+
+use haptic_core::control::{start_control_api, DEFAULT_CONTROL_ADDR, TOKEN_FILE};
+use haptic_core::file::AppRoot;
+use haptic_core::state;
+
+use std::net::SocketAddr;
+use std::panic::{set_hook, take_hook};
+use std::path::PathBuf;
+
+const USAGE: &str = "Usage: vrch-server <app-root> [--log-file <path>] [--log-level <error|warn|info|debug|trace>] [--api [<ip:port>]]";
+
+/// Options parsed from the command line.
+struct Args {
+    root: PathBuf,
+    log_file: Option<PathBuf>,
+    log_level: log::LevelFilter,
+    /// Address to serve the local control api on, disabled if `None`.
+    api: Option<SocketAddr>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut root = None;
+    let mut log_file = None;
+    let mut log_level = log::LevelFilter::Info;
+    let mut api = None;
+
+    let mut args = std::env::args().skip(1).peekable();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            "--log-file" => {
+                let path = args.next().ok_or("--log-file requires a path")?;
+                log_file = Some(PathBuf::from(path));
+            }
+            "--log-level" => {
+                let level = args.next().ok_or("--log-level requires a level")?;
+                log_level = level
+                    .parse()
+                    .map_err(|_| format!("Unknown log level: {}", level))?;
+            }
+            "--api" => {
+                let addr = match args.peek() {
+                    Some(next) if !next.starts_with('-') && next.contains(':') => {
+                        let next = args.next().unwrap();
+                        next.parse()
+                            .map_err(|_| format!("Invalid api address: {}", next))?
+                    }
+                    _ => SocketAddr::from(DEFAULT_CONTROL_ADDR),
+                };
+                api = Some(addr);
+            }
+            other if root.is_none() && !other.starts_with('-') => {
+                root = Some(PathBuf::from(other));
+            }
+            other => return Err(format!("Unexpected argument: {}\n{}", other, USAGE)),
+        }
+    }
+
+    Ok(Args {
+        root: root.ok_or(USAGE)?,
+        log_file,
+        log_level,
+        api,
+    })
+}
+
+/// Logs to stdout, and to `log_file` as well if given.
+fn init_logging(level: log::LevelFilter, log_file: Option<&PathBuf>) -> Result<(), fern::InitError> {
+    let mut dispatch = fern::Dispatch::new()
+        .format(|out, message, record| {
+            out.finish(format_args!(
+                "[{}][{}][{}] {}",
+                chrono::Local::now().format("%Y-%m-%d %H:%M:%S%.3f"),
+                record.level(),
+                record.target(),
+                message
+            ))
+        })
+        .level(level)
+        .filter(|metadata| {
+            !metadata.target().starts_with("mio")
+                && !metadata.target().starts_with("reqwest")
+                && !metadata.target().starts_with("btleplug")
+        })
+        .chain(std::io::stdout());
+
+    if let Some(path) = log_file {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        dispatch = dispatch.chain(fern::log_file(path)?);
+    }
+
+    dispatch.apply()?;
+    Ok(())
+}
+
+/// Resolves once SIGINT (or SIGTERM on unix) is received.
+async fn wait_for_shutdown() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut term = match signal(SignalKind::terminate()) {
+            Ok(term) => term,
+            Err(e) => {
+                log::error!("Unable to listen for SIGTERM: {}", e);
+                let _ = tokio::signal::ctrl_c().await;
+                return;
+            }
+        };
+
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => log::info!("Received SIGINT"),
+            _ = term.recv() => log::info!("Received SIGTERM"),
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+        log::info!("Received Ctrl-C");
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(msg) => {
+            eprintln!("{}", msg);
+            std::process::exit(2);
+        }
+    };
+
+    if let Err(e) = init_logging(args.log_level, args.log_file.as_ref()) {
+        eprintln!("Failed to initialize logging: {}", e);
+        std::process::exit(1);
+    }
+
+    let default_panic = take_hook();
+    set_hook(Box::new(move |info| {
+        log::logger().flush(); // flush previous logs
+        log::error!("Panic Captured: {info}");
+        log::logger().flush(); // flush added info.
+        default_panic(info);
+    }));
+
+    if let Err(e) = std::fs::create_dir_all(&args.root) {
+        log::error!("Unable to create app root {:?}: {}", args.root, e);
+        std::process::exit(1);
+    }
+    let Some(root) = AppRoot::from_path(&args.root.to_string_lossy()) else {
+        log::error!("Unable to initialize app root at {:?}", args.root);
+        std::process::exit(1);
+    };
+
+    log::info!("Starting haptics server in {:?}", args.root);
+    let (vrc, map, bhaptics, devices) = haptic_core::start_server(root).await;
+
+    let control = match args.api {
+        Some(addr) => match start_control_api(addr, vrc, map, devices.clone()).await {
+            Ok(control) => {
+                log::info!("Control api token is in {:?}", args.root.join(TOKEN_FILE));
+                Some(control)
+            }
+            Err(e) => {
+                log::error!("Unable to start control api: {}", e);
+                None
+            }
+        },
+        None => None,
+    };
+    log::info!("Server started, waiting for shutdown signal.");
+
+    wait_for_shutdown().await;
+
+    log::info!("Cleaning up and Shutting Down.");
+    state::save_config();
+
+    if let Some(control) = control {
+        log::trace!("Shutdown control api");
+        control.shutdown();
+    }
+
+    log::trace!("Shutdown bhaptics server");
+    bhaptics.shutdown();
+
+    log::trace!("Shutdown device manager");
+    devices.shutdown().await;
+
+    log::logger().flush();
+}
@@ -6,6 +6,8 @@ use crate::mapping::event::Event;
 use crate::mapping::haptic_node::HapticNode;
 use crate::mapping::{InputEventMessage};
 use crate::log_err;
+use crate::bhaptics::patterns::{self, RotationOption, ScaleOption, TactPattern};
+use crate::bhaptics::game::network::{self as bhaptics_network, CachedMapping};
 
 use crate::vrc::{config::GameMap};
 //standard imports
@@ -72,7 +74,72 @@ pub fn set_node_radius(
     }
 }
 
-const EPSILON: f32 = 0.001;
+/// Lists the bHaptics .tact files in the patterns folder.
+#[tauri::command]
+#[specta::specta]
+pub async fn list_patterns() -> Vec<String> {
+    patterns::list_patterns().await
+}
+
+/// Plays a .tact file from the patterns folder onto the map, returns the number of events started.
+#[tauri::command]
+#[specta::specta]
+pub async fn play_pattern(
+    name: String,
+    intensity: f32,
+    duration: f32,
+    offset_angle_x: f32,
+    offset_y: f32,
+    map: tauri::State<'_, MapHandle>,
+) -> Result<u32, String> {
+    let pattern = TactPattern::load(&name).await.map_err(|e| format!("{:?}", e))?;
+    let scale = ScaleOption { intensity, duration };
+    let rotation = RotationOption { offset_angle_x, offset_y };
+    patterns::play_pattern(&map, &pattern, scale, rotation)
+        .await
+        .map(|count| count as u32)
+        .map_err(|e| format!("{:?}", e))
+}
+
+/// Stops every pattern started with `play_pattern`.
+#[tauri::command]
+#[specta::specta]
+pub async fn stop_patterns(map: tauri::State<'_, MapHandle>) -> Result<(), String> {
+    patterns::stop_patterns(&map).await.map_err(|e| format!("{:?}", e))
+}
+
+/// Lists the bHaptics game mappings cached on disk.
+#[tauri::command]
+#[specta::specta]
+pub fn list_bhaptics_mappings() -> Vec<CachedMapping> {
+    bhaptics_network::list_cached()
+}
+
+/// Pins a game to a mapping version, or back to the latest when version is null.
+#[tauri::command]
+#[specta::specta]
+pub fn pin_bhaptics_mapping(app_id: String, version: Option<i32>) {
+    bhaptics_network::pin_version(&app_id, version);
+}
+
+/// Writes the cached mappings for app_ids (all when empty) to a bundle file, returns how many were written.
+#[tauri::command]
+#[specta::specta]
+pub fn export_bhaptics_mappings(path: String, app_ids: Vec<String>) -> Result<u32, String> {
+    let bundle = bhaptics_network::export_bundle(&app_ids);
+    bhaptics_network::write_bundle(&path.into(), &bundle).map_err(|e| format!("{:?}", e))?;
+    Ok(bundle.mappings.len() as u32)
+}
+
+/// Imports a bundle file written by `export_bhaptics_mappings`, returns how many mappings were imported.
+#[tauri::command]
+#[specta::specta]
+pub fn import_bhaptics_mappings(path: String) -> Result<u32, String> {
+    let bundle = bhaptics_network::read_bundle(&path.into()).map_err(|e| format!("{:?}", e))?;
+    bhaptics_network::import_bundle(&bundle)
+        .map(|count| count as u32)
+        .map_err(|e| format!("{:?}", e))
+}
 
 /// Swaps the haptic node indices on the given device id
 #[tauri::command]
@@ -86,33 +153,9 @@ pub fn swap_conf_nodes(
     devices
         .with_device_mut(&device_id.clone().into(), |d| {
             let mut info = d.info();
-            let mut nodes = info.get_nodes().to_owned();
-
-            let mut index1: Option<usize> = None;
-            let mut index2: Option<usize> = None;
-
-            for (index, node) in nodes.iter().enumerate() {
-                if node.to_vec3().abs_diff_eq(pos1, EPSILON) {
-                    index1 = Some(index);
-                    log::debug!("Found node 1 at index: {}", index);
-                } else if node.to_vec3().abs_diff_eq(pos2, EPSILON) {
-                    index2 = Some(index);
-                    log::debug!("Found node 2 at index: {}", index);
-                }
-            }
-
-            match (index1, index2) {
-                (Some(i1), Some(i2)) => {
-                    nodes.swap(i1, i2);
-                    info.set_nodes(nodes);
-                    d.update_info(info);
-                    Ok(())
-                }
-                _ => Err(format!(
-                    "Could not find both nodes at {:?} and {:?}",
-                    pos1, pos2
-                )),
-            }
+            info.swap_nodes(pos1, pos2)?;
+            d.update_info(info);
+            Ok(())
         })
         .unwrap_or_else(|| Err(format!("No device with id: {:?}", device_id)))
 }
@@ -126,6 +169,8 @@ pub fn play_point(
     duration: f32,                      // When should this point be removed.
     map: tauri::State<'_, MapHandle>,
 ) -> Result<(), ()> {
+    let duration = Duration::try_from_secs_f32(duration.max(0.))
+        .map_err(|e| log::warn!("Invalid play point duration: {}", e))?;
     let event = Event::new(
         "Play Point".to_string(),
         crate::mapping::event::EventEffectType::Location(Vec3 {
@@ -134,10 +179,10 @@ pub fn play_point(
             z: feedback_location.2,
         }),
         vec![power],
-        Duration::from_secs_f32(duration),
+        duration,
         vec!["UI".to_string()],
     )
-    .expect("unable to create play point event");
+    .map_err(|e| log::warn!("Unable to create play point event: {:?}", e))?;
 
     log_err!(map.send_event_blocking(InputEventMessage::StartEvent(event)));
     return Ok(());
@@ -78,6 +78,13 @@ async fn main() {
             commands::set_node_radius,
             commands::get_device_esp_model,
             commands::start_device_update,
+            commands::list_patterns,
+            commands::play_pattern,
+            commands::stop_patterns,
+            commands::list_bhaptics_mappings,
+            commands::pin_bhaptics_mapping,
+            commands::export_bhaptics_mappings,
+            commands::import_bhaptics_mappings,
         ]);
 
     #[cfg(debug_assertions)] // Only export on non-release builds
@@ -35,6 +35,20 @@ export const commands = {
 	getDeviceEspModel: (id: string) => typedError<ESP32Model, string>(__TAURI_INVOKE("get_device_esp_model", { id })),
 	// typescript seems to throw a fit with formats here. So invoke bypasses most of this. EUUUGH
 	startDeviceUpdate: (fw: Firmware) => typedError<null, string>(__TAURI_INVOKE("start_device_update", { fw })),
+	// Lists the bHaptics .tact files in the patterns folder.
+	listPatterns: () => __TAURI_INVOKE<string[]>("list_patterns"),
+	// Plays a .tact file from the patterns folder onto the map, returns the number of events started.
+	playPattern: (name: string, intensity: number, duration: number, offsetAngleX: number, offsetY: number) => typedError<number, string>(__TAURI_INVOKE("play_pattern", { name, intensity, duration, offsetAngleX, offsetY })),
+	// Stops every pattern started with `play_pattern`.
+	stopPatterns: () => typedError<null, string>(__TAURI_INVOKE("stop_patterns")),
+	// Lists the bHaptics game mappings cached on disk.
+	listBhapticsMappings: () => __TAURI_INVOKE<CachedMapping[]>("list_bhaptics_mappings"),
+	// Pins a game to a mapping version, or back to the latest when version is null.
+	pinBhapticsMapping: (appId: string, version: number | null) => __TAURI_INVOKE<null>("pin_bhaptics_mapping", { appId, version }),
+	// Writes the cached mappings for app_ids (all when empty) to a bundle file, returns how many were written.
+	exportBhapticsMappings: (path: string, appIds: string[]) => typedError<number, string>(__TAURI_INVOKE("export_bhaptics_mappings", { path, appIds })),
+	// Imports a bundle file written by `export_bhaptics_mappings`, returns how many mappings were imported.
+	importBhapticsMappings: (path: string) => typedError<number, string>(__TAURI_INVOKE("import_bhaptics_mappings", { path })),
 };
 
 /* Types */
@@ -56,9 +70,46 @@ export type BhapticInfo = {
 	id: DeviceId,
 	nodes: HapticNode[],
 	model: BhapticsModel,
+	connection: BleConnectionState,
 };
 
-export type BhapticsModel = "TacsuitX16";
+export type BhapticsModel = "TacsuitX16" | "TactsuitX40" | 
+// Head, 6 motors.
+"Tactal" | 
+// Head, 4 motors on the headset strap.
+"TactVisor" | "TactosyArmL" | "TactosyArmR" | "TactosyHandL" | "TactosyHandR" | "TactosyFootL" | "TactosyFootR" | "TactGloveL" | "TactGloveR";
+
+// Where a BLE device is in its connection lifecycle.
+export type BleConnectionState = 
+// First connection attempt in progress.
+{ state: "Connecting" } | { state: "Connected" } | 
+// The device was lost, retried whenever it is in range and the backoff has passed.
+{ state: "Reconnecting"; value: { 
+/**
+ *  Failed attempts so far.
+ */
+attempt: number; 
+/**
+ *  Delay before the next attempt.
+ */
+retry_in_ms: number } } | 
+// Disconnected on purpose, the slot is free for reuse.
+{ state: "Disconnected" };
+
+// How an input node combines with the rest of the map.
+export type BlendMode = 
+// Handled by the interpolation algorithm according to the nodes `InputType`. The original behaviour.
+"Normal" | 
+// Added on top of the result, scaled linearly over the nodes radius.
+"Add" | 
+// Result is at least this nodes value, scaled linearly over the nodes radius.
+"Max" | 
+/**
+ *  Replaces the algorithms result and any Add or Max from lower priority nodes, scaled linearly over the nodes radius.
+ * 
+ *  When several overlap the highest priority wins.
+ */
+"Override";
 
 /**
  *  A node cached by vrc, is an intermdieary between an `InputNode`.
@@ -93,6 +144,19 @@ export type CacheNode = {
 	},
 };
 
+// Summary of a cached game mapping.
+export type CachedMapping = {
+	appId: string,
+	// Game name from the mapping.
+	name: string,
+	version: number,
+	// Every version cached for this app, sorted.
+	versions: number[],
+	// Unix seconds the latest mapping was written.
+	cachedAt: number,
+	pinned: number | null,
+};
+
 // Metadata from the json config
 export type ConfMetadata = {
 	map_name: string,
@@ -121,7 +185,7 @@ export type DeviceId = string;
  *  An informattion that should be in all variants should be made so via the below impl.
  *  Don't manually dip into each variant please.
  */
-export type DeviceInfo = { variant: "Wifi"; value: WifiDeviceInfo } | { variant: "BhapticBle"; value: BhapticInfo };
+export type DeviceInfo = { variant: "Wifi"; value: WifiDeviceInfo } | { variant: "BhapticBle"; value: BhapticInfo } | { variant: "Virtual"; value: VirtualDeviceInfo };
 
 // The firmware type returned from the device.
 export type ESP32Model = 
@@ -159,6 +223,10 @@ export type Event = {
 	tags: string[],
 	// radius of effect this event will have
 	radius: number,
+	// How values and positions move between steps.
+	interp: EventInterp,
+	// Priority and blending of the nodes this event creates, when None it comes from the source tag settings.
+	layer: Layering | null,
 	managed_nodes: NodeId[],
 	time_step: {
 		secs: number,
@@ -187,6 +255,17 @@ export type EventEffectType =
 // Divides locations between the duration of the event and moves the node to that location.
 ({ MovingLocation: ([number, number, number])[] }) & { Location?: never; MultipleNodes?: never; SingleNode?: never; Tags?: never };
 
+// How an event moves between its steps (and waypoints for `EventEffectType::MovingLocation`).
+export type EventInterp = 
+// Jumps to each step when its time comes. The original behaviour.
+"Step" | 
+// Straight line between steps.
+"Linear" | 
+// Catmull-Rom spline through the steps, smooth but can overshoot slightly.
+"Cubic" | 
+// Eases in and out of each step (smoothstep).
+"EaseInOut";
+
 // Bundle containing all user-required information to start a firmware update.
 export type Firmware = {
 	// The ID that should be used to find the device:
@@ -242,6 +321,8 @@ export type InputNode = {
 	tags: string[],
 	// how this input node should be interpreted
 	input_type: InputType,
+	// Priority and blending, when None it comes from the source tag settings.
+	layer: Layering | null,
 };
 
 /**
@@ -269,6 +350,15 @@ export type InputType =
  */
 "SUBTRACTIVE";
 
+// Priority and blending for an input node, set directly on nodes and events or per source tag in `StandardMenu::source_layers`.
+export type Layering = {
+	// Higher priorities win overrides and duck lower ones.
+	priority: number,
+	blend: BlendMode,
+	// While a node with this layering is active, every lower priority node is scaled by `1 - duck`.
+	duck: number,
+};
+
 // Snapshot of map state.
 export type MapInfo = {
 	nodes: InputNode[],
@@ -684,6 +774,15 @@ export type UpdateMethod =
 // Not currently supported
 ({ Serial: string }) & { OTA?: never };
 
+// Info container for virtual devices.
+export type VirtualDeviceInfo = {
+	id: DeviceId,
+	name: string,
+	nodes: HapticNode[],
+	// Whether the device is currently registered with the manager.
+	connected: boolean,
+};
+
 /**
  *  struct exposed to the UI.
  * 
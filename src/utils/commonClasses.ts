@@ -97,6 +97,8 @@ export interface WifiConfig {
   motor_map_ledc_num: number;
   motor_map_ledc: number[];
   config_version: number;
+  /** Binary motor frame formats the firmware accepts, e.g. "hb16". Missing on older firmware. */
+  frame_formats?: string[];
 }
 
 